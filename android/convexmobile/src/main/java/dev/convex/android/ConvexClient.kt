@@ -3,8 +3,11 @@ package dev.convex.android
 import android.content.Context
 import android.util.Log
 import kotlinx.coroutines.CoroutineScope
+import kotlinx.coroutines.CancellationException
 import kotlinx.coroutines.SupervisorJob
 import kotlinx.coroutines.cancel
+import kotlinx.coroutines.channels.ClosedSendChannelException
+import kotlinx.coroutines.channels.SendChannel
 import kotlinx.coroutines.channels.awaitClose
 import kotlinx.coroutines.flow.Flow
 import kotlinx.coroutines.flow.MutableStateFlow
@@ -35,6 +38,25 @@ internal val jsonApi = Json {
         }
 }
 
+/**
+ * Sends [element], suspending until there's room for it, or drops it if the channel was closed
+ * because its collector went away.
+ *
+ * Subscriber callbacks send with this, as an exception thrown from a callback that doesn't return a
+ * result fails the native subscription.
+ */
+@PublishedApi
+internal suspend fun <E> SendChannel<E>.sendUnlessClosed(element: E) {
+    try {
+        send(element)
+    } catch (e: ClosedSendChannelException) {
+        return
+    } catch (e: CancellationException) {
+        // Closing the flow cancels the channel. Otherwise it's this call that was cancelled.
+        if (!isClosedForSend) throw e
+    }
+}
+
 typealias Int64 = @Serializable(Int64ToLongDecoder::class) Long
 typealias Float64 = @Serializable(Float64ToDoubleDecoder::class) Double
 typealias Int32 = @Serializable(Int64ToIntDecoder::class) Int
@@ -49,14 +71,20 @@ typealias ConvexNum = Contextual
  *
  * Consumers of this client should use Kotlin's JSON serialization to handle data sent to/from the
  * Convex backend.
+ *
+ * @param clientOptions tunes the native client, e.g. `requireEncryptedConnections` for release
+ * builds. It's ignored when an [ffiClientFactory] is passed.
  */
 open class ConvexClient(
     deploymentUrl: String,
-    ffiClientFactory: (deploymentUrl: String, clientId: String, webSocketStateSubscriber: WebSocketStateSubscriber?) -> MobileConvexClientInterface = ::MobileConvexClient
+    clientOptions: ClientOptions = ClientOptions(),
+    ffiClientFactory: (deploymentUrl: String, clientId: String, webSocketStateSubscriber: WebSocketStateSubscriber?) -> MobileConvexClientInterface = { url, clientId, subscriber ->
+        MobileConvexClient.withOptions(url, clientId, subscriber, clientOptions)
+    }
 ) {
     private val _webSocketStateFlow = MutableStateFlow(WebSocketState.CONNECTING)
     private val webSocketStateSubscriber = object : WebSocketStateSubscriber {
-        override fun onStateChange(state: WebSocketState) {
+        override suspend fun onStateChange(state: WebSocketState) {
             _webSocketStateFlow.value = state
         }
     }
@@ -95,10 +123,14 @@ open class ConvexClient(
             name,
             args?.mapValues { it.value.toJsonElement().toString() } ?: mapOf(),
             object : QuerySubscriber {
-                override fun onUpdate(value: String) {
+                override suspend fun onUpdate(value: String, isStale: Boolean) {
                     try {
                         val data = jsonApi.decodeFromString<T>(value)
-                        trySend(Result.success(data))
+                        // Suspending here until the collector is ready lets the Rust side conflate
+                        // updates for slow consumers.
+                        sendUnlessClosed(Result.success(data))
+                    } catch (e: CancellationException) {
+                        throw e
                     } catch (e: Throwable) {
                         // Don't catch when https://github.com/mozilla/uniffi-rs/issues/2194 is fixed.
                         // Ideally any unchecked exception that happens here goes uncaught and triggers
@@ -110,16 +142,20 @@ open class ConvexClient(
                     }
                 }
 
-                override fun onError(message: String, value: String?) {
+                override suspend fun onError(message: String, value: String?) {
                     if (value == null) {
                         // This is a server error of some sort.
-                        trySend(Result.failure(ServerError(message)))
+                        sendUnlessClosed(Result.failure(ServerError(message)))
                     } else {
                         // An application specific error thrown in a Convex backend function.
-                        trySend(Result.failure(ConvexError(message, value)))
+                        sendUnlessClosed(Result.failure(ConvexError(message, value)))
                     }
 
                 }
+
+                override suspend fun onSubscribed() {}
+
+                override suspend fun onResubscribed() {}
             })
 
         awaitClose {
@@ -208,6 +244,112 @@ open class ConvexClient(
     suspend fun mutation(name: String, args: Map<String, Any?>? = null) {
         mutation<Unit?>(name = name, args = args)
     }
+
+    /**
+     * Starts connecting to the Convex backend right away instead of on the first call.
+     *
+     * Returns without waiting for the connection, so it can be called from
+     * `Application.onCreate` to have the connection ready for the first screen.
+     */
+    fun connect() {
+        ffiClient.connect()
+    }
+
+    /**
+     * Authenticates as an admin of the deployment with a [deployKey], for internal tools and test
+     * harnesses against dev deployments.
+     *
+     * Deploy keys grant full access to the deployment, so never ship one in an app.
+     */
+    suspend fun setAdminAuth(deployKey: String) {
+        try {
+            ffiClient.setAdminAuth(deployKey)
+        } catch (e: ClientException) {
+            throw e.toError()
+        }
+    }
+
+    /**
+     * Tries to reconnect to the Convex backend right away, e.g. when the app is told that the
+     * network is back, instead of waiting for the next scheduled attempt.
+     *
+     * Subscriptions carry over to the new connection.
+     */
+    suspend fun reconnect() {
+        try {
+            ffiClient.reconnect()
+        } catch (e: ClientException) {
+            throw e.toError()
+        }
+    }
+
+    /**
+     * Lets the client know that the app went to the background, e.g. from
+     * `DefaultLifecycleObserver.onStop` of the `ProcessLifecycleOwner`.
+     *
+     * Subscriptions stop emitting until [notifyAppForeground], when each emits its latest result.
+     */
+    fun notifyAppBackground() {
+        ffiClient.notifyAppBackground()
+    }
+
+    /**
+     * Lets the client know that the app is back in the foreground, e.g. from
+     * `DefaultLifecycleObserver.onStart` of the `ProcessLifecycleOwner`.
+     */
+    fun notifyAppForeground() {
+        ffiClient.notifyAppForeground()
+    }
+
+    /**
+     * Lets the client know how the device is connected to the network, e.g. from a
+     * `ConnectivityManager.NetworkCallback`, and whether the connection is [metered].
+     *
+     * On a metered or cellular network, subscriptions emit at most about twice a second.
+     */
+    fun setNetworkType(networkType: NetworkType, metered: Boolean) {
+        ffiClient.setNetworkType(networkType, metered)
+    }
+
+    /**
+     * Turns low power mode on or off, e.g. when `PowerManager.isPowerSaveMode` changes.
+     *
+     * In low power mode, subscriptions emit at most about once a second.
+     */
+    fun setLowPowerMode(enabled: Boolean) {
+        ffiClient.setLowPowerMode(enabled)
+    }
+
+    /**
+     * Disconnects from the Convex backend until [resume], e.g. when the app goes to the background.
+     *
+     * Subscriptions are kept and get their results again once resumed.
+     */
+    fun pause() {
+        ffiClient.pause()
+    }
+
+    /**
+     * Reconnects to the Convex backend after [pause].
+     */
+    suspend fun resume() {
+        try {
+            ffiClient.resume()
+        } catch (e: ClientException) {
+            throw e.toError()
+        }
+    }
+
+    /**
+     * Shuts the client down, cancelling all subscriptions, closing the WebSocket connection and
+     * stopping the native worker threads.
+     *
+     * Calls made after this fail. Call it when done with a client that isn't meant to live as long
+     * as the app, e.g. at the end of each test.
+     */
+    fun shutdown() {
+        ffiClient.shutdown()
+    }
 }
 
 /**
@@ -219,8 +361,11 @@ class ConvexClientWithAuth<T>(
     deploymentUrl: String,
     private val authProvider: AuthProvider<T>,
     private val coroutineScope: CoroutineScope = CoroutineScope(SupervisorJob()),
-    ffiClientFactory: (deploymentUrl: String, clientId: String, webSocketSocketStateSubscriber: WebSocketStateSubscriber?) -> MobileConvexClientInterface = ::MobileConvexClient
-) : ConvexClient(deploymentUrl, ffiClientFactory) {
+    clientOptions: ClientOptions = ClientOptions(),
+    ffiClientFactory: (deploymentUrl: String, clientId: String, webSocketSocketStateSubscriber: WebSocketStateSubscriber?) -> MobileConvexClientInterface = { url, clientId, subscriber ->
+        MobileConvexClient.withOptions(url, clientId, subscriber, clientOptions)
+    }
+) : ConvexClient(deploymentUrl, clientOptions, ffiClientFactory) {
     private val _authState = MutableStateFlow<AuthState<T>>(AuthState.Unauthenticated())
     private var authBridge: AuthTokenProviderBridge? = null
 
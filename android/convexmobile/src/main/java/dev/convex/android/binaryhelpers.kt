@@ -0,0 +1,29 @@
+@file:OptIn(ExperimentalSerializationApi::class)
+
+package dev.convex.android
+
+import kotlinx.serialization.ExperimentalSerializationApi
+import kotlinx.serialization.cbor.Cbor
+import kotlinx.serialization.decodeFromByteArray
+import kotlinx.serialization.json.decodeFromStream
+import java.io.ByteArrayInputStream
+
+/**
+ * Decodes the CBOR encoded [bytes] of a result from a `*Binary` method with [BinaryFormat.CBOR].
+ *
+ * An `Int64` decodes to a [Long], a `Float64` to a [Double] and `Bytes` to a [ByteArray], without
+ * the special JSON objects of [JSON results][toJsonElement]. Fields that [T] doesn't have are
+ * ignored.
+ */
+inline fun <reified T> decodeConvexCbor(bytes: ByteArray): T =
+    convexCbor.decodeFromByteArray<T>(bytes)
+
+/**
+ * Decodes the UTF-8 JSON [bytes] of a result from a `*Binary` method with [BinaryFormat.JSON], like
+ * the JSON strings of the other methods but without making a [String] of a large result first.
+ */
+inline fun <reified T> decodeConvexJson(bytes: ByteArray): T =
+    jsonApi.decodeFromStream<T>(ByteArrayInputStream(bytes))
+
+@PublishedApi
+internal val convexCbor = Cbor { ignoreUnknownKeys = true }
@@ -43,8 +43,58 @@ class InternalError(message: String, cause: Throwable? = null) : Exception(messa
     }
 }
 
+/**
+ * An exception thrown when the Convex backend can't be reached.
+ *
+ * The call may succeed if it's [retryable] and tried again once the network is back.
+ */
+class NetworkError(message: String, val retryable: Boolean, cause: Throwable? = null) :
+    Exception(message, cause) {
+    companion object {
+        fun from(exception: ClientException.NetworkException): NetworkError =
+            NetworkError(exception.msg, exception.retryable, exception)
+    }
+}
+
+/**
+ * An exception thrown when authenticating with the Convex backend fails.
+ */
+class AuthError(message: String, cause: Throwable? = null) : Exception(message, cause) {
+    companion object {
+        fun from(exception: ClientException.AuthException): AuthError =
+            AuthError(exception.msg, exception)
+    }
+}
+
+/**
+ * An exception thrown when a function argument can't be converted to a Convex value.
+ *
+ * The [key] names the offending argument.
+ */
+class InvalidArgumentError(message: String, val key: String, cause: Throwable? = null) :
+    Exception(message, cause) {
+    companion object {
+        fun from(exception: ClientException.InvalidArgument): InvalidArgumentError =
+            InvalidArgumentError(exception.reason, exception.key, exception)
+    }
+}
+
+/**
+ * An exception thrown when a call doesn't finish within the timeout it was given.
+ */
+class TimeoutError(message: String, cause: Throwable? = null) : Exception(message, cause) {
+    companion object {
+        fun from(exception: ClientException.Timeout): TimeoutError =
+            TimeoutError(exception.msg, exception)
+    }
+}
+
 fun ClientException.toError() = when (this) {
     is ClientException.ConvexException -> ConvexError.from(this)
     is ClientException.InternalException -> InternalError.from(this)
     is ClientException.ServerException -> ServerError.from(this)
+    is ClientException.NetworkException -> NetworkError.from(this)
+    is ClientException.AuthException -> AuthError.from(this)
+    is ClientException.InvalidArgument -> InvalidArgumentError.from(this)
+    is ClientException.Timeout -> TimeoutError.from(this)
 }
\ No newline at end of file
@@ -55,13 +55,15 @@ internal fun Any?.toJsonElement(): JsonElement {
         is Long  -> mapOf("\$integer" to Base64.encode(this.toByteArray())).toJsonElement()
         is Int -> mapOf("\$integer" to Base64.encode(this.toLong().toByteArray())).toJsonElement()
         is ByteArray -> mapOf("\$bytes" to Base64.encode(this)).toJsonElement()
-        is Double -> when (this) {
-            Double.NaN, Double.NEGATIVE_INFINITY, Double.POSITIVE_INFINITY -> mapOf("\$float" to Base64.encode(this.toByteArray())).toJsonElement()
-            else -> JsonPrimitive(this)
+        is Double -> if (this.isSpecialFloat()) {
+            mapOf("\$float" to Base64.encode(this.toByteArray())).toJsonElement()
+        } else {
+            JsonPrimitive(this)
         }
-        is Float -> when (this) {
-            Float.NaN, Float.NEGATIVE_INFINITY, Float.POSITIVE_INFINITY -> mapOf("\$float" to Base64.encode(this.toDouble().toByteArray())).toJsonElement()
-            else -> JsonPrimitive(this)
+        is Float -> if (this.toDouble().isSpecialFloat()) {
+            mapOf("\$float" to Base64.encode(this.toDouble().toByteArray())).toJsonElement()
+        } else {
+            JsonPrimitive(this)
         }
         is Number -> JsonPrimitive(this)
         null -> JsonNull
@@ -69,6 +71,11 @@ internal fun Any?.toJsonElement(): JsonElement {
     }
 }
 
+// NaN, the infinities and -0.0 can't be represented as JSON numbers, so Convex encodes them as
+// $float instead.
+private fun Double.isSpecialFloat(): Boolean =
+    isNaN() || isInfinite() || (this == 0.0 && 1.0 / this < 0)
+
 private fun Long.toByteArray(): ByteArray = ByteBuffer.allocate(java.lang.Long.BYTES).apply { order(ByteOrder.LITTLE_ENDIAN); putLong(this@toByteArray) }.array()
 private fun ByteArray.toLong(): Long = ByteBuffer.allocate(java.lang.Long.BYTES).apply { order(ByteOrder.LITTLE_ENDIAN); put(this@toLong); flip() }.getLong()
 private fun Double.toByteArray(): ByteArray = ByteBuffer.allocate(java.lang.Double.BYTES).apply { order(ByteOrder.LITTLE_ENDIAN); putDouble(this@toByteArray) }.array()
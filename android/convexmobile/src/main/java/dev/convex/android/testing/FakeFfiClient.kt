@@ -1,13 +1,45 @@
 package dev.convex.android.testing
 
+import dev.convex.android.AuthErrorListener
+import dev.convex.android.AuthStateListener
 import dev.convex.android.AuthTokenProvider
+import dev.convex.android.BinaryFormat
+import dev.convex.android.BinaryQuerySubscriber
+import dev.convex.android.ClientStats
+import dev.convex.android.ConnectionStateListener
+import dev.convex.android.ConvexAuthSignIn
+import dev.convex.android.ConvexValue
+import dev.convex.android.DeploymentFailoverListener
+import dev.convex.android.DownloadSink
+import dev.convex.android.FunctionCall
+import dev.convex.android.FunctionCallResult
+import dev.convex.android.FunctionLogListener
+import dev.convex.android.HttpActionResponse
 import dev.convex.android.MobileConvexClientInterface
-import dev.convex.android.NoPointer
+import dev.convex.android.MutationOutboxListener
+import dev.convex.android.NetworkDiagnosis
+import dev.convex.android.NetworkType
+import dev.convex.android.NoHandle
+import dev.convex.android.OptimisticUpdater
+import dev.convex.android.PaginatedQuerySubscriber
+import dev.convex.android.PaginatedSubscription
 import dev.convex.android.QuerySubscriber
+import dev.convex.android.ServerNoticeListener
+import dev.convex.android.StorageProvider
+import dev.convex.android.SubscriptionEvictionListener
 import dev.convex.android.SubscriptionHandle
+import dev.convex.android.SubscriptionInfo
+import dev.convex.android.SubscriptionOptions
+import dev.convex.android.SubscriptionStream
+import dev.convex.android.TimedResult
+import dev.convex.android.TokenStore
+import dev.convex.android.TransferProgressListener
+import dev.convex.android.UploadSource
+import dev.convex.android.ValueQuerySubscriber
 import dev.convex.android.toJsonElement
 import kotlinx.serialization.encodeToString
 import kotlinx.serialization.json.Json
+import kotlinx.serialization.json.jsonObject
 
 class FakeFfiClient : MobileConvexClientInterface {
     val subscriptions = mutableMapOf<CallKey, QuerySubscriber>()
@@ -15,20 +47,104 @@ class FakeFfiClient : MobileConvexClientInterface {
     val mutations = mutableMapOf<String, Map<String, String>>()
     var receivedAuthProvider: AuthTokenProvider? = null
 
-    override suspend fun action(name: String, args: Map<String, String>): String {
+    override suspend fun action(name: String, args: Map<String, String>, timeoutMs: ULong?): String {
         actions[name] = args
         return Json.encodeToString<Unit?>(null)
     }
 
-    override suspend fun mutation(name: String, args: Map<String, String>): String {
+    override suspend fun mutation(name: String, args: Map<String, String>, timeoutMs: ULong?): String {
         mutations[name] = args
         return Json.encodeToString<Unit?>(null)
     }
 
-    override suspend fun query(name: String, args: Map<String, String>): String {
+    override suspend fun actionJson(name: String, args: String, timeoutMs: ULong?): String =
+        action(name, jsonArgs(args), timeoutMs)
+
+    override suspend fun mutationJson(name: String, args: String, timeoutMs: ULong?): String =
+        mutation(name, jsonArgs(args), timeoutMs)
+
+    override suspend fun queryJson(name: String, args: String, timeoutMs: ULong?): String =
+        query(name, jsonArgs(args), timeoutMs)
+
+    override suspend fun subscribeJson(
+        name: String,
+        args: String,
+        subscriber: QuerySubscriber
+    ): SubscriptionHandle = subscribe(name, jsonArgs(args), subscriber)
+
+    override suspend fun mutationBatch(mutations: List<FunctionCall>): List<FunctionCallResult> =
+        mutations.map { FunctionCallResult.Success(mutation(it.name, it.args)) }
+
+    override suspend fun query(name: String, args: Map<String, String>, timeoutMs: ULong?): String {
         TODO("Not yet implemented")
     }
 
+    override suspend fun queryBatch(queries: List<FunctionCall>): List<String> =
+        queries.map { query(it.name, it.args) }
+
+    override suspend fun actionTimed(name: String, args: Map<String, String>): TimedResult =
+        TimedResult(action(name, args, null), 0u)
+
+    override suspend fun mutationTimed(name: String, args: Map<String, String>): TimedResult =
+        TimedResult(mutation(name, args, null), 0u)
+
+    override suspend fun queryTimed(name: String, args: Map<String, String>): TimedResult =
+        TimedResult(query(name, args, null), 0u)
+
+    override suspend fun actionValue(name: String, args: Map<String, ConvexValue>): ConvexValue =
+        ConvexValue.Null
+
+    override suspend fun mutationValue(name: String, args: Map<String, ConvexValue>): ConvexValue =
+        ConvexValue.Null
+
+    override suspend fun queryValue(name: String, args: Map<String, ConvexValue>): ConvexValue {
+        TODO("Not yet implemented")
+    }
+
+    override suspend fun subscribeValue(
+        name: String,
+        args: Map<String, ConvexValue>,
+        subscriber: ValueQuerySubscriber
+    ): SubscriptionHandle = SubscriptionHandle(NoHandle)
+
+    override suspend fun actionBinary(
+        name: String,
+        args: Map<String, String>,
+        format: BinaryFormat,
+        timeoutMs: ULong?
+    ): ByteArray {
+        actions[name] = args
+        return binaryNull(format)
+    }
+
+    override suspend fun mutationBinary(
+        name: String,
+        args: Map<String, String>,
+        format: BinaryFormat,
+        timeoutMs: ULong?
+    ): ByteArray {
+        mutations[name] = args
+        return binaryNull(format)
+    }
+
+    override suspend fun queryBinary(
+        name: String,
+        args: Map<String, String>,
+        format: BinaryFormat,
+        timeoutMs: ULong?
+    ): ByteArray {
+        TODO("Not yet implemented")
+    }
+
+    override suspend fun subscribeBinary(
+        name: String,
+        args: Map<String, String>,
+        format: BinaryFormat,
+        subscriber: BinaryQuerySubscriber
+    ): SubscriptionHandle = SubscriptionHandle(NoHandle)
+
+    override suspend fun diagnoseNetwork(): NetworkDiagnosis = NetworkDiagnosis.Reachable
+
     override suspend fun setAuth(token: String?) {
         receivedAuthProvider = null
     }
@@ -37,6 +153,151 @@ class FakeFfiClient : MobileConvexClientInterface {
         receivedAuthProvider = provider
     }
 
+    override suspend fun setAdminAuth(deployKey: String) {
+        receivedAuthProvider = null
+    }
+
+    override suspend fun convexAuthSignIn(
+        provider: String,
+        params: Map<String, String>,
+        store: TokenStore
+    ): ConvexAuthSignIn = ConvexAuthSignIn.SignedIn
+
+    override suspend fun convexAuthRestore(store: TokenStore): Boolean = false
+
+    override suspend fun convexAuthSignOut() {}
+
+    override suspend fun subscribePaginated(
+        name: String,
+        args: Map<String, String>,
+        pageSize: UInt,
+        subscriber: PaginatedQuerySubscriber
+    ): PaginatedSubscription = PaginatedSubscription(NoHandle)
+
+    override fun setStorageProvider(storage: StorageProvider?) {}
+
+    override fun startRecording(storage: StorageProvider, key: String) {}
+
+    override fun stopRecording() {}
+
+    override fun activeSubscriptions(): List<SubscriptionInfo> = subscriptions.keys.map { key ->
+        SubscriptionInfo(0u, key.name, key.args.toString(), 0u)
+    }
+
+    override fun stats(): ClientStats = ClientStats(0u, subscriptions.size.toUInt(), 0u)
+
+    override fun unsubscribeAll() {
+        subscriptions.clear()
+    }
+
+    override fun setSubscriptionLimit(
+        maxSubscriptions: UInt?,
+        listener: SubscriptionEvictionListener?
+    ) {
+    }
+
+    override fun setFallbackUrls(
+        fallbackUrls: List<String>,
+        unreachableAfterMs: ULong,
+        listener: DeploymentFailoverListener?
+    ) {
+    }
+
+    override fun activeDeploymentUrl(): String = "https://fake.convex.cloud"
+
+    var isShutDown = false
+
+    override fun connect() {}
+
+    override suspend fun waitForConnection(timeoutMs: ULong?) {}
+
+    override suspend fun waitForAuth(timeoutMs: ULong?) {}
+
+    override suspend fun reconnect() {}
+
+    override fun notifyAppBackground() {}
+
+    override fun notifyAppForeground() {}
+
+    override fun setNetworkType(networkType: NetworkType, metered: Boolean) {}
+
+    override fun setLowPowerMode(enabled: Boolean) {}
+
+    override fun pause() {}
+
+    override suspend fun resume() {}
+
+    override fun shutdown() {
+        isShutDown = true
+        subscriptions.clear()
+    }
+
+    override fun setConnectionStateListener(listener: ConnectionStateListener?) {}
+
+    override fun setAuthStateListener(listener: AuthStateListener?) {}
+
+    override fun setAuthErrorListener(listener: AuthErrorListener?) {}
+
+    override fun currentIdentity(): String? = null
+
+    override suspend fun uploadFile(
+        uploadUrl: String,
+        source: UploadSource,
+        contentType: String,
+        progress: TransferProgressListener?
+    ): String = "fake-storage-id"
+
+    override suspend fun downloadFile(
+        url: String,
+        path: String,
+        progress: TransferProgressListener?
+    ): ULong = 0u
+
+    override suspend fun downloadToSink(
+        url: String,
+        sink: DownloadSink,
+        offset: ULong,
+        progress: TransferProgressListener?
+    ): ULong = offset
+
+    override suspend fun mutationWithOptimisticUpdate(
+        name: String,
+        args: Map<String, String>,
+        updater: OptimisticUpdater
+    ): String = mutation(name, args)
+
+    override suspend fun enableMutationOutbox(listener: MutationOutboxListener) {}
+
+    override suspend fun enqueueMutation(name: String, args: Map<String, String>): String =
+        "fake-outbox-id"
+
+    override suspend fun callHttpAction(
+        method: String,
+        path: String,
+        headers: Map<String, String>,
+        body: ByteArray?
+    ): HttpActionResponse = HttpActionResponse(200u, mapOf(), byteArrayOf())
+
+    override fun setHttpActionsUrl(url: String?) {}
+
+    override fun setServerNoticeListener(listener: ServerNoticeListener?) {}
+
+    override fun setFunctionLogListener(listener: FunctionLogListener?) {}
+
+    var sessionId: String? = null
+
+    override fun startAnonymousSession(existingSessionId: String?): String {
+        val id = existingSessionId ?: "fake-session-id"
+        sessionId = id
+        return id
+    }
+
+    override fun endAnonymousSession() {
+        sessionId = null
+    }
+
+    override fun anonymousSessionId(): String? = sessionId
+
     override suspend fun subscribe(
         name: String,
         args: Map<String, String>,
@@ -44,20 +305,33 @@ class FakeFfiClient : MobileConvexClientInterface {
     ): SubscriptionHandle {
         val subscriptionKey = CallKey(name, args)
         subscriptions[subscriptionKey] = subscriber
-        return object : SubscriptionHandle(NoPointer) {
+        return object : SubscriptionHandle(NoHandle) {
             override fun cancel() {
                 subscriptions.remove(subscriptionKey)
             }
         }
     }
 
-    fun sendSubscriptionData(name: String, args: Map<String, Any?>, data: String) {
+    override suspend fun subscribeWithOptions(
+        name: String,
+        args: Map<String, String>,
+        options: SubscriptionOptions,
+        subscriber: QuerySubscriber
+    ): SubscriptionHandle = subscribe(name, args, subscriber)
+
+    override suspend fun subscribeStream(
+        name: String,
+        args: Map<String, String>,
+        options: SubscriptionOptions
+    ): SubscriptionStream = SubscriptionStream(NoHandle)
+
+    suspend fun sendSubscriptionData(name: String, args: Map<String, Any?>, data: String) {
         subscriptions[CallKey(
             name,
-            args.mapValues { it.value.toJsonElement().toString() })]!!.onUpdate(data)
+            args.mapValues { it.value.toJsonElement().toString() })]!!.onUpdate(data, false)
     }
 
-    fun sendSubscriptionError(
+    suspend fun sendSubscriptionError(
         name: String,
         args: Map<String, Any?>,
         errorMessage: String,
@@ -80,6 +354,15 @@ class FakeFfiClient : MobileConvexClientInterface {
     fun subscriptionRequestsFor(name: String): Iterable<CallKey> {
         return subscriptions.keys.filter { key -> key.name == name }
     }
+
+    private fun binaryNull(format: BinaryFormat): ByteArray = when (format) {
+        BinaryFormat.CBOR -> byteArrayOf(0xf6.toByte())
+        BinaryFormat.MESSAGE_PACK -> byteArrayOf(0xc0.toByte())
+        BinaryFormat.JSON -> "null".toByteArray()
+    }
+
+    private fun jsonArgs(args: String): Map<String, String> =
+        Json.parseToJsonElement(args).jsonObject.mapValues { it.value.toString() }
 }
 
 data class CallKey(val name: String, val args: Map<String, String>)
\ No newline at end of file
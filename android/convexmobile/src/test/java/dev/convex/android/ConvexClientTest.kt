@@ -224,6 +224,19 @@ class ConvexClientTest {
         expectThat(ffiClient.hasSubscriptionFor(QUERY_NAME, QUERY_ARGS)).isFalse()
     }
 
+    @Test
+    fun `subscriber callbacks return normally once the Flow is closed`() = runTest {
+        val job = backgroundScope.launch(UnconfinedTestDispatcher(testScheduler)) {
+            client.subscribe<Foo>(QUERY_NAME, QUERY_ARGS).collect {}
+        }
+        val subscriber = ffiClient.subscriptions.values.single()
+        job.cancel()
+
+        // Throwing from these would fail the native subscription.
+        subscriber.onUpdate(Json.encodeToString(Foo(bar = "baz")), false)
+        subscriber.onError("an error broke out", null)
+    }
+
     @Test
     fun `subscribe Flow can receive ServerError`() = runTest {
         var observedError: Throwable? = null
@@ -0,0 +1,6 @@
+/// Dart bindings of the Rust client that the Convex Android and iOS libraries
+/// are built on, over its C ABI.
+library convex_mobile;
+
+export 'src/client.dart' show ConvexClient;
+export 'src/errors.dart' show ConvexError, ConvexException;
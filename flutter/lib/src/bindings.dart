@@ -0,0 +1,77 @@
+// Lookups of the functions declared in rust/c/include/convex_mobile.h.
+import 'dart:ffi';
+import 'dart:io';
+
+import 'package:ffi/ffi.dart';
+
+final class ClientHandle extends Opaque {}
+
+typedef ResultCallbackNative = Void Function(
+    Pointer<Void> context, Pointer<Utf8> value, Pointer<Utf8> error);
+typedef UpdateCallbackNative = Void Function(
+    Pointer<Void> context, Pointer<Utf8> value, Bool isStale, Pointer<Utf8> error);
+
+typedef _CallNative = Void Function(Pointer<ClientHandle>, Pointer<Utf8>, Pointer<Utf8>,
+    Pointer<Void>, Pointer<NativeFunction<ResultCallbackNative>>);
+typedef _Call = void Function(Pointer<ClientHandle>, Pointer<Utf8>, Pointer<Utf8>, Pointer<Void>,
+    Pointer<NativeFunction<ResultCallbackNative>>);
+
+/// Opens the library for the platform: it's linked into the app on iOS, and
+/// bundled with it everywhere else.
+DynamicLibrary openLibrary() {
+  if (Platform.isIOS) {
+    return DynamicLibrary.process();
+  }
+  if (Platform.isMacOS) {
+    return DynamicLibrary.open('libconvexmobile_c.dylib');
+  }
+  if (Platform.isWindows) {
+    return DynamicLibrary.open('convexmobile_c.dll');
+  }
+  return DynamicLibrary.open('libconvexmobile_c.so');
+}
+
+class Bindings {
+  Bindings(DynamicLibrary library)
+      : clientNew = library.lookupFunction<
+            Pointer<ClientHandle> Function(Pointer<Utf8>, Pointer<Utf8>),
+            Pointer<ClientHandle> Function(Pointer<Utf8>, Pointer<Utf8>)>('convex_client_new'),
+        clientFree = library.lookupFunction<Void Function(Pointer<ClientHandle>),
+            void Function(Pointer<ClientHandle>)>('convex_client_free'),
+        query = library.lookupFunction<_CallNative, _Call>('convex_query'),
+        mutation = library.lookupFunction<_CallNative, _Call>('convex_mutation'),
+        action = library.lookupFunction<_CallNative, _Call>('convex_action'),
+        subscribe = library.lookupFunction<
+            Uint64 Function(Pointer<ClientHandle>, Pointer<Utf8>, Pointer<Utf8>, Pointer<Void>,
+                Pointer<NativeFunction<UpdateCallbackNative>>),
+            int Function(Pointer<ClientHandle>, Pointer<Utf8>, Pointer<Utf8>, Pointer<Void>,
+                Pointer<NativeFunction<UpdateCallbackNative>>)>('convex_subscribe'),
+        unsubscribe = library.lookupFunction<Void Function(Pointer<ClientHandle>, Uint64),
+            void Function(Pointer<ClientHandle>, int)>('convex_unsubscribe'),
+        setAuth = library.lookupFunction<
+            Void Function(Pointer<ClientHandle>, Pointer<Utf8>, Pointer<Void>,
+                Pointer<NativeFunction<ResultCallbackNative>>),
+            void Function(Pointer<ClientHandle>, Pointer<Utf8>, Pointer<Void>,
+                Pointer<NativeFunction<ResultCallbackNative>>)>('convex_set_auth'),
+        stringFree = library.lookupFunction<Void Function(Pointer<Utf8>),
+            void Function(Pointer<Utf8>)>('convex_string_free');
+
+  final Pointer<ClientHandle> Function(Pointer<Utf8>, Pointer<Utf8>) clientNew;
+  final void Function(Pointer<ClientHandle>) clientFree;
+  final _Call query;
+  final _Call mutation;
+  final _Call action;
+  final int Function(Pointer<ClientHandle>, Pointer<Utf8>, Pointer<Utf8>, Pointer<Void>,
+      Pointer<NativeFunction<UpdateCallbackNative>>) subscribe;
+  final void Function(Pointer<ClientHandle>, int) unsubscribe;
+  final void Function(Pointer<ClientHandle>, Pointer<Utf8>, Pointer<Void>,
+      Pointer<NativeFunction<ResultCallbackNative>>) setAuth;
+  final void Function(Pointer<Utf8>) stringFree;
+
+  /// Reads a string that a callback got and frees it.
+  String take(Pointer<Utf8> string) {
+    final taken = string.toDartString();
+    stringFree(string);
+    return taken;
+  }
+}
@@ -0,0 +1,151 @@
+import 'dart:async';
+import 'dart:convert';
+import 'dart:ffi';
+
+import 'package:ffi/ffi.dart';
+
+import 'bindings.dart';
+import 'errors.dart';
+
+late final Bindings _bindings = Bindings(ConvexClient.library ?? openLibrary());
+
+/// The calls and subscriptions waiting on a callback, by the address of the
+/// context that was passed with them.
+final _calls = <int, Completer<String>>{};
+final _subscriptions = <int, StreamController<dynamic>>{};
+var _nextContext = 1;
+
+// The callbacks are never closed, as the client may call them until it's
+// freed, so they can't keep the isolate alive.
+late final _resultCallback =
+    NativeCallable<ResultCallbackNative>.listener(_onResult)..keepIsolateAlive = false;
+late final _updateCallback =
+    NativeCallable<UpdateCallbackNative>.listener(_onUpdate)..keepIsolateAlive = false;
+
+void _onResult(Pointer<Void> context, Pointer<Utf8> value, Pointer<Utf8> error) {
+  final call = _calls.remove(context.address)!;
+  if (value != nullptr) {
+    call.complete(_bindings.take(value));
+  } else {
+    call.completeError(ConvexException.fromJson(_bindings.take(error)));
+  }
+}
+
+void _onUpdate(Pointer<Void> context, Pointer<Utf8> value, bool isStale, Pointer<Utf8> error) {
+  if (value != nullptr) {
+    _subscriptions[context.address]?.add(jsonDecode(_bindings.take(value)));
+  } else if (error != nullptr) {
+    _subscriptions[context.address]?.addError(ConvexException.fromJson(_bindings.take(error)));
+  } else {
+    _subscriptions.remove(context.address)?.close();
+  }
+}
+
+/// A client for a Convex deployment, with the same sync engine as the
+/// Android and iOS libraries.
+///
+/// Arguments are JSON encodable maps and results are decoded JSON. Call
+/// [close] once the client isn't needed anymore.
+class ConvexClient {
+  /// Creates a client for the deployment at [deploymentUrl], which connects
+  /// on its first call.
+  ConvexClient(String deploymentUrl, {String clientId = 'flutter-convex-mobile'}) {
+    _handle = using((arena) => _bindings.clientNew(
+        deploymentUrl.toNativeUtf8(allocator: arena), clientId.toNativeUtf8(allocator: arena)));
+  }
+
+  /// The library to load the client from instead of the one bundled with the
+  /// app, if it's set before the first client is created.
+  static DynamicLibrary? library;
+
+  late final Pointer<ClientHandle> _handle;
+  var _closed = false;
+
+  /// Runs the query [name] once.
+  Future<dynamic> query(String name, [Map<String, Object?> args = const {}]) =>
+      _call(_bindings.query, name, args);
+
+  /// Runs the mutation [name].
+  Future<dynamic> mutation(String name, [Map<String, Object?> args = const {}]) =>
+      _call(_bindings.mutation, name, args);
+
+  /// Runs the action [name].
+  Future<dynamic> action(String name, [Map<String, Object?> args = const {}]) =>
+      _call(_bindings.action, name, args);
+
+  /// Subscribes to the query [name] while the returned stream is listened to.
+  ///
+  /// The stream gets every result of the query and its errors, and is done
+  /// once the subscription stops, e.g. because the client was closed.
+  Stream<dynamic> subscribe(String name, [Map<String, Object?> args = const {}]) {
+    late final StreamController<dynamic> controller;
+    var id = 0;
+    controller = StreamController(
+      onListen: () {
+        _checkOpen();
+        final context = _nextContext++;
+        _subscriptions[context] = controller;
+        id = using((arena) => _bindings.subscribe(
+            _handle,
+            name.toNativeUtf8(allocator: arena),
+            jsonEncode(args).toNativeUtf8(allocator: arena),
+            Pointer.fromAddress(context),
+            _updateCallback.nativeFunction));
+      },
+      onCancel: () {
+        if (!_closed) {
+          _bindings.unsubscribe(_handle, id);
+        }
+      },
+    );
+    return controller.stream;
+  }
+
+  /// Sets the JWT [token] to authenticate as, or signs out if it's `null`.
+  Future<void> setAuth(String? token) async {
+    _checkOpen();
+    final completer = Completer<String>();
+    final context = _nextContext++;
+    _calls[context] = completer;
+    using((arena) => _bindings.setAuth(
+        _handle,
+        token == null ? nullptr : token.toNativeUtf8(allocator: arena),
+        Pointer.fromAddress(context),
+        _resultCallback.nativeFunction));
+    await completer.future;
+  }
+
+  /// Shuts the client down. Calls that are still in flight fail, and the
+  /// streams of subscriptions are done.
+  void close() {
+    if (!_closed) {
+      _closed = true;
+      _bindings.clientFree(_handle);
+    }
+  }
+
+  Future<dynamic> _call(
+      void Function(Pointer<ClientHandle>, Pointer<Utf8>, Pointer<Utf8>, Pointer<Void>,
+              Pointer<NativeFunction<ResultCallbackNative>>)
+          function,
+      String name,
+      Map<String, Object?> args) async {
+    _checkOpen();
+    final completer = Completer<String>();
+    final context = _nextContext++;
+    _calls[context] = completer;
+    using((arena) => function(
+        _handle,
+        name.toNativeUtf8(allocator: arena),
+        jsonEncode(args).toNativeUtf8(allocator: arena),
+        Pointer.fromAddress(context),
+        _resultCallback.nativeFunction));
+    return jsonDecode(await completer.future);
+  }
+
+  void _checkOpen() {
+    if (_closed) {
+      throw StateError('The client is closed');
+    }
+  }
+}
@@ -0,0 +1,37 @@
+import 'dart:convert';
+
+/// A call to the Convex backend that failed, with the [kind] of error that
+/// the client reported.
+///
+/// The kinds are `InternalError`, `ConvexError`, `ServerError`,
+/// `NetworkError`, `AuthError`, `InvalidArgument` and `Timeout`.
+class ConvexException implements Exception {
+  ConvexException(this.kind, this.message, {this.retryable = false});
+
+  factory ConvexException.fromJson(String json) {
+    final error = jsonDecode(json) as Map<String, dynamic>;
+    final kind = error['kind'] as String;
+    final message = error['message'] as String;
+    if (kind == 'ConvexError') {
+      return ConvexError(message, error['data']);
+    }
+    return ConvexException(kind, message, retryable: error['retryable'] == true);
+  }
+
+  final String kind;
+  final String message;
+
+  /// Whether a `NetworkError` may go away once the network is back.
+  final bool retryable;
+
+  @override
+  String toString() => '$kind: $message';
+}
+
+/// A `ConvexError` that a backend function threw, with the [data] it was
+/// thrown with.
+class ConvexError extends ConvexException {
+  ConvexError(String message, this.data) : super('ConvexError', message);
+
+  final Object? data;
+}
@@ -0,0 +1,18 @@
+import 'package:convex_mobile/convex_mobile.dart';
+import 'package:test/test.dart';
+
+void main() {
+  test("args that can't be encoded as JSON are rejected", () async {
+    final client = ConvexClient('https://happy-otter-123.convex.cloud', clientId: 'dart-test');
+    addTearDown(client.close);
+    await expectLater(
+        client.query('messages:list', {'limit': double.nan}),
+        throwsA(isA<JsonUnsupportedObjectError>()));
+  });
+
+  test('calls fail once the client is closed', () {
+    final client = ConvexClient('https://happy-otter-123.convex.cloud', clientId: 'dart-test');
+    client.close();
+    expect(() => client.query('messages:list'), throwsStateError);
+  });
+}
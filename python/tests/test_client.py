@@ -0,0 +1,20 @@
+import asyncio
+import unittest
+
+import convexmobile
+
+
+class ClientTest(unittest.TestCase):
+    def test_client_starts_without_connecting(self):
+        client = convexmobile.MobileConvexClient("https://happy-otter-123.convex.cloud", "python-test", None)
+        self.assertEqual(client.active_deployment_url(), "https://happy-otter-123.convex.cloud")
+        self.assertEqual(client.stats().active_subscriptions, 0)
+
+    def test_invalid_args_are_rejected(self):
+        client = convexmobile.MobileConvexClient("https://happy-otter-123.convex.cloud", "python-test", None)
+        with self.assertRaises(convexmobile.ClientError.InvalidArgument):
+            asyncio.run(client.query("messages:list", {"limit": "not json"}, None))
+
+
+if __name__ == "__main__":
+    unittest.main()
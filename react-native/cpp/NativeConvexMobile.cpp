@@ -0,0 +1,141 @@
+#include "NativeConvexMobile.h"
+
+namespace facebook::react {
+
+namespace {
+
+// Takes over a string that the client passed to a callback.
+std::string take(char *string) {
+  std::string taken(string);
+  convex_string_free(string);
+  return taken;
+}
+
+// The promise of a call, which the callback settles from a thread of the
+// client's. AsyncPromise hands that over to the JS thread.
+struct CallContext {
+  AsyncPromise<std::string> promise;
+};
+
+void onCallResult(void *context, char *value, char *error) {
+  std::unique_ptr<CallContext> call(static_cast<CallContext *>(context));
+  if (value != nullptr) {
+    call->promise.resolve(take(value));
+  } else {
+    call->promise.reject(Error(take(error)));
+  }
+}
+
+struct SubscriptionContext {
+  AsyncCallback<std::string, bool> onUpdate;
+  AsyncCallback<std::string> onError;
+};
+
+void onSubscriptionUpdate(void *context, char *value, bool isStale, char *error) {
+  auto subscription = static_cast<SubscriptionContext *>(context);
+  if (value != nullptr) {
+    subscription->onUpdate.call(take(value), isStale);
+  } else if (error != nullptr) {
+    subscription->onError.call(take(error));
+  } else {
+    // The subscription stopped, so its callbacks aren't called again.
+    delete subscription;
+  }
+}
+
+} // namespace
+
+NativeConvexMobile::NativeConvexMobile(std::shared_ptr<CallInvoker> jsInvoker)
+    : NativeConvexMobileCxxSpec(std::move(jsInvoker)) {}
+
+NativeConvexMobile::~NativeConvexMobile() {
+  for (auto &[id, client] : clients_) {
+    convex_client_free(client);
+  }
+}
+
+double NativeConvexMobile::createClient(jsi::Runtime &rt, std::string deploymentUrl,
+                                        std::string clientId) {
+  auto client = convex_client_new(deploymentUrl.c_str(), clientId.c_str());
+  if (client == nullptr) {
+    throw jsi::JSError(rt, "The deployment URL and client ID must be valid UTF-8");
+  }
+  auto id = nextClient_++;
+  clients_[id] = client;
+  return id;
+}
+
+void NativeConvexMobile::freeClient(jsi::Runtime &rt, double client) {
+  auto found = clients_.find(static_cast<int>(client));
+  if (found != clients_.end()) {
+    convex_client_free(found->second);
+    clients_.erase(found);
+  }
+}
+
+AsyncPromise<std::string> NativeConvexMobile::query(jsi::Runtime &rt, double client,
+                                                    std::string name, std::string args) {
+  return call(rt, convex_query, client, name, args);
+}
+
+AsyncPromise<std::string> NativeConvexMobile::mutation(jsi::Runtime &rt, double client,
+                                                       std::string name, std::string args) {
+  return call(rt, convex_mutation, client, name, args);
+}
+
+AsyncPromise<std::string> NativeConvexMobile::action(jsi::Runtime &rt, double client,
+                                                     std::string name, std::string args) {
+  return call(rt, convex_action, client, name, args);
+}
+
+double NativeConvexMobile::subscribe(jsi::Runtime &rt, double client, std::string name,
+                                     std::string args, AsyncCallback<std::string, bool> onUpdate,
+                                     AsyncCallback<std::string> onError) {
+  auto convexClient = lookup(client);
+  if (convexClient == nullptr) {
+    throw jsi::JSError(rt, "The client is closed");
+  }
+  auto context = new SubscriptionContext{std::move(onUpdate), std::move(onError)};
+  // IDs count up from 1, so they stay exact as JavaScript numbers.
+  return static_cast<double>(convex_subscribe(convexClient, name.c_str(), args.c_str(), context,
+                                              onSubscriptionUpdate));
+}
+
+void NativeConvexMobile::unsubscribe(jsi::Runtime &rt, double client, double id) {
+  if (auto convexClient = lookup(client)) {
+    convex_unsubscribe(convexClient, static_cast<uint64_t>(id));
+  }
+}
+
+AsyncPromise<std::string> NativeConvexMobile::setAuth(jsi::Runtime &rt, double client,
+                                                      std::optional<std::string> token) {
+  AsyncPromise<std::string> promise(rt, jsInvoker_);
+  auto convexClient = lookup(client);
+  if (convexClient == nullptr) {
+    promise.reject(Error("The client is closed"));
+    return promise;
+  }
+  convex_set_auth(convexClient, token ? token->c_str() : nullptr, new CallContext{promise},
+                  onCallResult);
+  return promise;
+}
+
+AsyncPromise<std::string> NativeConvexMobile::call(jsi::Runtime &rt, Call function, double client,
+                                                   const std::string &name,
+                                                   const std::string &args) {
+  AsyncPromise<std::string> promise(rt, jsInvoker_);
+  auto convexClient = lookup(client);
+  if (convexClient == nullptr) {
+    promise.reject(Error("The client is closed"));
+    return promise;
+  }
+  function(convexClient, name.c_str(), args.c_str(), new CallContext{promise}, onCallResult);
+  return promise;
+}
+
+const ConvexClient *NativeConvexMobile::lookup(double client) const {
+  auto found = clients_.find(static_cast<int>(client));
+  return found == clients_.end() ? nullptr : found->second;
+}
+
+} // namespace facebook::react
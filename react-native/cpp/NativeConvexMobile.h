@@ -0,0 +1,49 @@
+// A pure C++ TurboModule over the C ABI of rust/c, registered by the app as
+// "NativeConvexMobile".
+#pragma once
+
+#include <NativeConvexMobileSpecJSI.h>
+
+#include <memory>
+#include <optional>
+#include <string>
+#include <unordered_map>
+
+#include "convex_mobile.h"
+
+namespace facebook::react {
+
+class NativeConvexMobile : public NativeConvexMobileCxxSpec<NativeConvexMobile> {
+ public:
+  explicit NativeConvexMobile(std::shared_ptr<CallInvoker> jsInvoker);
+  ~NativeConvexMobile();
+
+  double createClient(jsi::Runtime &rt, std::string deploymentUrl, std::string clientId);
+  void freeClient(jsi::Runtime &rt, double client);
+  AsyncPromise<std::string> query(jsi::Runtime &rt, double client, std::string name,
+                                  std::string args);
+  AsyncPromise<std::string> mutation(jsi::Runtime &rt, double client, std::string name,
+                                     std::string args);
+  AsyncPromise<std::string> action(jsi::Runtime &rt, double client, std::string name,
+                                   std::string args);
+  double subscribe(jsi::Runtime &rt, double client, std::string name, std::string args,
+                   AsyncCallback<std::string, bool> onUpdate, AsyncCallback<std::string> onError);
+  void unsubscribe(jsi::Runtime &rt, double client, double id);
+  AsyncPromise<std::string> setAuth(jsi::Runtime &rt, double client,
+                                    std::optional<std::string> token);
+
+ private:
+  using Call = void (*)(const ConvexClient *, const char *, const char *, void *,
+                        ConvexResultCallback);
+
+  AsyncPromise<std::string> call(jsi::Runtime &rt, Call function, double client,
+                                 const std::string &name, const std::string &args);
+  const ConvexClient *lookup(double client) const;
+
+  // The clients by the number that JavaScript has for them. The module is
+  // only called on the JS thread.
+  std::unordered_map<int, ConvexClient *> clients_;
+  int nextClient_ = 1;
+};
+
+} // namespace facebook::react
@@ -0,0 +1,22 @@
+// The TurboModule spec of the C++ module in cpp/, which calls the C ABI of rust/c.
+import type {TurboModule} from 'react-native';
+import {TurboModuleRegistry} from 'react-native';
+
+export interface Spec extends TurboModule {
+  createClient(deploymentUrl: string, clientId: string): number;
+  freeClient(client: number): void;
+  query(client: number, name: string, args: string): Promise<string>;
+  mutation(client: number, name: string, args: string): Promise<string>;
+  action(client: number, name: string, args: string): Promise<string>;
+  subscribe(
+    client: number,
+    name: string,
+    args: string,
+    onUpdate: (value: string, isStale: boolean) => void,
+    onError: (error: string) => void,
+  ): number;
+  unsubscribe(client: number, id: number): void;
+  setAuth(client: number, token: string | null): Promise<string>;
+}
+
+export default TurboModuleRegistry.getEnforcing<Spec>('NativeConvexMobile');
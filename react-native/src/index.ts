@@ -0,0 +1,98 @@
+import NativeConvexMobile from '../specs/NativeConvexMobile';
+
+/**
+ * A call to the Convex backend that failed, with the `kind` of error that the
+ * client reported: `InternalError`, `ConvexError`, `ServerError`,
+ * `NetworkError`, `AuthError`, `InvalidArgument` or `Timeout`.
+ */
+export class ConvexException extends Error {
+  constructor(
+    readonly kind: string,
+    message: string,
+    /** The data that a backend function threw a `ConvexError` with. */
+    readonly data?: unknown,
+    /** Whether a `NetworkError` may go away once the network is back. */
+    readonly retryable = false,
+  ) {
+    super(message);
+    this.name = kind;
+  }
+}
+
+function toException(json: string): ConvexException {
+  try {
+    const error = JSON.parse(json);
+    return new ConvexException(error.kind, error.message, error.data, error.retryable === true);
+  } catch {
+    return new ConvexException('InternalError', json);
+  }
+}
+
+async function settle(call: Promise<string>): Promise<any> {
+  try {
+    return JSON.parse(await call);
+  } catch (e) {
+    throw e instanceof Error && !(e instanceof SyntaxError) ? toException(e.message) : e;
+  }
+}
+
+/**
+ * A client for a Convex deployment, with the same sync engine as the Android
+ * and iOS libraries rather than the JavaScript client's.
+ *
+ * Arguments are JSON serializable objects and results are parsed JSON. Call
+ * `close()` once the client isn't needed anymore.
+ */
+export class ConvexClient {
+  private readonly client: number;
+
+  constructor(deploymentUrl: string, clientId = 'react-native-convexmobile') {
+    this.client = NativeConvexMobile.createClient(deploymentUrl, clientId);
+  }
+
+  query(name: string, args: Record<string, unknown> = {}): Promise<any> {
+    return settle(NativeConvexMobile.query(this.client, name, JSON.stringify(args)));
+  }
+
+  mutation(name: string, args: Record<string, unknown> = {}): Promise<any> {
+    return settle(NativeConvexMobile.mutation(this.client, name, JSON.stringify(args)));
+  }
+
+  action(name: string, args: Record<string, unknown> = {}): Promise<any> {
+    return settle(NativeConvexMobile.action(this.client, name, JSON.stringify(args)));
+  }
+
+  /**
+   * Subscribes to the query `name`, calling `onUpdate` with each of its
+   * results. Results that are `isStale` come from the cache and are followed
+   * by fresh ones. Returns the function to unsubscribe with.
+   */
+  subscribe(
+    name: string,
+    args: Record<string, unknown>,
+    onUpdate: (value: any, isStale: boolean) => void,
+    onError?: (error: ConvexException) => void,
+  ): () => void {
+    const id = NativeConvexMobile.subscribe(
+      this.client,
+      name,
+      JSON.stringify(args),
+      (value, isStale) => onUpdate(JSON.parse(value), isStale),
+      (error) => onError?.(toException(error)),
+    );
+    return () => NativeConvexMobile.unsubscribe(this.client, id);
+  }
+
+  /** Sets the JWT `token` to authenticate as, or signs out if it's `null`. */
+  async setAuth(token: string | null): Promise<void> {
+    await settle(NativeConvexMobile.setAuth(this.client, token));
+  }
+
+  /**
+   * Shuts the client down. Calls that are still in flight fail and
+   * subscriptions stop.
+   */
+  close(): void {
+    NativeConvexMobile.freeClient(this.client);
+  }
+}
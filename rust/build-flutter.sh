@@ -0,0 +1,19 @@
+#!/usr/bin/env bash
+
+# Builds the C ABI library for the host into the Dart package in ../flutter, for running its tests
+# and for desktop apps. Android and iOS apps bundle it for their targets instead, see its README.
+
+set -e
+set -u
+
+basename=convexmobile_c
+package_dir=../flutter
+
+case "$(uname -s)" in
+  Darwin) lib_file=lib$basename.dylib ;;
+  MINGW*|MSYS*|CYGWIN*) lib_file=$basename.dll ;;
+  *) lib_file=lib$basename.so ;;
+esac
+
+cargo build -p convex-mobile-c --lib --release --no-default-features
+cp target/release/$lib_file $package_dir/
@@ -12,6 +12,11 @@ set -u
 # This is only needed when cutting a new release, not for local development.
 release=false
 
+# watchOS and tvOS are tier 3 Rust targets, so their standard library is built from source with a nightly
+# toolchain (`rustup toolchain install nightly --component rust-src`). They're only built when asked for.
+watchos=false
+tvos=false
+
 for arg in "$@"
 do
     case $arg in
@@ -19,6 +24,14 @@ do
             release=true
             shift # Remove --release from processing
             ;;
+        --watchos)
+            watchos=true
+            shift
+            ;;
+        --tvos)
+            tvos=true
+            shift
+            ;;
         *)
             shift # Ignore other argument from processing
             ;;
@@ -33,7 +46,10 @@ generate_ffi() {
   cargo run --features=uniffi/cli --bin uniffi-bindgen generate --library target/aarch64-apple-ios/release/lib$1.dylib --language swift --out-dir target/uniffi-xcframework-staging
   mkdir -p ../ios/Sources/UniFFI/
   mv target/uniffi-xcframework-staging/*.swift ../ios/Sources/UniFFI/
-  mv target/uniffi-xcframework-staging/$1FFI.modulemap target/uniffi-xcframework-staging/module.modulemap  # Convention requires this have a specific name
+  # The core and the FFI crate are generated as two modules, whose maps go in one file with the name that
+  # convention requires.
+  awk 1 target/uniffi-xcframework-staging/*FFI.modulemap > target/uniffi-xcframework-staging/module.modulemap
+  rm target/uniffi-xcframework-staging/*FFI.modulemap
 }
 
 create_simulator_lib() {
@@ -42,15 +58,46 @@ create_simulator_lib() {
   lipo -create target/aarch64-apple-ios-sim/release/lib$1.a -output $simulator_lib_dir/lib$1.a
 }
 
+create_catalyst_lib() {
+  echo "Creating a library for Mac Catalyst"
+  mkdir -p target/maccatalyst/release
+  lipo -create target/aarch64-apple-ios-macabi/release/lib$1.a target/x86_64-apple-ios-macabi/release/lib$1.a \
+    -output target/maccatalyst/release/lib$1.a
+}
+
+build_tier3() {
+  cargo +nightly build -Z build-std --lib --release --target $1
+}
+
+create_watchos_lib() {
+  # Apple Watches before Series 9 are arm64_32, later ones arm64, and the App Store takes both in one library.
+  echo "Creating a library for watchOS devices"
+  mkdir -p target/watchos/release
+  lipo -create target/arm64_32-apple-watchos/release/lib$1.a target/aarch64-apple-watchos/release/lib$1.a \
+    -output target/watchos/release/lib$1.a
+}
+
 build_xcframework() {
   # Builds an XCFramework
   echo "Generating XCFramework"
   rm -rf target/ios  # Delete the output folder so we can regenerate it
-  xcodebuild -create-xcframework \
-    -library target/aarch64-apple-ios/release/lib$1.a -headers target/uniffi-xcframework-staging \
-    -library target/ios-simulator/release/lib$1.a -headers target/uniffi-xcframework-staging \
-    -library target/aarch64-apple-darwin/release/lib$1.a -headers target/uniffi-xcframework-staging \
-    -output target/ios/lib$1-rs.xcframework
+  libraries=(
+    target/aarch64-apple-ios/release/lib$1.a
+    target/ios-simulator/release/lib$1.a
+    target/aarch64-apple-darwin/release/lib$1.a
+    target/maccatalyst/release/lib$1.a
+  )
+  if $watchos; then
+    libraries+=(target/watchos/release/lib$1.a target/aarch64-apple-watchos-sim/release/lib$1.a)
+  fi
+  if $tvos; then
+    libraries+=(target/aarch64-apple-tvos/release/lib$1.a target/aarch64-apple-tvos-sim/release/lib$1.a)
+  fi
+  args=()
+  for library in $libraries; do
+    args+=(-library $library -headers target/uniffi-xcframework-staging)
+  done
+  xcodebuild -create-xcframework $args -output target/ios/lib$1-rs.xcframework
   cp -R target/ios/lib$1-rs.xcframework ../ios
 
   if $release; then
@@ -66,8 +113,24 @@ build_xcframework() {
 cargo build --lib --release --target aarch64-apple-ios-sim
 cargo build --lib --release --target aarch64-apple-ios
 cargo build --lib --release --target aarch64-apple-darwin
+# Mac Catalyst builds of iPad apps link these, which are iOS to Rust, so they log to os_log too.
+cargo build --lib --release --target aarch64-apple-ios-macabi
+cargo build --lib --release --target x86_64-apple-ios-macabi
+if $watchos; then
+  build_tier3 arm64_32-apple-watchos
+  build_tier3 aarch64-apple-watchos
+  build_tier3 aarch64-apple-watchos-sim
+fi
+if $tvos; then
+  build_tier3 aarch64-apple-tvos
+  build_tier3 aarch64-apple-tvos-sim
+fi
 
 basename=convexmobile
 generate_ffi $basename
 create_simulator_lib $basename
+create_catalyst_lib $basename
+if $watchos; then
+  create_watchos_lib $basename
+fi
 build_xcframework $basename
\ No newline at end of file
@@ -0,0 +1,29 @@
+#!/usr/bin/env bash
+
+# Builds the Python package in ../python for the host: the Rust library for its OS and architecture,
+# and the UniFFI bindings generated from it.
+#
+# Wheels are platform specific, so run this on every platform that wheels are published for.
+
+set -e
+set -u
+
+basename=convexmobile
+package_dir=../python/convexmobile
+
+case "$(uname -s)" in
+  Darwin) lib_file=lib$basename.dylib ;;
+  MINGW*|MSYS*|CYGWIN*) lib_file=$basename.dll ;;
+  *) lib_file=lib$basename.so ;;
+esac
+
+cargo build --lib --release --no-default-features
+
+echo "Generating Python bindings"
+cargo run --no-default-features --bin uniffi-bindgen generate --library target/release/$lib_file --language python --out-dir target/uniffi-python-staging
+mkdir -p $package_dir
+# The bindings load the library from their own directory. The core and the FFI crate are generated as
+# two modules, which the package re-exports together.
+mv target/uniffi-python-staging/$basename.py target/uniffi-python-staging/${basename}_ffi.py $package_dir/
+printf 'from .%s import *\nfrom .%s_ffi import *\n' $basename $basename > $package_dir/__init__.py
+cp target/release/$lib_file $package_dir/
@@ -1,3 +1,9 @@
 fn main() {
-    uniffi::generate_scaffolding("src/convex-mobile.udl").unwrap();
+    // iOS, watchOS and tvOS all log to os_log, which `cfg(apple_mobile)`
+    // stands for. Cargo.toml can't use it, so it lists the three instead.
+    println!("cargo::rustc-check-cfg=cfg(apple_mobile)");
+    let target_os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    if matches!(target_os.as_str(), "ios" | "watchos" | "tvos") {
+        println!("cargo::rustc-cfg=apple_mobile");
+    }
 }
@@ -0,0 +1,81 @@
+/*
+ * C ABI of the Convex mobile client, for hosts that UniFFI doesn't generate
+ * bindings for, like Dart's dart:ffi.
+ *
+ * Calls return right away and report to a callback, on a thread of the
+ * client's. Strings passed to callbacks belong to the host, which frees them
+ * with convex_string_free().
+ *
+ * Function arguments are a JSON object, results are JSON, and errors are JSON
+ * objects like {"kind": "ConvexError", "message": "...", "data": ...}. The
+ * kind is one of InternalError, ConvexError, ServerError, NetworkError,
+ * AuthError, InvalidArgument and Timeout. ConvexErrors have the "data" they
+ * were thrown with, and NetworkErrors whether they're "retryable".
+ *
+ * Contexts are passed back to their callback as they are, and must stay valid
+ * on any thread until their last callback.
+ */
+#ifndef CONVEX_MOBILE_H
+#define CONVEX_MOBILE_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ConvexClient ConvexClient;
+
+/* Called once with the value of a call that succeeded, or the error of one
+ * that failed. The other one is NULL. */
+typedef void (*ConvexResultCallback)(void *context, char *value, char *error);
+
+/* Called with the value or error of every update of a subscription, the other
+ * one being NULL, and then once with both NULL when it stopped, after which
+ * the context isn't used again. */
+typedef void (*ConvexUpdateCallback)(void *context, char *value, bool is_stale, char *error);
+
+/* Creates a client for the deployment at deployment_url, which connects on its
+ * first call. The client_id names the host, like "flutter-my-app-1.0.0".
+ * Returns NULL if either string isn't valid UTF-8. */
+ConvexClient *convex_client_new(const char *deployment_url, const char *client_id);
+
+/* Shuts the client down and frees it. Calls that are still in flight fail and
+ * subscriptions stop, which their callbacks still hear about.
+ *
+ * This returns right away, before the client has finished shutting down, as
+ * stopping its runtime can take seconds and the caller is often the host's UI
+ * thread. */
+void convex_client_free(ConvexClient *client);
+
+/* Runs a function with the args JSON object, which may be NULL for none. */
+void convex_query(const ConvexClient *client, const char *name, const char *args, void *context,
+                  ConvexResultCallback callback);
+void convex_mutation(const ConvexClient *client, const char *name, const char *args, void *context,
+                     ConvexResultCallback callback);
+void convex_action(const ConvexClient *client, const char *name, const char *args, void *context,
+                   ConvexResultCallback callback);
+
+/* Subscribes to a query and returns the ID to unsubscribe with. Failing to
+ * subscribe is reported to the callback, which is then told that the
+ * subscription stopped. */
+uint64_t convex_subscribe(const ConvexClient *client, const char *name, const char *args,
+                          void *context, ConvexUpdateCallback callback);
+
+/* Stops a subscription. Unsubscribing again does nothing. */
+void convex_unsubscribe(const ConvexClient *client, uint64_t id);
+
+/* Sets the JWT to authenticate as, or signs out if token is NULL. The value
+ * of a success is "null". */
+void convex_set_auth(const ConvexClient *client, const char *token, void *context,
+                     ConvexResultCallback callback);
+
+/* Frees a string that was passed to a callback. */
+void convex_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CONVEX_MOBILE_H */
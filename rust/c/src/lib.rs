@@ -0,0 +1,542 @@
+//! A C ABI for the Convex mobile client, for hosts that UniFFI doesn't
+//! generate bindings for, like Dart's `dart:ffi`. It's declared in
+//! `include/convex_mobile.h`.
+//!
+//! Calls return right away and report to a callback of the host's, on a
+//! thread of the client's. Strings passed to callbacks belong to the host,
+//! which frees them with [convex_string_free]. Function arguments are a JSON
+//! object, results are JSON and errors are JSON objects with the `kind` of
+//! [ClientError] and a `message`, along with the `data` of a `ConvexError`
+//! and whether a `NetworkError` is `retryable`.
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_void, CStr, CString},
+    future::Future,
+    ptr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use convex_mobile_core::{ClientError, MobileConvexClient, QuerySubscriber, SubscriptionHandle};
+use serde_json::{json, Value};
+use tokio::runtime::{Builder, Runtime};
+
+/// Waits for the results of the calls of every client, which do their work
+/// on runtimes of their own.
+static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
+    Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .thread_name("convex-c")
+        .build()
+        .unwrap()
+});
+
+/// Called once with the JSON `value` of a call that succeeded, or the JSON
+/// `error` of one that failed. The other one is null.
+pub type ConvexResultCallback =
+    extern "C" fn(context: *mut c_void, value: *mut c_char, error: *mut c_char);
+
+/// Called with the JSON `value` or `error` of every update of a subscription,
+/// the other one being null, and then once with both null when it stopped.
+pub type ConvexUpdateCallback =
+    extern "C" fn(context: *mut c_void, value: *mut c_char, is_stale: bool, error: *mut c_char);
+
+/// The pointer that the host gets back in its callback.
+#[derive(Clone, Copy)]
+struct Context(*mut c_void);
+
+// SAFETY: The header requires contexts to stay valid on any thread until
+// their last callback.
+unsafe impl Send for Context {}
+unsafe impl Sync for Context {}
+
+/// A client for the C ABI, which also keeps the handles of its
+/// subscriptions, as the host only gets their IDs.
+pub struct ConvexClient {
+    client: Arc<MobileConvexClient>,
+    /// The handles of the subscriptions by ID, which are `None` until they're
+    /// subscribed.
+    subscriptions: Arc<Mutex<HashMap<u64, Option<Arc<SubscriptionHandle>>>>>,
+    next_subscription_id: AtomicU64,
+}
+
+struct ResultCallback {
+    callback: ConvexResultCallback,
+    context: Context,
+}
+
+impl ResultCallback {
+    fn call(self, result: Result<String, ClientError>) {
+        match result {
+            Ok(value) => (self.callback)(self.context.0, into_c_string(value), ptr::null_mut()),
+            Err(error) => (self.callback)(
+                self.context.0,
+                ptr::null_mut(),
+                into_c_string(error_json(&error)),
+            ),
+        }
+    }
+}
+
+struct CallbackSubscriber {
+    callback: ConvexUpdateCallback,
+    context: Context,
+}
+
+impl CallbackSubscriber {
+    fn error(&self, error: String) {
+        (self.callback)(self.context.0, ptr::null_mut(), false, into_c_string(error));
+    }
+}
+
+#[async_trait::async_trait]
+impl QuerySubscriber for CallbackSubscriber {
+    async fn on_update(&self, value: String, is_stale: bool) {
+        (self.callback)(
+            self.context.0,
+            into_c_string(value),
+            is_stale,
+            ptr::null_mut(),
+        );
+    }
+
+    async fn on_error(&self, message: String, data: Option<String>) {
+        let error = match data {
+            Some(data) => {
+                json!({ "kind": "ConvexError", "message": message, "data": parse_json(&data) })
+            },
+            None => json!({ "kind": "ServerError", "message": message }),
+        };
+        self.error(error.to_string());
+    }
+
+    async fn on_subscribed(&self) {}
+
+    async fn on_resubscribed(&self) {}
+}
+
+impl Drop for CallbackSubscriber {
+    /// Tells the host that the subscription stopped, so it can let go of the
+    /// context.
+    fn drop(&mut self) {
+        (self.callback)(self.context.0, ptr::null_mut(), false, ptr::null_mut());
+    }
+}
+
+/// Creates a client for the deployment at `deployment_url`, which connects on
+/// its first call. The `client_id` names the host, like
+/// `flutter-my-app-1.0.0`.
+///
+/// Returns null if either string isn't valid UTF-8.
+///
+/// # Safety
+///
+/// Both strings must be valid and NUL terminated.
+#[no_mangle]
+pub unsafe extern "C" fn convex_client_new(
+    deployment_url: *const c_char,
+    client_id: *const c_char,
+) -> *mut ConvexClient {
+    let (Ok(Some(deployment_url)), Ok(Some(client_id))) = (
+        read_string(deployment_url, "deployment_url"),
+        read_string(client_id, "client_id"),
+    ) else {
+        return ptr::null_mut();
+    };
+    Box::into_raw(Box::new(ConvexClient {
+        client: Arc::new(MobileConvexClient::new(deployment_url, client_id, None)),
+        subscriptions: Arc::default(),
+        next_subscription_id: AtomicU64::new(1),
+    }))
+}
+
+/// Shuts the client down and frees it. Calls that are still in flight fail
+/// and subscriptions stop, which their callbacks still hear about.
+///
+/// This returns right away, before the client has finished shutting down, as
+/// stopping its runtime can take seconds and the caller is often the host's UI
+/// thread.
+///
+/// # Safety
+///
+/// `client` must be null or a client from [convex_client_new] that isn't
+/// used again.
+#[no_mangle]
+pub unsafe extern "C" fn convex_client_free(client: *mut ConvexClient) {
+    if client.is_null() {
+        return;
+    }
+    let client = Box::from_raw(client);
+    RUNTIME.spawn_blocking(move || {
+        client.client.shutdown();
+        let subscriptions = std::mem::take(&mut *lock(&client.subscriptions));
+        drop(subscriptions);
+    });
+}
+
+/// Runs the query `name` with the `args` JSON object, which may be null for
+/// no arguments.
+///
+/// # Safety
+///
+/// `client` must be a client from [convex_client_new] and the strings must
+/// be valid and NUL terminated. `context` must stay valid for `callback` to
+/// use on any thread until it's called.
+#[no_mangle]
+pub unsafe extern "C" fn convex_query(
+    client: *const ConvexClient,
+    name: *const c_char,
+    args: *const c_char,
+    context: *mut c_void,
+    callback: ConvexResultCallback,
+) {
+    call(
+        client,
+        name,
+        args,
+        context,
+        callback,
+        |client, name, args| async move { client.query(name, args, None).await },
+    );
+}
+
+/// Runs the mutation `name`, like [convex_query].
+///
+/// # Safety
+///
+/// See [convex_query].
+#[no_mangle]
+pub unsafe extern "C" fn convex_mutation(
+    client: *const ConvexClient,
+    name: *const c_char,
+    args: *const c_char,
+    context: *mut c_void,
+    callback: ConvexResultCallback,
+) {
+    call(
+        client,
+        name,
+        args,
+        context,
+        callback,
+        |client, name, args| async move { client.mutation(name, args, None).await },
+    );
+}
+
+/// Runs the action `name`, like [convex_query].
+///
+/// # Safety
+///
+/// See [convex_query].
+#[no_mangle]
+pub unsafe extern "C" fn convex_action(
+    client: *const ConvexClient,
+    name: *const c_char,
+    args: *const c_char,
+    context: *mut c_void,
+    callback: ConvexResultCallback,
+) {
+    call(
+        client,
+        name,
+        args,
+        context,
+        callback,
+        |client, name, args| async move { client.action(name, args, None).await },
+    );
+}
+
+/// Subscribes to the query `name` with the `args` JSON object, which may be
+/// null for no arguments, and returns the ID to unsubscribe with.
+///
+/// `callback` gets the subscription's results and errors, including the one
+/// for failing to subscribe, and is called once more with neither a value
+/// nor an error when it stopped, after which `context` isn't used again.
+///
+/// # Safety
+///
+/// See [convex_query]. `context` must stay valid until the last callback.
+#[no_mangle]
+pub unsafe extern "C" fn convex_subscribe(
+    client: *const ConvexClient,
+    name: *const c_char,
+    args: *const c_char,
+    context: *mut c_void,
+    callback: ConvexUpdateCallback,
+) -> u64 {
+    let client = &*client;
+    let subscriber = Arc::new(CallbackSubscriber {
+        callback,
+        context: Context(context),
+    });
+    let id = client.next_subscription_id.fetch_add(1, Ordering::Relaxed);
+    lock(&client.subscriptions).insert(id, None);
+    let request = read_request(name, args);
+    let inner = client.client.clone();
+    let subscriptions = client.subscriptions.clone();
+    RUNTIME.spawn(async move {
+        let result = match request {
+            Ok((name, args)) => inner.subscribe(name, args, subscriber.clone()).await,
+            Err(error) => Err(error),
+        };
+        match result {
+            // Dropping the handle cancels a subscription that was unsubscribed
+            // in the meantime.
+            Ok(handle) => {
+                if let Some(entry) = lock(&subscriptions).get_mut(&id) {
+                    *entry = Some(handle);
+                }
+            },
+            Err(error) => {
+                lock(&subscriptions).remove(&id);
+                subscriber.error(error_json(&error));
+            },
+        }
+    });
+    id
+}
+
+/// Stops the subscription `id`. Doing that again does nothing.
+///
+/// # Safety
+///
+/// `client` must be a client from [convex_client_new].
+#[no_mangle]
+pub unsafe extern "C" fn convex_unsubscribe(client: *const ConvexClient, id: u64) {
+    let handle = lock(&(*client).subscriptions).remove(&id);
+    drop(handle);
+}
+
+/// Sets the JWT `token` to authenticate as, or signs out if it's null. The
+/// `value` of a success is `null`.
+///
+/// # Safety
+///
+/// See [convex_query].
+#[no_mangle]
+pub unsafe extern "C" fn convex_set_auth(
+    client: *const ConvexClient,
+    token: *const c_char,
+    context: *mut c_void,
+    callback: ConvexResultCallback,
+) {
+    let callback = ResultCallback {
+        callback,
+        context: Context(context),
+    };
+    let token = read_string(token, "token");
+    let client = (*client).client.clone();
+    RUNTIME.spawn(async move {
+        let result = match token {
+            Ok(token) => client.set_auth(token).await,
+            Err(error) => Err(error),
+        };
+        callback.call(result.map(|()| "null".into()));
+    });
+}
+
+/// Frees a string that was passed to a callback.
+///
+/// # Safety
+///
+/// `string` must be null or a string from a callback that isn't used again.
+#[no_mangle]
+pub unsafe extern "C" fn convex_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Runs a function call on [RUNTIME] and passes its result to `callback`.
+unsafe fn call<F, Fut>(
+    client: *const ConvexClient,
+    name: *const c_char,
+    args: *const c_char,
+    context: *mut c_void,
+    callback: ConvexResultCallback,
+    f: F,
+) where
+    F: FnOnce(Arc<MobileConvexClient>, String, HashMap<String, String>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<String, ClientError>> + Send,
+{
+    let callback = ResultCallback {
+        callback,
+        context: Context(context),
+    };
+    let request = read_request(name, args);
+    let client = (*client).client.clone();
+    RUNTIME.spawn(async move {
+        let result = match request {
+            Ok((name, args)) => f(client, name, args).await,
+            Err(error) => Err(error),
+        };
+        callback.call(result);
+    });
+}
+
+/// Reads the name and arguments of a function call.
+unsafe fn read_request(
+    name: *const c_char,
+    args: *const c_char,
+) -> Result<(String, HashMap<String, String>), ClientError> {
+    let name = read_string(name, "name")?.ok_or_else(|| invalid_argument("name", "is null"))?;
+    let args = function_args(read_string(args, "args")?)?;
+    Ok((name, args))
+}
+
+/// Reads a string of the host's, which is `None` if it's null.
+unsafe fn read_string(string: *const c_char, key: &str) -> Result<Option<String>, ClientError> {
+    if string.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map(|string| Some(string.to_owned()))
+        .map_err(|e| invalid_argument(key, e))
+}
+
+/// Splits a JSON object of arguments into the JSON of each one, which is what
+/// [MobileConvexClient] takes.
+fn function_args(args: Option<String>) -> Result<HashMap<String, String>, ClientError> {
+    let Some(args) = args else {
+        return Ok(HashMap::new());
+    };
+    match serde_json::from_str(&args) {
+        Ok(Value::Object(args)) => Ok(args
+            .into_iter()
+            .map(|(key, value)| (key, value.to_string()))
+            .collect()),
+        Ok(_) => Err(invalid_argument("args", "isn't a JSON object")),
+        Err(e) => Err(invalid_argument("args", e)),
+    }
+}
+
+fn invalid_argument(key: &str, reason: impl ToString) -> ClientError {
+    ClientError::InvalidArgument {
+        key: key.into(),
+        reason: reason.to_string(),
+    }
+}
+
+fn error_json(error: &ClientError) -> String {
+    let error = match error {
+        ClientError::InternalError { msg } => json!({ "kind": "InternalError", "message": msg }),
+        ClientError::ConvexError { data } => {
+            json!({ "kind": "ConvexError", "message": error.to_string(), "data": parse_json(data) })
+        },
+        ClientError::ServerError { msg } => json!({ "kind": "ServerError", "message": msg }),
+        ClientError::NetworkError { msg, retryable } => {
+            json!({ "kind": "NetworkError", "message": msg, "retryable": retryable })
+        },
+        ClientError::AuthError { msg } => json!({ "kind": "AuthError", "message": msg }),
+        ClientError::InvalidArgument { key, reason } => {
+            json!({ "kind": "InvalidArgument", "message": format!("{key}: {reason}") })
+        },
+        ClientError::Timeout { msg } => json!({ "kind": "Timeout", "message": msg }),
+    };
+    error.to_string()
+}
+
+/// Parses the JSON `data` of an error, keeping it as a string if it isn't.
+fn parse_json(data: &str) -> Value {
+    serde_json::from_str(data).unwrap_or_else(|_| Value::String(data.into()))
+}
+
+/// Hands a string over to the host. JSON never contains NUL bytes, as they're
+/// escaped in strings.
+fn into_c_string(string: String) -> *mut c_char {
+    CString::new(string)
+        .unwrap_or_else(|e| {
+            let mut bytes = e.into_vec();
+            bytes.retain(|&byte| byte != 0);
+            CString::new(bytes).unwrap()
+        })
+        .into_raw()
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::{c_char, c_void, CStr},
+        sync::mpsc,
+        time::Duration,
+    };
+
+    use super::{
+        convex_client_free, convex_client_new, convex_query, convex_string_free, convex_subscribe,
+    };
+
+    type Received = (Option<String>, Option<String>);
+
+    unsafe fn take(string: *mut c_char) -> Option<String> {
+        if string.is_null() {
+            return None;
+        }
+        let taken = CStr::from_ptr(string).to_str().unwrap().to_owned();
+        convex_string_free(string);
+        Some(taken)
+    }
+
+    extern "C" fn on_result(context: *mut c_void, value: *mut c_char, error: *mut c_char) {
+        let sender = unsafe { &*(context as *const mpsc::Sender<Received>) };
+        let _ = sender.send(unsafe { (take(value), take(error)) });
+    }
+
+    extern "C" fn on_update(
+        context: *mut c_void,
+        value: *mut c_char,
+        _is_stale: bool,
+        error: *mut c_char,
+    ) {
+        on_result(context, value, error);
+    }
+
+    #[test]
+    fn test_calls_with_invalid_args_fail() {
+        let (sender, receiver) = mpsc::channel::<Received>();
+        // Leaked, as the last callback may still be in `send` once the test
+        // has received what it sent.
+        let context = Box::into_raw(Box::new(sender)) as *mut c_void;
+        unsafe {
+            let client =
+                convex_client_new(c"https://example.convex.cloud".as_ptr(), c"tests".as_ptr());
+            convex_query(
+                client,
+                c"messages:list".as_ptr(),
+                c"[1, 2]".as_ptr(),
+                context,
+                on_result,
+            );
+            let (value, error) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(value, None);
+            assert_eq!(
+                error.unwrap(),
+                r#"{"kind":"InvalidArgument","message":"args: isn't a JSON object"}"#
+            );
+
+            // A subscription that fails tells the host that it stopped too.
+            convex_subscribe(
+                client,
+                c"messages:list".as_ptr(),
+                c"{".as_ptr(),
+                context,
+                on_update,
+            );
+            let (value, error) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(value, None);
+            assert!(error.unwrap().contains("InvalidArgument"));
+            assert_eq!(
+                receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+                (None, None)
+            );
+            convex_client_free(client);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Tracking of whether the backend has accepted the client's auth.
+//!
+//! The sync protocol doesn't acknowledge auth explicitly, so like the JS client
+//! we consider it accepted once the backend sends a transition after it was
+//! set, and rejected when the backend responds with an auth error.
+use std::sync::{Arc, Weak};
+
+use base64::Engine;
+use convex::QuerySetSubscription;
+use futures::StreamExt;
+use parking_lot::Mutex;
+use tokio::sync::watch;
+
+use crate::{
+    events::{self, ConvexEvent, EventSink, Origin},
+    ClientError,
+};
+
+/// Whether the client is authenticated with the backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum AuthState {
+    /// No auth is set, or the backend rejected it with `error`.
+    Unauthenticated { error: Option<String> },
+    /// Auth was set and the backend hasn't accepted it yet.
+    AuthLoading,
+    /// The backend accepted the auth. The `identity` is the JSON encoded claims
+    /// of the token, if it's a JWT.
+    Authenticated { identity: Option<String> },
+}
+
+/// Receives changes to the [AuthState].
+///
+/// Like [crate::WebSocketStateSubscriber], only the latest state is delivered
+/// once the host is ready for it.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait AuthStateListener: Send + Sync {
+    async fn on_auth_state_change(&self, state: AuthState) -> ();
+}
+
+/// Gets told when the backend rejects the auth, e.g. because the token
+/// expired, once for each auth that the host sets.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait AuthErrorListener: Send + Sync {
+    async fn on_auth_error(&self, message: String) -> ();
+}
+
+/// Secure storage for auth material, implemented by the host on top of the
+/// Android Keystore or the iOS Keychain, so sessions survive restarts.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait TokenStore: Send + Sync {
+    async fn load(&self) -> Result<Option<String>, ClientError>;
+
+    async fn save(&self, material: String) -> Result<(), ClientError>;
+
+    async fn clear(&self) -> Result<(), ClientError>;
+}
+
+pub(crate) struct AuthTracker {
+    state: watch::Sender<AuthState>,
+    /// The identity of the auth that's loading.
+    pending_identity: Mutex<Option<String>>,
+    /// The first rejection of each auth the host set, which the same auth
+    /// getting rejected again on reconnecting doesn't replace.
+    rejection: watch::Sender<Option<String>>,
+    /// How many auths the host has set, and which of them was last rejected.
+    auths: Mutex<(u64, Option<u64>)>,
+}
+
+impl AuthTracker {
+    /// Creates a tracker for the rejections logged on the threads of `origin`,
+    /// see [events::register].
+    pub(crate) fn new(origin: Option<Origin>) -> Arc<Self> {
+        let tracker = Arc::new(AuthTracker {
+            state: watch::Sender::new(AuthState::Unauthenticated { error: None }),
+            pending_identity: Mutex::new(None),
+            rejection: watch::Sender::new(None),
+            auths: Mutex::new((0, None)),
+        });
+        events::register(origin, Arc::downgrade(&tracker) as Weak<dyn EventSink>);
+        tracker
+    }
+
+    pub(crate) fn watch(&self) -> watch::Receiver<AuthState> {
+        self.state.subscribe()
+    }
+
+    pub(crate) fn watch_rejection(&self) -> watch::Receiver<Option<String>> {
+        self.rejection.subscribe()
+    }
+
+    /// The host set a new auth, which may get rejected in turn.
+    pub(crate) fn auth_set(&self) {
+        self.auths.lock().0 += 1;
+    }
+
+    /// `token` was sent to the backend.
+    pub(crate) fn loading(&self, token: &str) {
+        *self.pending_identity.lock() = token_claims(token).map(|claims| claims.to_string());
+        self.state.send_replace(AuthState::AuthLoading);
+    }
+
+    /// A token is being fetched, to be sent to the backend.
+    pub(crate) fn fetching(&self) {
+        *self.pending_identity.lock() = None;
+        self.state.send_replace(AuthState::AuthLoading);
+    }
+
+    pub(crate) fn logged_out(&self) {
+        self.state
+            .send_replace(AuthState::Unauthenticated { error: None });
+    }
+
+    pub(crate) fn rejected(&self, error: String) {
+        let mut auths = self.auths.lock();
+        if auths.1 != Some(auths.0) {
+            auths.1 = Some(auths.0);
+            self.rejection.send_replace(Some(error.clone()));
+        }
+        self.state
+            .send_replace(AuthState::Unauthenticated { error: Some(error) });
+    }
+
+    fn on_transition(&self) {
+        self.state.send_if_modified(|state| {
+            if *state != AuthState::AuthLoading {
+                return false;
+            }
+            *state = AuthState::Authenticated {
+                identity: self.pending_identity.lock().clone(),
+            };
+            true
+        });
+    }
+}
+
+impl EventSink for AuthTracker {
+    fn on_event(&self, event: &ConvexEvent) {
+        let ConvexEvent::AuthError(error) = event else {
+            return;
+        };
+        // Clients on the shared runtime can't tell whose auth was rejected,
+        // but it can't have been one that has none.
+        if matches!(*self.state.borrow(), AuthState::Unauthenticated { .. }) {
+            return;
+        }
+        self.rejected(error.clone());
+    }
+}
+
+/// Marks loading auth as accepted on each of the `transitions` of a client.
+pub(crate) async fn watch_transitions(
+    tracker: Weak<AuthTracker>,
+    mut transitions: QuerySetSubscription,
+) {
+    while transitions.next().await.is_some() {
+        let Some(tracker) = tracker.upgrade() else {
+            break;
+        };
+        tracker.on_transition();
+    }
+}
+
+/// The claims of a JWT.
+pub(crate) fn token_claims(token: &str) -> Option<serde_json::Value> {
+    let payload = token.split('.').nth(1)?;
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    serde_json::from_slice(&payload).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthState, AuthTracker};
+
+    #[test]
+    fn test_auth_is_accepted_on_the_next_transition() {
+        let tracker = AuthTracker::new(None);
+        let states = tracker.watch();
+
+        tracker.on_transition();
+        assert_eq!(*states.borrow(), AuthState::Unauthenticated { error: None });
+
+        tracker.loading("not a jwt");
+        assert_eq!(*states.borrow(), AuthState::AuthLoading);
+        tracker.on_transition();
+        assert_eq!(
+            *states.borrow(),
+            AuthState::Authenticated { identity: None }
+        );
+    }
+
+    #[test]
+    fn test_each_auth_is_rejected_once() {
+        let tracker = AuthTracker::new(None);
+        let mut rejection = tracker.watch_rejection();
+
+        tracker.auth_set();
+        tracker.rejected("expired".into());
+        assert!(rejection.has_changed().unwrap());
+        assert_eq!(*rejection.borrow_and_update(), Some("expired".into()));
+        tracker.rejected("expired".into());
+        assert!(!rejection.has_changed().unwrap());
+
+        tracker.auth_set();
+        tracker.rejected("invalid".into());
+        assert_eq!(*rejection.borrow_and_update(), Some("invalid".into()));
+    }
+}
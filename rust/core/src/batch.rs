@@ -0,0 +1,89 @@
+//! Several function calls made together.
+use std::collections::{BTreeMap, HashMap};
+
+use convex::{ConvexClient, FunctionResult, Value};
+use futures::{FutureExt, StreamExt};
+
+/// A function to call as part of a batch, with its JSON encoded arguments.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct FunctionCall {
+    pub name: String,
+    pub args: HashMap<String, String>,
+}
+
+/// How one function call of a batch turned out.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum FunctionCallResult {
+    Success {
+        value: String,
+    },
+    /// The `data` is that of a `ConvexError` thrown by the function, if
+    /// that's what it was.
+    Failure {
+        message: String,
+        data: Option<String>,
+    },
+}
+
+impl From<anyhow::Result<FunctionResult>> for FunctionCallResult {
+    fn from(result: anyhow::Result<FunctionResult>) -> Self {
+        match result {
+            Ok(FunctionResult::Value(value)) => FunctionCallResult::Success {
+                value: serde_json::Value::from(value).to_string(),
+            },
+            Ok(FunctionResult::ErrorMessage(message)) => FunctionCallResult::Failure {
+                message,
+                data: None,
+            },
+            Ok(FunctionResult::ConvexError(error)) => FunctionCallResult::Failure {
+                message: error.message,
+                data: Some(serde_json::Value::from(error.data).to_string()),
+            },
+            Err(e) => FunctionCallResult::Failure {
+                message: e.to_string(),
+                data: None,
+            },
+        }
+    }
+}
+
+/// Runs `queries` and returns their results, all as of the same timestamp.
+///
+/// Every transition from the backend carries the results of all the client's
+/// queries at a single timestamp, so this subscribes to the queries and waits
+/// for a view of them that has all of their results.
+pub(crate) async fn query_batch(
+    client: &mut ConvexClient,
+    queries: Vec<(String, BTreeMap<String, Value>)>,
+) -> anyhow::Result<Vec<FunctionResult>> {
+    let mut snapshots = client.watch_all();
+    let mut subscriptions = Vec::with_capacity(queries.len());
+    for (name, args) in queries {
+        subscriptions.push(client.subscribe(&name, args).await?);
+    }
+    // Queries that another subscription already has a result for start with
+    // it, as of the latest transition.
+    let mut results: Vec<_> = subscriptions
+        .iter_mut()
+        .map(|subscription| subscription.next().now_or_never().flatten())
+        .collect();
+    loop {
+        let snapshot = match snapshots.next().now_or_never() {
+            Some(snapshot) => snapshot,
+            // Nothing happened since the last transition that was applied, so
+            // all the results are as of its timestamp.
+            None if results.iter().all(Option::is_some) => {
+                return Ok(results.into_iter().flatten().collect());
+            },
+            None => snapshots.next().await,
+        };
+        let snapshot = snapshot.ok_or_else(|| anyhow::anyhow!("The client disconnected"))?;
+        for (result, subscription) in results.iter_mut().zip(&subscriptions) {
+            if let Some(latest) = snapshot.get(subscription.id()) {
+                *result = Some(latest.clone());
+            }
+        }
+    }
+}
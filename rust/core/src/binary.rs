@@ -0,0 +1,183 @@
+//! Results that cross the FFI boundary as bytes instead of JSON strings,
+//! which are quicker to produce and parse for large ones.
+//!
+//! CBOR and MessagePack keep the Convex types apart without JSON's special
+//! objects: an `Int64` is an integer, a `Float64` is a float and `Bytes` are
+//! a byte string. CBOR floats take the shortest width that holds the value
+//! exactly, and MessagePack ones are always 8 bytes. UTF-8 JSON bytes have
+//! the same special objects as JSON strings, but save hosts from decoding
+//! multi-megabyte results into a string before they parse them.
+//!
+//! The Android library decodes the binary formats with kotlinx.serialization.
+//! Swift has no CBOR or MessagePack decoder built in, so iOS hosts are best
+//! served by [BinaryFormat::Json], which `JSONDecoder` parses from the bytes
+//! as they are.
+use convex::Value;
+use serde::{Serialize, Serializer};
+
+/// How results are encoded as bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum BinaryFormat {
+    /// [CBOR](https://www.rfc-editor.org/rfc/rfc8949).
+    Cbor,
+    /// [MessagePack](https://msgpack.org).
+    MessagePack,
+    /// UTF-8 JSON, like the results of
+    /// [MobileConvexClient::query](crate::MobileConvexClient::query).
+    Json,
+}
+
+/// Receives updates for a query subscription, with results in a
+/// [BinaryFormat].
+///
+/// Updates are delivered like those of a
+/// [QuerySubscriber](crate::QuerySubscriber).
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait BinaryQuerySubscriber: Send + Sync {
+    async fn on_update(&self, value: Vec<u8>, is_stale: bool);
+
+    /// `data` is that of a `ConvexError`, in the same format as the results.
+    async fn on_error(&self, message: String, data: Option<Vec<u8>>);
+
+    async fn on_subscribed(&self);
+
+    async fn on_resubscribed(&self);
+}
+
+/// Encodes `value` in `format`.
+pub(crate) fn encode(format: BinaryFormat, value: Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    match format {
+        BinaryFormat::Cbor => {
+            ciborium::into_writer(&Typed(&value), &mut out).expect("values always encode")
+        },
+        BinaryFormat::MessagePack => {
+            rmp_serde::encode::write(&mut out, &Typed(&value)).expect("values always encode")
+        },
+        BinaryFormat::Json => {
+            serde_json::to_writer(&mut out, &serde_json::Value::from(value))
+                .expect("JSON values always serialize");
+        },
+    }
+    out
+}
+
+/// Serializes a [Value] with the serde type of each Convex type, where
+/// going through [serde_json::Value] would turn `Int64`s and `Bytes` into
+/// JSON's special objects.
+struct Typed<'a>(&'a Value);
+
+impl Serialize for Typed<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Null => serializer.serialize_unit(),
+            Value::Int64(n) => serializer.serialize_i64(*n),
+            Value::Float64(n) => serializer.serialize_f64(*n),
+            Value::Boolean(b) => serializer.serialize_bool(*b),
+            Value::String(string) => serializer.serialize_str(string),
+            Value::Bytes(bytes) => serializer.serialize_bytes(bytes),
+            Value::Array(values) => serializer.collect_seq(values.iter().map(Typed)),
+            Value::Object(fields) => {
+                serializer.collect_map(fields.iter().map(|(field, value)| (field, Typed(value))))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use convex::Value;
+    use maplit::btreemap;
+
+    use super::{encode, BinaryFormat};
+    use crate::MockConvexClient;
+
+    fn hex(format: BinaryFormat, value: Value) -> String {
+        encode(format, value)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    #[test]
+    fn test_cbor_matches_the_rfc_examples() {
+        let cbor = |value| hex(BinaryFormat::Cbor, value);
+        assert_eq!(cbor(Value::Int64(0)), "00");
+        assert_eq!(cbor(Value::Int64(24)), "1818");
+        assert_eq!(cbor(Value::Int64(1000)), "1903e8");
+        assert_eq!(cbor(Value::Int64(1000000000000)), "1b000000e8d4a51000");
+        assert_eq!(cbor(Value::Int64(-100)), "3863");
+        assert_eq!(cbor(Value::Int64(i64::MIN)), "3b7fffffffffffffff");
+        assert_eq!(cbor(Value::Float64(1.1)), "fb3ff199999999999a");
+        assert_eq!(cbor(Value::Float64(1.0)), "f93c00");
+        assert_eq!(cbor(Value::Boolean(true)), "f5");
+        assert_eq!(cbor(Value::Null), "f6");
+        assert_eq!(cbor(Value::Bytes(vec![1, 2, 3, 4])), "4401020304");
+        assert_eq!(cbor(Value::String("IETF".into())), "6449455446");
+        assert_eq!(
+            cbor(Value::Array(vec![Value::Int64(1), Value::Int64(2)])),
+            "820102"
+        );
+        assert_eq!(
+            cbor(Value::Object(btreemap! {
+                "a".into() => Value::Int64(1),
+                "b".into() => Value::Array(vec![Value::Int64(2), Value::Int64(3)]),
+            })),
+            "a26161016162820203"
+        );
+    }
+
+    #[test]
+    fn test_message_pack_uses_the_shortest_forms() {
+        let message_pack = |value| hex(BinaryFormat::MessagePack, value);
+        assert_eq!(message_pack(Value::Int64(1)), "01");
+        assert_eq!(message_pack(Value::Int64(-1)), "ff");
+        assert_eq!(message_pack(Value::Int64(200)), "ccc8");
+        assert_eq!(message_pack(Value::Int64(-33)), "d0df");
+        assert_eq!(message_pack(Value::Int64(70000)), "ce00011170");
+        assert_eq!(message_pack(Value::Int64(i64::MIN)), "d38000000000000000");
+        assert_eq!(message_pack(Value::Float64(1.0)), "cb3ff0000000000000");
+        assert_eq!(message_pack(Value::Null), "c0");
+        assert_eq!(message_pack(Value::Bytes(vec![1])), "c40101");
+        assert_eq!(message_pack(Value::String("a".into())), "a161");
+        assert_eq!(
+            message_pack(Value::String("a".repeat(32))),
+            format!("d920{}", "61".repeat(32))
+        );
+        assert_eq!(message_pack(Value::Array(vec![Value::Int64(1)])), "9101");
+        assert_eq!(
+            message_pack(Value::Array(vec![Value::Null; 16])),
+            format!("dc0010{}", "c0".repeat(16))
+        );
+        assert_eq!(
+            message_pack(Value::Object(btreemap! {"a".into() => Value::Int64(1)})),
+            "81a16101"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_bytes_match_the_json_string() {
+        let mock = MockConvexClient::new();
+        mock.set_result("tiles:list".into(), "[{\"x\":1.5,\"id\":\"a\"}]".into())
+            .unwrap();
+        let client = mock.client();
+        let json = client
+            .query("tiles:list".into(), HashMap::new(), None)
+            .await
+            .unwrap();
+        let bytes = client
+            .query_binary(
+                "tiles:list".into(),
+                HashMap::new(),
+                BinaryFormat::Json,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(bytes, json.into_bytes());
+    }
+}
@@ -0,0 +1,106 @@
+//! A persistent cache of query results, stored by the host.
+//!
+//! Subscription results are written through to a [StorageProvider] so that on
+//! the next cold start they can be shown right away, marked as stale, instead
+//! of waiting for the WebSocket to connect.
+use std::{collections::BTreeMap, sync::Arc};
+
+use convex::Value;
+use parking_lot::Mutex;
+use tracing::warn;
+
+use crate::ClientError;
+
+/// Key-value storage implemented by the host, e.g. on top of SharedPreferences
+/// or a database.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait StorageProvider: Send + Sync {
+    async fn get(&self, key: String) -> Result<Option<String>, ClientError>;
+
+    async fn put(&self, key: String, value: String) -> Result<(), ClientError>;
+
+    async fn delete(&self, key: String) -> Result<(), ClientError>;
+}
+
+#[derive(Default)]
+pub(crate) struct QueryCache {
+    storage: Mutex<Option<Arc<dyn StorageProvider>>>,
+}
+
+impl QueryCache {
+    pub(crate) fn set_storage(&self, storage: Option<Arc<dyn StorageProvider>>) {
+        *self.storage.lock() = storage;
+    }
+
+    pub(crate) fn storage(&self) -> Option<Arc<dyn StorageProvider>> {
+        self.storage.lock().clone()
+    }
+
+    /// The key that the results of the query `name` with `args` are stored
+    /// under, if there's anywhere to store them.
+    pub(crate) fn key(&self, name: &str, args: &BTreeMap<String, Value>) -> Option<CacheKey> {
+        let storage = self.storage.lock().clone()?;
+        Some(CacheKey {
+            storage,
+            key: cache_key(name, args),
+        })
+    }
+}
+
+fn cache_key(name: &str, args: &BTreeMap<String, Value>) -> String {
+    format!("convex:query:{name}:{}", crate::args_json(args))
+}
+
+/// Where the results of a single query are stored.
+///
+/// Storage failures are logged rather than failing the subscription, which
+/// works fine without the cache.
+#[derive(Clone)]
+pub(crate) struct CacheKey {
+    storage: Arc<dyn StorageProvider>,
+    key: String,
+}
+
+impl CacheKey {
+    pub(crate) async fn get(&self) -> Option<String> {
+        self.storage
+            .get(self.key.clone())
+            .await
+            .inspect_err(|e| warn!("Failed to read cached {}: {e}", self.key))
+            .ok()
+            .flatten()
+    }
+
+    pub(crate) async fn put(&self, value: String) {
+        if let Err(e) = self.storage.put(self.key.clone(), value).await {
+            warn!("Failed to cache {}: {e}", self.key);
+        }
+    }
+
+    pub(crate) async fn delete(&self) {
+        if let Err(e) = self.storage.delete(self.key.clone()).await {
+            warn!("Failed to delete cached {}: {e}", self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use convex::Value;
+    use maplit::btreemap;
+
+    use super::cache_key;
+
+    #[test]
+    fn test_cache_key_includes_name_and_args() {
+        let args = btreemap! {
+            String::from("b") => Value::Float64(1.0),
+            String::from("a") => Value::String(String::from("x")),
+        };
+        assert_eq!(
+            cache_key("messages:list", &args),
+            r#"convex:query:messages:list:{"a":"x","b":1.0}"#
+        );
+    }
+}
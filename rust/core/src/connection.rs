@@ -0,0 +1,682 @@
+//! Ownership of the [ConvexClient] used to talk to the deployment.
+//!
+//! The active client gets replaced when failing over to a fallback deployment
+//! URL. Anything that outlives a single call, like subscriptions and auth,
+//! watches for replacements and re-applies itself to the new client.
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, SystemTime},
+};
+
+use convex::{AuthTokenFetcher, AuthenticationToken, ConvexClient, ConvexClientBuilder};
+use parking_lot::Mutex;
+use reqwest::Url;
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, watch},
+    time::Instant,
+};
+use tracing::{info, warn};
+
+use crate::{
+    auth::{self, token_claims, AuthTracker},
+    events::Origin,
+    latest::LatestSender,
+    AuthTokenProvider, ClientError,
+};
+
+/// Gets told which deployment URL is active after the client fails over.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait DeploymentFailoverListener: Send + Sync {
+    async fn on_active_deployment_changed(&self, deployment_url: String) -> ();
+}
+
+/// The state of the connection to the deployment, as seen by the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum ConnectionState {
+    /// No connection has been attempted yet; the client connects lazily on the
+    /// first call that hits the backend.
+    Disconnected,
+    /// Connecting for the first time.
+    Connecting,
+    Connected,
+    /// The connection was lost and the client is trying to get it back,
+    /// possibly on a fallback deployment. A connection that silently dropped
+    /// is noticed once the backend hasn't sent anything for 30 seconds, or
+    /// right away with [crate::MobileConvexClient::reconnect].
+    Reconnecting,
+    /// The client gave up after
+    /// [crate::ClientOptions::max_connection_attempts] failed in a row. No
+    /// more attempts are made and calls fail until
+    /// [crate::MobileConvexClient::reconnect].
+    Failed,
+    /// Disconnected by [crate::MobileConvexClient::pause].
+    Paused,
+}
+
+/// The state of the WebSocket of the current client, as the underlying
+/// client reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum WebSocketState {
+    Connected,
+    Connecting,
+}
+
+impl From<convex::WebSocketState> for WebSocketState {
+    fn from(state: convex::WebSocketState) -> Self {
+        match state {
+            convex::WebSocketState::Connected => WebSocketState::Connected,
+            convex::WebSocketState::Connecting => WebSocketState::Connecting,
+        }
+    }
+}
+
+impl ConnectionState {
+    /// The state after the WebSocket of the current client reports `state`.
+    fn next(self, state: &WebSocketState) -> Self {
+        match (self, state) {
+            (ConnectionState::Failed | ConnectionState::Paused, _) => self,
+            (_, WebSocketState::Connected) => ConnectionState::Connected,
+            (ConnectionState::Disconnected | ConnectionState::Connecting, _) => {
+                ConnectionState::Connecting
+            },
+            (ConnectionState::Connected | ConnectionState::Reconnecting, _) => {
+                ConnectionState::Reconnecting
+            },
+        }
+    }
+}
+
+/// Receives transitions of the [ConnectionState].
+///
+/// Like [crate::WebSocketStateSubscriber], only the latest state is delivered
+/// once the host is ready for it.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait ConnectionStateListener: Send + Sync {
+    async fn on_connection_state_change(&self, state: ConnectionState) -> ();
+}
+
+/// The auth that gets re-applied to each new [ConvexClient].
+#[derive(Clone)]
+pub(crate) enum Auth {
+    None,
+    Token(String),
+    Provider(Arc<dyn AuthTokenProvider>),
+    /// A deploy key, which authenticates as an admin of the deployment.
+    Admin(String),
+}
+
+impl Auth {
+    async fn apply(&self, client: &mut ConvexClient, refresh: &AuthRefresh) {
+        match self {
+            Auth::None => {
+                refresh.tracker.logged_out();
+                client.set_auth(None).await
+            },
+            Auth::Token(token) => {
+                refresh.tracker.loading(token);
+                client.set_auth(Some(token.clone())).await
+            },
+            Auth::Provider(provider) => {
+                refresh.tracker.fetching();
+                client
+                    .set_auth_callback(Some(token_fetcher(provider.clone(), refresh.clone())))
+                    .await
+            },
+            Auth::Admin(deploy_key) => {
+                refresh.tracker.loading(deploy_key);
+                client.set_admin_auth(deploy_key.clone(), None).await
+            },
+        }
+    }
+}
+
+/// How long before a token from an [AuthTokenProvider] expires that a fresh
+/// one gets fetched.
+const REFRESH_BEFORE_EXPIRY: Duration = Duration::from_secs(60);
+
+/// What it takes to refresh tokens from an [AuthTokenProvider] before they
+/// expire, which the underlying client doesn't do on its own.
+#[derive(Clone)]
+struct AuthRefresh {
+    /// Makes the next fetch force a refresh.
+    force: Arc<AtomicBool>,
+    /// When the last fetched token needs to be refreshed.
+    deadline: Arc<watch::Sender<Option<Instant>>>,
+    tracker: Arc<AuthTracker>,
+}
+
+fn token_fetcher(provider: Arc<dyn AuthTokenProvider>, refresh: AuthRefresh) -> AuthTokenFetcher {
+    Box::new(move |force_refresh: bool| {
+        let provider = provider.clone();
+        let refresh = refresh.clone();
+        Box::pin(async move {
+            let force_refresh = refresh.force.swap(false, Ordering::SeqCst) || force_refresh;
+            match provider.fetch_token(force_refresh).await {
+                Ok(Some(token)) => {
+                    refresh.deadline.send_replace(refresh_deadline(&token));
+                    refresh.tracker.loading(&token);
+                    Ok(AuthenticationToken::User(token))
+                },
+                Ok(None) => {
+                    refresh.deadline.send_replace(None);
+                    refresh.tracker.logged_out();
+                    Ok(AuthenticationToken::None)
+                },
+                Err(e) => {
+                    refresh.tracker.rejected(e.to_string());
+                    Err(anyhow::anyhow!("{e}"))
+                },
+            }
+        })
+    })
+}
+
+/// When to refresh `token`, if it's a JWT with an expiry that isn't already
+/// too close to refresh ahead of.
+fn refresh_deadline(token: &str) -> Option<Instant> {
+    let until_refresh = token_expiry(token)?
+        .duration_since(SystemTime::now())
+        .ok()?
+        .checked_sub(REFRESH_BEFORE_EXPIRY)?;
+    Some(Instant::now() + until_refresh)
+}
+
+/// The `exp` claim of a JWT.
+pub(crate) fn token_expiry(token: &str) -> Option<SystemTime> {
+    let exp = token_claims(token)?.get("exp")?.as_f64()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs_f64(exp.max(0.0)))
+}
+
+struct Failover {
+    fallback_urls: Vec<String>,
+    unreachable_after: Duration,
+    listener: Option<Arc<dyn DeploymentFailoverListener>>,
+}
+
+pub(crate) struct Connection {
+    primary_url: String,
+    client_id: String,
+    rt: Handle,
+    failover: Mutex<Option<Failover>>,
+    /// The active URL; 0 is the primary and the rest index into the fallbacks.
+    active: Mutex<usize>,
+    /// Held while auth is applied so a client that's replacing the current one
+    /// can't miss an update.
+    auth: tokio::sync::Mutex<Auth>,
+    auth_refresh: AuthRefresh,
+    /// Bumped whenever the host sets a new auth.
+    auth_changes: watch::Sender<()>,
+    current: watch::Sender<Option<ConvexClient>>,
+    state_sender: mpsc::Sender<convex::WebSocketState>,
+    state: watch::Sender<ConnectionState>,
+    /// How often a connection that was up has been lost.
+    reconnects: AtomicU64,
+    max_attempts: Option<u32>,
+    require_encrypted: bool,
+}
+
+impl Connection {
+    /// Creates a new [Connection] and the receiver of the state changes of all
+    /// of the clients it creates, to be passed to [monitor].
+    pub(crate) fn new(
+        primary_url: String,
+        client_id: String,
+        rt: Handle,
+        origin: Option<Origin>,
+        max_attempts: Option<u32>,
+        require_encrypted: bool,
+    ) -> (Arc<Self>, mpsc::Receiver<convex::WebSocketState>) {
+        let (state_sender, states) = mpsc::channel(16);
+        let (deadline, deadlines) = watch::channel(None);
+        let connection = Arc::new(Connection {
+            primary_url,
+            client_id,
+            rt: rt.clone(),
+            failover: Mutex::new(None),
+            active: Mutex::new(0),
+            auth: tokio::sync::Mutex::new(Auth::None),
+            auth_refresh: AuthRefresh {
+                force: Arc::new(AtomicBool::new(false)),
+                deadline: Arc::new(deadline),
+                tracker: AuthTracker::new(origin),
+            },
+            auth_changes: watch::Sender::new(()),
+            current: watch::Sender::new(None),
+            state_sender,
+            state: watch::Sender::new(ConnectionState::Disconnected),
+            reconnects: AtomicU64::new(0),
+            max_attempts,
+            require_encrypted,
+        });
+        rt.spawn(refresh_auth(Arc::downgrade(&connection), deadlines));
+        (connection, states)
+    }
+
+    /// The client currently in use, if any has been connected yet.
+    pub(crate) fn current(&self) -> Option<ConvexClient> {
+        self.current.borrow().clone()
+    }
+
+    /// A receiver that sees every client that replaces the current one.
+    pub(crate) fn watch(&self) -> watch::Receiver<Option<ConvexClient>> {
+        self.current.subscribe()
+    }
+
+    /// A receiver of the [ConnectionState], which starts out with the current
+    /// one.
+    pub(crate) fn watch_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    /// A receiver of the [auth::AuthState], which starts out with the current
+    /// one.
+    pub(crate) fn watch_auth_state(&self) -> watch::Receiver<auth::AuthState> {
+        self.auth_refresh.tracker.watch()
+    }
+
+    /// A receiver that's told each time the host sets a new auth.
+    pub(crate) fn watch_auth_changes(&self) -> watch::Receiver<()> {
+        self.auth_changes.subscribe()
+    }
+
+    /// A receiver of the latest rejection of the auth, if any.
+    pub(crate) fn watch_auth_rejection(&self) -> watch::Receiver<Option<String>> {
+        self.auth_refresh.tracker.watch_rejection()
+    }
+
+    /// How many times the client had to reconnect after being connected.
+    pub(crate) fn reconnects(&self) -> u64 {
+        self.reconnects.load(Ordering::Relaxed)
+    }
+
+    /// The deployment URL that the current client talks to.
+    pub(crate) fn active_url(&self) -> String {
+        let active = *self.active.lock();
+        if active == 0 {
+            return self.primary_url.clone();
+        }
+        self.failover
+            .lock()
+            .as_ref()
+            .and_then(|failover| failover.fallback_urls.get(active - 1).cloned())
+            .unwrap_or_else(|| self.primary_url.clone())
+    }
+
+    pub(crate) fn set_failover(
+        &self,
+        fallback_urls: Vec<String>,
+        unreachable_after: Duration,
+        listener: Option<Arc<dyn DeploymentFailoverListener>>,
+    ) {
+        *self.failover.lock() = Some(Failover {
+            fallback_urls,
+            unreachable_after,
+            listener,
+        });
+    }
+
+    /// Creates a client for the active URL and makes it the current one.
+    pub(crate) async fn connect(&self) -> anyhow::Result<ConvexClient> {
+        let url = self.active_url();
+        if self.require_encrypted {
+            check_encrypted(&url)?;
+        }
+        let builder = ConvexClientBuilder::new(&url)
+            .with_client_id(&self.client_id)
+            .with_on_state_change(self.state_sender.clone());
+        let mut client = self.rt.spawn(builder.build()).await??;
+        self.rt.spawn(auth::watch_transitions(
+            Arc::downgrade(&self.auth_refresh.tracker),
+            client.watch_all(),
+        ));
+
+        let auth = self.auth.lock().await;
+        if !matches!(*auth, Auth::None) {
+            auth.apply(&mut client, &self.auth_refresh).await;
+        }
+        self.current.send_replace(Some(client.clone()));
+        Ok(client)
+    }
+
+    /// Drops the current client, which disconnects it once the subscriptions
+    /// holding on to it have been cancelled.
+    pub(crate) fn close(&self) {
+        self.current.send_replace(None);
+        self.state.send_replace(ConnectionState::Disconnected);
+    }
+
+    /// Replaces the current client with a new one for the active URL, which
+    /// tries to connect right away instead of waiting out the backoff of the
+    /// current one. This also starts over after giving up.
+    pub(crate) async fn reconnect(&self) -> anyhow::Result<()> {
+        info!("Reconnecting to {}", self.active_url());
+        self.connect().await?;
+        Ok(())
+    }
+
+    /// Drops the current client as if its WebSocket had been cut off, going
+    /// through [ConnectionState::Reconnecting] to a new one that the
+    /// subscriptions carry over to.
+    #[cfg(feature = "fault-injection")]
+    pub(crate) async fn drop_connection(&self) -> anyhow::Result<()> {
+        warn!(
+            "Dropping the connection to {} on purpose",
+            self.active_url()
+        );
+        self.state.send_if_modified(|state| {
+            let connected = *state == ConnectionState::Connected;
+            if connected {
+                self.reconnects.fetch_add(1, Ordering::Relaxed);
+            }
+            std::mem::replace(state, ConnectionState::Reconnecting) != ConnectionState::Reconnecting
+        });
+        self.connect().await?;
+        Ok(())
+    }
+
+    /// Leaves the [ConnectionState::Failed] or [ConnectionState::Paused]
+    /// state, to try connecting again.
+    pub(crate) fn start_over(&self) {
+        self.state.send_if_modified(|state| {
+            let stopped = matches!(state, ConnectionState::Failed | ConnectionState::Paused);
+            if stopped {
+                *state = ConnectionState::Reconnecting;
+            }
+            stopped
+        });
+    }
+
+    /// Drops the current client like [Connection::give_up], but to be resumed
+    /// later.
+    pub(crate) fn pause(&self) {
+        info!("Pausing the connection");
+        self.current.send_replace(None);
+        self.state.send_replace(ConnectionState::Paused);
+    }
+
+    /// Stops trying to connect by dropping the current client, which stops
+    /// retrying once the calls and subscriptions using it are gone.
+    fn give_up(&self) {
+        warn!("Giving up on connecting after too many failed attempts");
+        self.current.send_replace(None);
+        self.state.send_replace(ConnectionState::Failed);
+    }
+
+    /// Sets the auth for the current client and any that replace it.
+    pub(crate) async fn set_auth(&self, auth: Auth) {
+        let mut current_auth = self.auth.lock().await;
+        self.auth_refresh.tracker.auth_set();
+        self.auth_changes.send_replace(());
+        if !matches!(auth, Auth::Provider(_)) {
+            self.auth_refresh.deadline.send_replace(None);
+        }
+        if let Some(mut client) = self.current() {
+            auth.apply(&mut client, &self.auth_refresh).await;
+        }
+        *current_auth = auth;
+    }
+
+    /// The token that the current auth sends to the backend, if any.
+    pub(crate) async fn auth_token(&self) -> Result<Option<String>, crate::ClientError> {
+        let auth = self.auth.lock().await.clone();
+        match auth {
+            Auth::None => Ok(None),
+            Auth::Token(token) => Ok(Some(token)),
+            Auth::Provider(provider) => provider.fetch_token(false).await,
+            // HTTP actions take user identities, which a deploy key isn't.
+            Auth::Admin(_) => Ok(None),
+        }
+    }
+
+    /// Fetches a fresh token from the [AuthTokenProvider], if there is one.
+    async fn force_auth_refresh(&self) {
+        let auth = self.auth.lock().await;
+        if let (Auth::Provider(_), Some(mut client)) = (&*auth, self.current()) {
+            info!("Refreshing auth token before it expires");
+            self.auth_refresh.force.store(true, Ordering::SeqCst);
+            auth.apply(&mut client, &self.auth_refresh).await;
+        }
+    }
+
+    fn unreachable_after(&self) -> Option<Duration> {
+        self.failover
+            .lock()
+            .as_ref()
+            .filter(|failover| !failover.fallback_urls.is_empty())
+            .map(|failover| failover.unreachable_after)
+    }
+
+    /// Switches to the next URL, wrapping around to the primary after the last
+    /// fallback.
+    async fn fail_over(&self) {
+        let listener = {
+            let failover = self.failover.lock();
+            let Some(failover) = failover.as_ref() else {
+                return;
+            };
+            let mut active = self.active.lock();
+            *active = (*active + 1) % (failover.fallback_urls.len() + 1);
+            failover.listener.clone()
+        };
+        let url = self.active_url();
+        info!("Deployment unreachable, failing over to {url}");
+        match self.connect().await {
+            Ok(_) => {
+                if let Some(listener) = listener {
+                    self.rt.spawn(async move {
+                        listener.on_active_deployment_changed(url).await;
+                    });
+                }
+            },
+            Err(e) => warn!("Failed to connect to {url}: {e}"),
+        }
+    }
+}
+
+/// Refreshes tokens from an [AuthTokenProvider] as they're about to expire.
+async fn refresh_auth(
+    connection: Weak<Connection>,
+    mut deadlines: watch::Receiver<Option<Instant>>,
+) {
+    loop {
+        let deadline = *deadlines.borrow_and_update();
+        if let Some(deadline) = deadline {
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {
+                    let Some(connection) = connection.upgrade() else {
+                        break;
+                    };
+                    connection.force_auth_refresh().await;
+                },
+                changed = deadlines.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    continue;
+                },
+            }
+        }
+        if deadlines.changed().await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Watches the state changes of every client of `connection`, forwarding them
+/// to the host and failing over once the active deployment has been
+/// unreachable for too long.
+pub(crate) async fn monitor(
+    connection: Weak<Connection>,
+    mut states: mpsc::Receiver<convex::WebSocketState>,
+    host: Option<LatestSender<WebSocketState>>,
+) {
+    let mut connecting_since: Option<Instant> = None;
+    // Every WebSocket attempt starts by reporting that it's connecting.
+    let mut attempts = 0;
+    loop {
+        let deadline = connecting_since.and_then(|since| {
+            let unreachable_after = connection.upgrade()?.unreachable_after()?;
+            Some(since + unreachable_after)
+        });
+        let state = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, states.recv()).await {
+                Ok(state) => state,
+                Err(_) => {
+                    let Some(connection) = connection.upgrade() else {
+                        break;
+                    };
+                    connection.fail_over().await;
+                    connecting_since = Some(Instant::now());
+                    continue;
+                },
+            },
+            None => states.recv().await,
+        };
+        let Some(state) = state.map(WebSocketState::from) else {
+            break;
+        };
+        let Some(connection) = connection.upgrade() else {
+            break;
+        };
+        if matches!(
+            *connection.state.borrow(),
+            ConnectionState::Failed | ConnectionState::Paused
+        ) {
+            // These come from the client that was dropped.
+            connecting_since = None;
+            attempts = 0;
+            continue;
+        }
+        match state {
+            WebSocketState::Connected => {
+                connecting_since = None;
+                attempts = 0;
+            },
+            WebSocketState::Connecting => {
+                if connection
+                    .max_attempts
+                    .is_some_and(|max_attempts| attempts >= max_attempts.max(1))
+                {
+                    connecting_since = None;
+                    attempts = 0;
+                    connection.give_up();
+                    continue;
+                }
+                attempts += 1;
+                connecting_since.get_or_insert_with(Instant::now);
+            },
+        }
+        connection.state.send_if_modified(|current| {
+            let next = current.next(&state);
+            if *current == ConnectionState::Connected && next == ConnectionState::Reconnecting {
+                connection.reconnects.fetch_add(1, Ordering::Relaxed);
+            }
+            std::mem::replace(current, next) != next
+        });
+        drop(connection);
+        if let Some(host) = &host {
+            host.send(state);
+        }
+    }
+}
+
+/// Fails for a `url` that isn't encrypted, unless it's on the device itself.
+fn check_encrypted(url: &str) -> Result<(), ClientError> {
+    let invalid = |reason: String| ClientError::InvalidArgument {
+        key: "deployment_url".into(),
+        reason,
+    };
+    let parsed = Url::parse(url).map_err(|e| invalid(e.to_string()))?;
+    if matches!(parsed.scheme(), "https" | "wss") {
+        return Ok(());
+    }
+    let loopback = match parsed.host_str() {
+        Some("localhost") => true,
+        Some(host) => host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback()),
+        None => false,
+    };
+    if loopback {
+        return Ok(());
+    }
+    Err(invalid(format!(
+        "{url} isn't encrypted, unset ClientOptions::require_encrypted_connections to connect to it"
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use base64::Engine;
+
+    use super::{check_encrypted, token_expiry, ConnectionState, WebSocketState};
+
+    #[test]
+    fn test_only_local_urls_may_be_unencrypted() {
+        assert!(check_encrypted("https://cool-music-123.convex.cloud").is_ok());
+        assert!(check_encrypted("http://127.0.0.1:3210").is_ok());
+        assert!(check_encrypted("ws://[::1]:3210").is_ok());
+        assert!(check_encrypted("ws://localhost:3210").is_ok());
+        assert!(check_encrypted("ws://192.168.1.20:3210").is_err());
+        assert!(check_encrypted("http://10.0.2.2:3210").is_err());
+    }
+
+    #[test]
+    fn test_token_expiry_is_read_from_jwt() {
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(r#"{"sub":"me","exp":1700000000}"#);
+        let token = format!("header.{payload}.signature");
+        assert_eq!(
+            token_expiry(&token),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+        assert_eq!(token_expiry("not a jwt"), None);
+    }
+
+    #[test]
+    fn test_connecting_after_connected_is_reconnecting() {
+        let mut state = ConnectionState::Disconnected;
+        let mut seen = vec![];
+        for ws in [
+            WebSocketState::Connecting,
+            WebSocketState::Connected,
+            WebSocketState::Connecting,
+            WebSocketState::Connecting,
+            WebSocketState::Connected,
+        ] {
+            state = state.next(&ws);
+            seen.push(state);
+        }
+        assert_eq!(
+            seen,
+            vec![
+                ConnectionState::Connecting,
+                ConnectionState::Connected,
+                ConnectionState::Reconnecting,
+                ConnectionState::Reconnecting,
+                ConnectionState::Connected,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_and_paused_ignore_the_web_socket() {
+        for state in [ConnectionState::Failed, ConnectionState::Paused] {
+            assert_eq!(state.next(&WebSocketState::Connecting), state);
+            assert_eq!(state.next(&WebSocketState::Connected), state);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Sign-in with Convex Auth (`@convex-dev/auth`).
+//!
+//! Convex Auth hands out short-lived JWTs along with a refresh token that is
+//! rotated on every use. The `auth:signIn` action is called over HTTP rather
+//! than through the WebSocket client, as refreshing happens inside that
+//! client's token fetcher, which it awaits before handling anything else.
+//!
+//! The refresh token, and the PKCE verifier of an OAuth sign-in waiting for
+//! the redirect back, are kept in the host's [TokenStore] as one JSON object.
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::Url;
+use serde_json::{json, Map, Value};
+use tracing::{debug, warn};
+
+use crate::{connection::token_expiry, http, AuthTokenProvider, ClientError, TokenStore};
+
+/// An access token that's valid for at least this much longer is reused on
+/// reconnects instead of spending the refresh token.
+const REUSE_FOR_AT_LEAST: Duration = Duration::from_secs(120);
+
+/// How a [crate::MobileConvexClient::convex_auth_sign_in] call turned out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum ConvexAuthSignIn {
+    /// The client is now authenticated as the signed in user.
+    SignedIn,
+    /// An OAuth sign-in that continues in the browser at `url`. Once it
+    /// redirects back to the app, sign in again with the same provider and
+    /// the `code` from the redirect in the params.
+    Redirect { url: String },
+    /// A sign-in that continues out of band, like a magic link or a one-time
+    /// code sent by email.
+    Started,
+}
+
+/// The Convex Auth session of a client, which provides its access tokens.
+pub(crate) struct ConvexAuth {
+    deployment_url: String,
+    store: Arc<dyn TokenStore>,
+    token: tokio::sync::Mutex<Option<String>>,
+}
+
+/// What's kept in the [TokenStore].
+#[derive(Default)]
+struct Material {
+    refresh_token: Option<String>,
+    verifier: Option<String>,
+}
+
+impl ConvexAuth {
+    pub(crate) fn new(deployment_url: String, store: Arc<dyn TokenStore>) -> Arc<Self> {
+        Arc::new(ConvexAuth {
+            deployment_url,
+            store,
+            token: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    async fn load(&self) -> Result<Material, ClientError> {
+        let Some(stored) = self.store.load().await? else {
+            return Ok(Material::default());
+        };
+        // Anything unreadable is as good as nothing stored.
+        let stored: Value = serde_json::from_str(&stored).unwrap_or_default();
+        let field = |name: &str| stored.get(name).and_then(Value::as_str).map(String::from);
+        Ok(Material {
+            refresh_token: field("refreshToken"),
+            verifier: field("verifier"),
+        })
+    }
+
+    async fn save(&self, material: Material) -> Result<(), ClientError> {
+        if material.refresh_token.is_none() && material.verifier.is_none() {
+            return self.store.clear().await;
+        }
+        let stored = json!({
+            "refreshToken": material.refresh_token,
+            "verifier": material.verifier,
+        });
+        self.store.save(stored.to_string()).await
+    }
+
+    /// Calls `auth:signIn` with the `provider` and `params`, adding the
+    /// verifier of a pending OAuth sign-in when completing it.
+    pub(crate) async fn sign_in(
+        &self,
+        provider: String,
+        params: Map<String, Value>,
+    ) -> Result<ConvexAuthSignIn, ClientError> {
+        let completes_oauth = params.contains_key("code");
+        let mut args = json!({ "provider": provider, "params": params });
+        if completes_oauth {
+            let mut material = self.load().await?;
+            if let Some(verifier) = material.verifier.take() {
+                args["verifier"] = verifier.into();
+                self.save(material).await?;
+            }
+        }
+        let result = self.call_sign_in(args).await?;
+        if let Some(url) = result.get("redirect").and_then(Value::as_str) {
+            let verifier = result.get("verifier").and_then(Value::as_str);
+            if let Some(verifier) = verifier {
+                let mut material = self.load().await?;
+                material.verifier = Some(verifier.into());
+                self.save(material).await?;
+            }
+            return Ok(ConvexAuthSignIn::Redirect { url: url.into() });
+        }
+        if self.store_tokens(&result).await? {
+            return Ok(ConvexAuthSignIn::SignedIn);
+        }
+        if result.get("started").and_then(Value::as_bool) == Some(true) {
+            return Ok(ConvexAuthSignIn::Started);
+        }
+        Err(ClientError::AuthError {
+            msg: "Convex Auth didn't sign in".into(),
+        })
+    }
+
+    /// Trades the stored refresh token for fresh tokens, returning whether
+    /// there was a session to restore.
+    pub(crate) async fn refresh(&self) -> Result<bool, ClientError> {
+        let Some(refresh_token) = self.load().await?.refresh_token else {
+            return Ok(false);
+        };
+        let result = self
+            .call_sign_in(json!({ "refreshToken": refresh_token }))
+            .await?;
+        if self.store_tokens(&result).await? {
+            return Ok(true);
+        }
+        // The refresh token expired or was revoked, so the session is over.
+        debug!("Convex Auth session ended");
+        self.clear().await?;
+        Ok(false)
+    }
+
+    /// Forgets the session, locally only.
+    pub(crate) async fn clear(&self) -> Result<(), ClientError> {
+        *self.token.lock().await = None;
+        self.store.clear().await
+    }
+
+    /// Keeps the `tokens` of a `signIn` result, if it has any.
+    async fn store_tokens(&self, result: &Value) -> Result<bool, ClientError> {
+        let tokens = result.get("tokens");
+        let token = tokens.and_then(|tokens| tokens.get("token")?.as_str());
+        let refresh_token = tokens.and_then(|tokens| tokens.get("refreshToken")?.as_str());
+        let (Some(token), Some(refresh_token)) = (token, refresh_token) else {
+            return Ok(false);
+        };
+        self.save(Material {
+            refresh_token: Some(refresh_token.into()),
+            verifier: None,
+        })
+        .await?;
+        *self.token.lock().await = Some(token.into());
+        Ok(true)
+    }
+
+    async fn call_sign_in(&self, args: Value) -> Result<Value, ClientError> {
+        let url = action_url(&self.deployment_url)?;
+        let client = http::client_builder()?
+            .build()
+            .map_err(anyhow::Error::from)?;
+        let response = client
+            .post(url)
+            .json(&json!({ "path": "auth:signIn", "args": args, "format": "json" }))
+            .send()
+            .await
+            .map_err(http::network_error)?;
+        if !response.status().is_success() {
+            return Err(http::status_error(response).await);
+        }
+        let body: Value = response.json().await.map_err(http::network_error)?;
+        match body.get("status").and_then(Value::as_str) {
+            Some("success") => Ok(body.get("value").cloned().unwrap_or(Value::Null)),
+            _ => Err(ClientError::AuthError {
+                msg: body
+                    .get("errorMessage")
+                    .and_then(Value::as_str)
+                    .unwrap_or("Convex Auth sign-in failed")
+                    .into(),
+            }),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthTokenProvider for ConvexAuth {
+    async fn fetch_token(&self, force_refresh: bool) -> Result<Option<String>, ClientError> {
+        let token = self.token.lock().await.clone();
+        let fresh_enough = token
+            .as_deref()
+            .and_then(token_expiry)
+            .is_some_and(|expiry| {
+                expiry
+                    .duration_since(SystemTime::now())
+                    .is_ok_and(|left| left > REUSE_FOR_AT_LEAST)
+            });
+        if token.is_some() && (!force_refresh || fresh_enough) {
+            return Ok(token);
+        }
+        match self.refresh().await {
+            Ok(_) => Ok(self.token.lock().await.clone()),
+            Err(e) => {
+                warn!("Failed to refresh the Convex Auth session: {e}");
+                Err(e)
+            },
+        }
+    }
+}
+
+/// The URL of the HTTP API for running actions on the deployment.
+fn action_url(deployment_url: &str) -> Result<Url, ClientError> {
+    let mut url = Url::parse(deployment_url).map_err(anyhow::Error::from)?;
+    let scheme = match url.scheme() {
+        "ws" => "http",
+        "wss" => "https",
+        scheme => scheme,
+    }
+    .to_owned();
+    url.set_scheme(&scheme)
+        .map_err(|_| anyhow::anyhow!("Can't set the scheme of {deployment_url}"))?;
+    Ok(url.join("api/action").map_err(anyhow::Error::from)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::action_url;
+
+    #[test]
+    fn test_action_url_is_on_the_deployment() {
+        assert_eq!(
+            action_url("https://happy-otter-123.convex.cloud")
+                .unwrap()
+                .as_str(),
+            "https://happy-otter-123.convex.cloud/api/action"
+        );
+        assert_eq!(
+            action_url("ws://127.0.0.1:3210").unwrap().as_str(),
+            "http://127.0.0.1:3210/api/action"
+        );
+    }
+}
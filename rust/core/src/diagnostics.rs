@@ -0,0 +1,167 @@
+//! Detection of networks that block Convex even though the device reports
+//! connectivity, like captive portals and TLS-intercepting proxies.
+use std::{error::Error, time::Duration};
+
+use reqwest::{header::LOCATION, redirect::Policy, Response, Url};
+use tracing::debug;
+
+use crate::http;
+
+/// How long each probe request may take before the deployment is considered
+/// unreachable.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The outcome of probing the network path to a Convex deployment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum NetworkDiagnosis {
+    /// The deployment answered over a trusted connection.
+    Reachable,
+    /// Requests are being answered by something other than the deployment,
+    /// typically a Wi-Fi sign-in page. `redirect_url` is where the portal
+    /// tried to send us, if it said.
+    CaptivePortal { redirect_url: Option<String> },
+    /// The deployment presented a certificate that isn't trusted, which
+    /// usually means a proxy is intercepting TLS traffic.
+    TlsInterception { reason: String },
+    /// The deployment couldn't be reached at all.
+    Unreachable { reason: String },
+}
+
+/// Probes the deployment at `deployment_url` and classifies what's in the way,
+/// if anything.
+pub(crate) async fn diagnose(deployment_url: &str) -> anyhow::Result<NetworkDiagnosis> {
+    let deployment_url = Url::parse(deployment_url)?;
+    let client = http::client_builder()?
+        .redirect(Policy::none())
+        .timeout(PROBE_TIMEOUT)
+        .build()?;
+
+    let diagnosis = match client.get(deployment_url.join("version")?).send().await {
+        Ok(response) => match redirect_target(&response) {
+            Some(target) if leaves_host(&deployment_url, target.as_deref()) => {
+                NetworkDiagnosis::CaptivePortal {
+                    redirect_url: target,
+                }
+            },
+            _ => NetworkDiagnosis::Reachable,
+        },
+        Err(e) if is_certificate_error(&e) => {
+            // Portals that hijack HTTPS usually hijack plain HTTP too, and
+            // that's the one they answer with a redirect to their sign-in page.
+            match probe_plain_http(&client, &deployment_url).await {
+                Some(redirect_url) => NetworkDiagnosis::CaptivePortal { redirect_url },
+                None => NetworkDiagnosis::TlsInterception {
+                    reason: error_chain(&e),
+                },
+            }
+        },
+        Err(e) => NetworkDiagnosis::Unreachable {
+            reason: error_chain(&e),
+        },
+    };
+    debug!("Network diagnosis for {deployment_url}: {diagnosis:?}");
+    Ok(diagnosis)
+}
+
+/// Requests the deployment over plain HTTP and returns the redirect target if
+/// something other than the deployment answered.
+async fn probe_plain_http(
+    client: &reqwest::Client,
+    deployment_url: &Url,
+) -> Option<Option<String>> {
+    let mut url = deployment_url.join("version").ok()?;
+    url.set_scheme("http").ok()?;
+    let response = client.get(url).send().await.ok()?;
+    match redirect_target(&response) {
+        // The deployment itself only ever redirects to its HTTPS URL.
+        Some(target) if leaves_host(deployment_url, target.as_deref()) => Some(target),
+        Some(_) => None,
+        None => Some(None),
+    }
+}
+
+/// Returns `Some` for redirect responses, containing the `Location` header
+/// when present.
+fn redirect_target(response: &Response) -> Option<Option<String>> {
+    if !response.status().is_redirection() {
+        return None;
+    }
+    Some(
+        response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .map(str::to_owned),
+    )
+}
+
+fn leaves_host(deployment_url: &Url, target: Option<&str>) -> bool {
+    let Some(target) = target else {
+        return true;
+    };
+    match deployment_url.join(target) {
+        Ok(target) => target.host_str() != deployment_url.host_str(),
+        Err(_) => true,
+    }
+}
+
+fn is_certificate_error(error: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if let Some(rustls::Error::InvalidCertificate(_)) = error.downcast_ref::<rustls::Error>() {
+            return true;
+        }
+        // io::Error::source skips over the error it wraps, so check that too.
+        if let Some(inner) = error
+            .downcast_ref::<std::io::Error>()
+            .and_then(|e| e.get_ref())
+        {
+            if is_certificate_error(inner) {
+                return true;
+            }
+        }
+        source = error.source();
+    }
+    false
+}
+
+fn error_chain(error: &(dyn Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(": ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+    use rustls::CertificateError;
+
+    use super::{is_certificate_error, leaves_host};
+
+    #[test]
+    fn test_certificate_errors_are_found_inside_io_errors() {
+        let error = std::io::Error::other(rustls::Error::InvalidCertificate(
+            CertificateError::UnknownIssuer,
+        ));
+        assert!(is_certificate_error(&error));
+        assert!(!is_certificate_error(&std::io::Error::other("refused")));
+    }
+
+    #[test]
+    fn test_redirects_to_other_hosts_leave_the_deployment() {
+        let url = Url::parse("https://cool-music-123.convex.cloud").unwrap();
+        assert!(!leaves_host(
+            &url,
+            Some("https://cool-music-123.convex.cloud/version")
+        ));
+        assert!(!leaves_host(&url, Some("/version")));
+        assert!(leaves_host(&url, Some("http://login.hotel-wifi.example/")));
+        assert!(leaves_host(&url, None));
+    }
+}
@@ -0,0 +1,167 @@
+//! Delivery of subscription results as RFC 6902 JSON Patches.
+//!
+//! With [crate::SubscriptionOptions::json_patch], each result after the first
+//! is sent to the host as the patch from the previous one, which is much
+//! smaller than the whole result for big lists that change a row at a time.
+use serde_json::{json, Map, Value};
+
+/// Turns each result of a subscription into what gets sent to the host:
+/// `{"snapshot": <result>}` or `{"patch": [<operations>]}`.
+pub(crate) struct Differ {
+    last: Option<Value>,
+    snapshot_interval: u32,
+    since_snapshot: u32,
+}
+
+impl Differ {
+    pub(crate) fn new(snapshot_interval: u32) -> Self {
+        Differ {
+            last: None,
+            snapshot_interval,
+            since_snapshot: 0,
+        }
+    }
+
+    pub(crate) fn next(&mut self, value: Value) -> String {
+        let due = self.snapshot_interval > 0 && self.since_snapshot >= self.snapshot_interval;
+        let patch = match &self.last {
+            Some(last) if !due => {
+                let mut patch = Vec::new();
+                diff(last, &value, &mut String::new(), &mut patch);
+                Some(json!({ "patch": patch }).to_string())
+            },
+            _ => None,
+        };
+        let snapshot = || json!({ "snapshot": &value }).to_string();
+        let update = match patch {
+            // A patch that rewrites most of the result isn't worth applying.
+            Some(patch) if patch.len() < value.to_string().len() => {
+                self.since_snapshot += 1;
+                patch
+            },
+            _ => {
+                self.since_snapshot = 0;
+                snapshot()
+            },
+        };
+        self.last = Some(value);
+        update
+    }
+
+    /// Makes the next result a snapshot, for when the host may have lost
+    /// track of the previous one.
+    pub(crate) fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Appends the operations that turn `old` into `new`, both found at `path`.
+fn diff(old: &Value, new: &Value, path: &mut String, patch: &mut Vec<Value>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => diff_objects(old, new, path, patch),
+        (Value::Array(old), Value::Array(new)) => diff_arrays(old, new, path, patch),
+        _ if old == new => {},
+        _ => patch.push(json!({ "op": "replace", "path": path, "value": new })),
+    }
+}
+
+fn diff_objects(
+    old: &Map<String, Value>,
+    new: &Map<String, Value>,
+    path: &mut String,
+    patch: &mut Vec<Value>,
+) {
+    for (key, old_value) in old {
+        let len = path.len();
+        push_token(path, key);
+        match new.get(key) {
+            Some(new_value) => diff(old_value, new_value, path, patch),
+            None => patch.push(json!({ "op": "remove", "path": path })),
+        }
+        path.truncate(len);
+    }
+    for (key, new_value) in new {
+        if !old.contains_key(key) {
+            let len = path.len();
+            push_token(path, key);
+            patch.push(json!({ "op": "add", "path": path, "value": new_value }));
+            path.truncate(len);
+        }
+    }
+}
+
+/// Diffs the elements between the common prefix and suffix of the arrays
+/// pairwise, so a row inserted or removed anywhere is a single operation.
+fn diff_arrays(old: &[Value], new: &[Value], path: &mut String, patch: &mut Vec<Value>) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+    let common = old.len().min(new.len());
+    let len = path.len();
+    for (i, (old, new)) in old.iter().zip(new).enumerate() {
+        push_token(path, &(prefix + i).to_string());
+        diff(old, new, path, patch);
+        path.truncate(len);
+    }
+    push_token(path, &(prefix + common).to_string());
+    for _ in common..old.len() {
+        patch.push(json!({ "op": "remove", "path": path }));
+    }
+    for (i, value) in new[common..].iter().enumerate() {
+        path.truncate(len);
+        push_token(path, &(prefix + common + i).to_string());
+        patch.push(json!({ "op": "add", "path": path, "value": value }));
+    }
+    path.truncate(len);
+}
+
+/// Appends a JSON Pointer reference token, escaped as in RFC 6901.
+fn push_token(path: &mut String, token: &str) {
+    path.push('/');
+    path.push_str(&token.replace('~', "~0").replace('/', "~1"));
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::Differ;
+
+    #[test]
+    fn test_results_after_the_first_are_patches() {
+        let rows = |rows: Vec<serde_json::Value>| json!({ "rows": rows, "title": "Inbox" });
+        let row = |id: u32, read: bool| json!({ "id": id, "read": read, "body": "x".repeat(100) });
+        let mut differ = Differ::new(2);
+        let first = rows(vec![row(1, false), row(2, false)]);
+        assert_eq!(
+            differ.next(first.clone()),
+            json!({ "snapshot": first }).to_string()
+        );
+
+        let second = rows(vec![row(0, false), row(1, false), row(2, false)]);
+        assert_eq!(
+            differ.next(second),
+            json!({ "patch": [{ "op": "add", "path": "/rows/0", "value": row(0, false) }] })
+                .to_string()
+        );
+
+        let third = rows(vec![row(0, false), row(1, true), row(2, false)]);
+        assert_eq!(
+            differ.next(third),
+            json!({ "patch": [{ "op": "replace", "path": "/rows/1/read", "value": true }] })
+                .to_string()
+        );
+
+        let fourth = rows(vec![row(0, false), row(2, false)]);
+        assert_eq!(
+            differ.next(fourth.clone()),
+            json!({ "snapshot": fourth }).to_string()
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! Routing of events that `convex-rs` only reports through `tracing`.
+//!
+//! The underlying client logs things like deprecation notices from the backend
+//! instead of returning them, so [events_layer] watches for those events and
+//! hands them to the [MobileConvexClient](crate::MobileConvexClient) that
+//! logged them. The layer has to be part of the global subscriber, which the
+//! FFI crate's logging initialization takes care of.
+//!
+//! `convex-rs` doesn't log in a span of its own, so events are attributed by
+//! the thread they're logged on: each client marks the threads of the runtime
+//! it owns with its [Origin]. Events logged anywhere else, like on the shared
+//! runtime, go to the clients without one.
+use std::{
+    cell::Cell,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+};
+
+use parking_lot::Mutex;
+use tokio::runtime::Handle;
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, Layer};
+
+/// The kinds of [ServerNotice].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum ServerNoticeKind {
+    /// The backend reported that this client or its protocol version is
+    /// deprecated, sent when the WebSocket connects.
+    Deprecation,
+    /// The backend hit an error processing the sync protocol and the client is
+    /// reconnecting to recover.
+    ProtocolError,
+}
+
+/// A non-fatal notice from the Convex backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct ServerNotice {
+    pub kind: ServerNoticeKind,
+    pub message: String,
+}
+
+/// Receives [ServerNotice]s for a client.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait ServerNoticeListener: Send + Sync {
+    async fn on_notice(&self, notice: ServerNotice) -> ();
+}
+
+/// Receives the `console` log lines of backend functions, which dev
+/// deployments send along with function results.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait FunctionLogListener: Send + Sync {
+    async fn on_log_line(&self, line: String) -> ();
+}
+
+/// The `tracing` target that `convex-rs` logs function log lines to.
+const FUNCTION_LOGS_TARGET: &str = "convex_logs";
+
+/// An event that `convex-rs` reported through `tracing`.
+#[derive(Debug, Clone)]
+pub(crate) enum ConvexEvent {
+    Notice(ServerNotice),
+    /// The backend rejected the client's auth.
+    AuthError(String),
+    /// A log line of a backend function.
+    FunctionLog(String),
+}
+
+/// Something that wants to know about [ConvexEvent]s.
+pub(crate) trait EventSink: Send + Sync {
+    fn on_event(&self, event: &ConvexEvent);
+}
+
+/// The client whose runtime threads an event was logged on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Origin(u64);
+
+thread_local! {
+    static ORIGIN: Cell<Option<Origin>> = const { Cell::new(None) };
+}
+
+impl Origin {
+    pub(crate) fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Origin(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Marks the current thread as one of the client's, for as long as it
+    /// runs.
+    pub(crate) fn enter(self) {
+        ORIGIN.set(Some(self));
+    }
+}
+
+/// The registered sinks, with the origin of the events they get.
+type Sinks = Vec<(Option<Origin>, Weak<dyn EventSink>)>;
+
+static SINKS: Mutex<Sinks> = Mutex::new(Vec::new());
+
+/// Starts delivering the events logged on the threads of `origin` to `sink`
+/// until it's dropped, or those logged on any other thread if it's [None].
+pub(crate) fn register(origin: Option<Origin>, sink: Weak<dyn EventSink>) {
+    let mut sinks = SINKS.lock();
+    sinks.retain(|(_, sink)| sink.strong_count() > 0);
+    sinks.push((origin, sink));
+}
+
+/// Forwards [ServerNotice]s to the listener of a client, if it has one.
+pub(crate) struct NoticeForwarder {
+    listener: Mutex<Option<Arc<dyn ServerNoticeListener>>>,
+    rt: Handle,
+}
+
+impl NoticeForwarder {
+    pub(crate) fn new(rt: Handle, origin: Option<Origin>) -> Arc<Self> {
+        let forwarder = Arc::new(NoticeForwarder {
+            listener: Mutex::new(None),
+            rt,
+        });
+        register(origin, Arc::downgrade(&forwarder) as Weak<dyn EventSink>);
+        forwarder
+    }
+
+    pub(crate) fn set_listener(&self, listener: Option<Arc<dyn ServerNoticeListener>>) {
+        *self.listener.lock() = listener;
+    }
+}
+
+impl EventSink for NoticeForwarder {
+    fn on_event(&self, event: &ConvexEvent) {
+        let ConvexEvent::Notice(notice) = event else {
+            return;
+        };
+        let Some(listener) = self.listener.lock().clone() else {
+            return;
+        };
+        let notice = notice.clone();
+        self.rt
+            .spawn(async move { listener.on_notice(notice).await });
+    }
+}
+
+/// Forwards function log lines to the listener of a client, if it has one.
+pub(crate) struct FunctionLogForwarder {
+    listener: Mutex<Option<Arc<dyn FunctionLogListener>>>,
+    rt: Handle,
+}
+
+impl FunctionLogForwarder {
+    pub(crate) fn new(rt: Handle, origin: Option<Origin>) -> Arc<Self> {
+        let forwarder = Arc::new(FunctionLogForwarder {
+            listener: Mutex::new(None),
+            rt,
+        });
+        register(origin, Arc::downgrade(&forwarder) as Weak<dyn EventSink>);
+        forwarder
+    }
+
+    pub(crate) fn set_listener(&self, listener: Option<Arc<dyn FunctionLogListener>>) {
+        *self.listener.lock() = listener;
+    }
+}
+
+impl EventSink for FunctionLogForwarder {
+    fn on_event(&self, event: &ConvexEvent) {
+        let ConvexEvent::FunctionLog(line) = event else {
+            return;
+        };
+        let Some(listener) = self.listener.lock().clone() else {
+            return;
+        };
+        let line = line.clone();
+        self.rt
+            .spawn(async move { listener.on_log_line(line).await });
+    }
+}
+
+fn dispatch(event: ConvexEvent) {
+    let origin = ORIGIN.get();
+    let sinks: Vec<Arc<dyn EventSink>> = SINKS
+        .lock()
+        .iter()
+        .filter(|(sink_origin, _)| *sink_origin == origin)
+        .filter_map(|(_, sink)| sink.upgrade())
+        .collect();
+    for sink in sinks {
+        sink.on_event(&event);
+    }
+}
+
+/// A [Layer] that picks out the events that [MobileConvexClient] surfaces to
+/// the host.
+///
+/// Add it to the global `tracing` subscriber.
+///
+/// [MobileConvexClient]: crate::MobileConvexClient
+pub fn events_layer<S: Subscriber>() -> impl Layer<S> {
+    ConvexEventsLayer
+}
+
+struct ConvexEventsLayer;
+
+impl<S: Subscriber> Layer<S> for ConvexEventsLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let function_log = metadata.target() == FUNCTION_LOGS_TARGET;
+        if !function_log && !metadata.target().starts_with("convex::") {
+            return;
+        }
+        let mut message = MessageVisitor::default();
+        event.record(&mut message);
+        if function_log {
+            dispatch(ConvexEvent::FunctionLog(message.0));
+            return;
+        }
+        if let Some(event) = classify(metadata.target(), *metadata.level(), message.0) {
+            dispatch(event);
+        }
+    }
+}
+
+fn classify(target: &str, level: Level, message: String) -> Option<ConvexEvent> {
+    if let Some(error) = message.strip_prefix("AuthError: ") {
+        let error = error.trim_end_matches(" Restarting protocol.");
+        let error = error
+            .rsplit_once(" for identity version ")
+            .map_or(error, |(error, _)| error);
+        return Some(ConvexEvent::AuthError(error.to_owned()));
+    }
+    let kind = if target.ends_with("web_socket_manager") && level == Level::WARN {
+        ServerNoticeKind::Deprecation
+    } else if message.starts_with("FatalError: ") {
+        ServerNoticeKind::ProtocolError
+    } else {
+        return None;
+    };
+    Some(ConvexEvent::Notice(ServerNotice { kind, message }))
+}
+
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = value.to_owned();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Weak};
+
+    use parking_lot::Mutex;
+    use tracing::Level;
+
+    use super::{classify, dispatch, register, ConvexEvent, EventSink, Origin, ServerNoticeKind};
+
+    #[derive(Default)]
+    struct Collector(Mutex<Vec<String>>);
+
+    impl EventSink for Collector {
+        fn on_event(&self, event: &ConvexEvent) {
+            if let ConvexEvent::AuthError(error) = event {
+                self.0.lock().push(error.clone());
+            }
+        }
+    }
+
+    #[test]
+    fn test_events_go_to_the_client_whose_thread_logged_them() {
+        let (first, second) = (Origin::new(), Origin::new());
+        let first_sink = Arc::new(Collector::default());
+        let second_sink = Arc::new(Collector::default());
+        register(
+            Some(first),
+            Arc::downgrade(&first_sink) as Weak<dyn EventSink>,
+        );
+        register(
+            Some(second),
+            Arc::downgrade(&second_sink) as Weak<dyn EventSink>,
+        );
+
+        std::thread::spawn(move || {
+            first.enter();
+            dispatch(ConvexEvent::AuthError("expired".into()));
+        })
+        .join()
+        .unwrap();
+        assert_eq!(*first_sink.0.lock(), vec!["expired".to_owned()]);
+        assert!(second_sink.0.lock().is_empty());
+    }
+
+    #[test]
+    fn test_deprecation_and_fatal_errors_are_notices() {
+        let Some(ConvexEvent::Notice(notice)) = classify(
+            "convex::sync::web_socket_manager",
+            Level::WARN,
+            "Deprecated: upgrade your client".into(),
+        ) else {
+            panic!("expected a notice");
+        };
+        assert_eq!(notice.kind, ServerNoticeKind::Deprecation);
+
+        let Some(ConvexEvent::Notice(notice)) = classify(
+            "convex::base_client",
+            Level::ERROR,
+            "FatalError: oops. Restarting protocol.".into(),
+        ) else {
+            panic!("expected a notice");
+        };
+        assert_eq!(notice.kind, ServerNoticeKind::ProtocolError);
+
+        let Some(ConvexEvent::AuthError(error)) = classify(
+            "convex::base_client",
+            Level::ERROR,
+            "AuthError: Token expired for identity version 3. Restarting protocol.".into(),
+        ) else {
+            panic!("expected an auth error");
+        };
+        assert_eq!(error, "Token expired");
+
+        assert!(classify(
+            "convex::base_client",
+            Level::INFO,
+            "Starting mutation".into()
+        )
+        .is_none());
+    }
+}
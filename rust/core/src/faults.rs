@@ -0,0 +1,101 @@
+//! Faults that QA builds inject into a client to exercise the error handling
+//! of host apps, behind the `fault-injection` feature.
+//!
+//! Faults apply to the calls and updates that come after they're injected,
+//! the way the real ones would: calls fail without reaching the backend,
+//! results arrive late and the WebSocket reconnects.
+use std::{collections::VecDeque, time::Duration};
+
+use parking_lot::Mutex;
+
+use crate::ClientError;
+
+#[derive(Default)]
+pub(crate) struct Faults {
+    delay: Mutex<Duration>,
+    errors: Mutex<VecDeque<ClientError>>,
+}
+
+impl Faults {
+    pub(crate) fn set_delay(&self, delay: Duration) {
+        *self.delay.lock() = delay;
+    }
+
+    pub(crate) fn push_error(&self, error: ClientError) {
+        self.errors.lock().push_back(error);
+    }
+
+    /// The error that the next call fails with, if one was injected.
+    pub(crate) fn take_error(&self) -> Option<ClientError> {
+        self.errors.lock().pop_front()
+    }
+
+    /// Waits for as long as results and updates are delayed.
+    pub(crate) async fn delay(&self) {
+        let delay = *self.delay.lock();
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    };
+
+    use crate::{ClientError, MockConvexClient};
+
+    #[tokio::test]
+    async fn test_injected_errors_fail_the_next_calls_in_order() {
+        let mock = MockConvexClient::new();
+        mock.set_result("messages:send".into(), "null".into())
+            .unwrap();
+        let client = mock.client();
+        client.inject_error(ClientError::NetworkError {
+            msg: "Offline".into(),
+            retryable: true,
+        });
+        client.inject_error(ClientError::ServerError { msg: "Boom".into() });
+
+        assert!(matches!(
+            client
+                .mutation("messages:send".into(), HashMap::new(), None)
+                .await,
+            Err(ClientError::NetworkError {
+                retryable: true,
+                ..
+            })
+        ));
+        assert!(matches!(
+            client
+                .mutation("messages:send".into(), HashMap::new(), None)
+                .await,
+            Err(ClientError::ServerError { .. })
+        ));
+        assert!(client
+            .mutation("messages:send".into(), HashMap::new(), None)
+            .await
+            .is_ok());
+        // The failed calls never reached the backend.
+        assert_eq!(mock.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_delayed_results_arrive_late() {
+        let mock = MockConvexClient::new();
+        mock.set_result("messages:list".into(), "[]".into())
+            .unwrap();
+        let client = mock.client();
+        client.inject_delay(100);
+
+        let started = Instant::now();
+        client
+            .query("messages:list".into(), HashMap::new(), None)
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+}
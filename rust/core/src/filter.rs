@@ -0,0 +1,96 @@
+//! Processing of JSON subscription results on their way to the host, as set
+//! up by [crate::SubscriptionOptions].
+use serde_json::Value;
+
+use crate::{diff::Differ, SubscriptionOptions};
+
+/// Narrows each result down to the selected part and encodes it for the host,
+/// skipping the ones that wouldn't change anything.
+pub(crate) struct JsonFilter {
+    select: Option<String>,
+    distinct: bool,
+    /// The last selected value and whether it was stale.
+    last: Option<(Value, bool)>,
+    differ: Option<Differ>,
+}
+
+impl JsonFilter {
+    /// The filter for `options`, if they need one.
+    pub(crate) fn new(options: &SubscriptionOptions) -> Option<Self> {
+        if options.select.is_none() && !options.distinct && !options.json_patch {
+            return None;
+        }
+        Some(JsonFilter {
+            select: options.select.clone(),
+            // Selecting a part only makes sense for telling when it changes.
+            distinct: options.distinct || options.select.is_some(),
+            last: None,
+            differ: options
+                .json_patch
+                .then(|| Differ::new(options.json_patch_snapshot_interval)),
+        })
+    }
+
+    /// What to deliver to the host for `value`, if anything.
+    pub(crate) fn apply(&mut self, value: Value, is_stale: bool) -> Option<String> {
+        let value = match &self.select {
+            Some(pointer) => value.pointer(pointer).cloned().unwrap_or(Value::Null),
+            None => value,
+        };
+        if self.distinct {
+            if self.last.as_ref() == Some(&(value.clone(), is_stale)) {
+                return None;
+            }
+            self.last = Some((value.clone(), is_stale));
+        }
+        Some(match &mut self.differ {
+            Some(differ) => differ.next(value),
+            None => value.to_string(),
+        })
+    }
+
+    /// Makes the next result get delivered in full, for when the host may
+    /// have lost track of the previous one.
+    pub(crate) fn reset(&mut self) {
+        self.last = None;
+        if let Some(differ) = &mut self.differ {
+            differ.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::JsonFilter;
+    use crate::SubscriptionOptions;
+
+    #[test]
+    fn test_selected_value_is_only_delivered_when_it_changes() {
+        let mut filter = JsonFilter::new(&SubscriptionOptions {
+            select: Some("/items/0/status".into()),
+            ..Default::default()
+        })
+        .unwrap();
+        let result =
+            |status: &str, count: u32| json!({ "items": [{ "status": status }], "count": count });
+        assert_eq!(
+            filter.apply(result("pending", 1), true),
+            Some("\"pending\"".into())
+        );
+        assert_eq!(
+            filter.apply(result("pending", 1), false),
+            Some("\"pending\"".into())
+        );
+        assert_eq!(filter.apply(result("pending", 2), false), None);
+        assert_eq!(
+            filter.apply(result("done", 2), false),
+            Some("\"done\"".into())
+        );
+        assert_eq!(
+            filter.apply(json!({ "items": [] }), false),
+            Some("null".into())
+        );
+    }
+}
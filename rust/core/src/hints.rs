@@ -0,0 +1,62 @@
+//! What the host knows about the device that the client can't find out for
+//! itself, and how the client adapts to it.
+use std::time::Duration;
+
+/// How the device is connected to the network, as reported with
+/// [crate::MobileConvexClient::set_network_type].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum NetworkType {
+    #[default]
+    Unknown,
+    Wifi,
+    Cellular,
+    Ethernet,
+}
+
+/// How long subscription results are debounced for at least on a network that
+/// costs data.
+const METERED_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How long subscription results are debounced for at least in low power
+/// mode.
+const LOW_POWER_DEBOUNCE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DeviceHints {
+    pub(crate) network_type: NetworkType,
+    pub(crate) metered: bool,
+    pub(crate) low_power: bool,
+}
+
+impl DeviceHints {
+    /// Whether data on the current network costs the user, which cellular data
+    /// usually does even when the host doesn't say so.
+    fn saves_data(&self) -> bool {
+        self.metered || self.network_type == NetworkType::Cellular
+    }
+
+    /// The debounce window that subscriptions use at least, on top of their
+    /// [crate::SubscriptionOptions::debounce_ms].
+    pub(crate) fn min_debounce(&self) -> Option<Duration> {
+        let low_power = self.low_power.then_some(LOW_POWER_DEBOUNCE);
+        low_power.max(self.saves_data().then_some(METERED_DEBOUNCE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{DeviceHints, NetworkType};
+
+    #[test]
+    fn test_the_longest_min_debounce_wins() {
+        let mut hints = DeviceHints::default();
+        assert_eq!(hints.min_debounce(), None);
+        hints.network_type = NetworkType::Cellular;
+        assert_eq!(hints.min_debounce(), Some(Duration::from_millis(500)));
+        hints.low_power = true;
+        assert_eq!(hints.min_debounce(), Some(Duration::from_secs(1)));
+    }
+}
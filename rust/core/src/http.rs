@@ -0,0 +1,167 @@
+//! Plain HTTP(S) requests made alongside the WebSocket connection.
+use std::{collections::HashMap, sync::Arc};
+
+use reqwest::{header::AUTHORIZATION, Method, StatusCode, Url};
+use rustls::{crypto::aws_lc_rs, ClientConfig, RootCertStore};
+
+use crate::ClientError;
+
+/// The response to a request to an HTTP action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct HttpActionResponse {
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// A request to an HTTP action, relative to the deployment's site URL.
+pub(crate) struct HttpActionRequest {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) body: Option<Vec<u8>>,
+}
+
+/// The URL that serves the HTTP actions of the deployment at
+/// `deployment_url`: `.convex.site` for cloud deployments and the next port
+/// for local ones.
+pub(crate) fn site_url(deployment_url: &str) -> anyhow::Result<Url> {
+    let mut url = Url::parse(deployment_url)?;
+    // HTTP actions are served over HTTP(S), whichever scheme the WebSocket
+    // URL was given with.
+    let scheme = match url.scheme() {
+        "ws" => Some("http"),
+        "wss" => Some("https"),
+        _ => None,
+    };
+    if let Some(scheme) = scheme {
+        url.set_scheme(scheme)
+            .map_err(|_| anyhow::anyhow!("Can't set the scheme of {deployment_url}"))?;
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("{deployment_url} has no host"))?;
+    if let Some(name) = host.strip_suffix(".convex.cloud") {
+        let site = format!("{name}.convex.site");
+        url.set_host(Some(&site))?;
+    } else if let Some(port) = url.port() {
+        url.set_port(Some(port + 1))
+            .map_err(|_| anyhow::anyhow!("Can't set the port of {deployment_url}"))?;
+    } else {
+        anyhow::bail!("Can't tell the HTTP actions URL of {deployment_url}, set it explicitly");
+    }
+    Ok(url)
+}
+
+/// Sends `request` to the HTTP actions at `site_url`, authenticated with
+/// `token` if there is one.
+pub(crate) async fn http_action(
+    site_url: Url,
+    request: HttpActionRequest,
+    token: Option<String>,
+) -> Result<HttpActionResponse, ClientError> {
+    let method = Method::from_bytes(request.method.as_bytes()).map_err(|e| {
+        ClientError::InvalidArgument {
+            key: "method".into(),
+            reason: e.to_string(),
+        }
+    })?;
+    let url = site_url
+        .join(request.path.trim_start_matches('/'))
+        .map_err(|e| ClientError::InvalidArgument {
+            key: "path".into(),
+            reason: e.to_string(),
+        })?;
+    let client = client_builder()?.build().map_err(anyhow::Error::from)?;
+    let mut builder = client.request(method, url);
+    for (name, value) in request.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(token) = token {
+        builder = builder.header(AUTHORIZATION, format!("Bearer {token}"));
+    }
+    if let Some(body) = request.body {
+        builder = builder.body(body);
+    }
+    let response = builder.send().await.map_err(network_error)?;
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+        .collect();
+    let body = response.bytes().await.map_err(network_error)?.to_vec();
+    Ok(HttpActionResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+/// Returns a [reqwest::ClientBuilder] that trusts the same roots as the
+/// WebSocket connection.
+pub(crate) fn client_builder() -> anyhow::Result<reqwest::ClientBuilder> {
+    Ok(reqwest::Client::builder().use_preconfigured_tls(tls_config()?))
+}
+
+/// The TLS configuration of the client's HTTP requests, for other HTTP
+/// clients in the app that should trust the same roots, like a telemetry
+/// exporter.
+pub fn tls_config() -> anyhow::Result<ClientConfig> {
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    Ok(
+        ClientConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    )
+}
+
+pub(crate) async fn status_error(response: reqwest::Response) -> ClientError {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        ClientError::NetworkError {
+            msg: format!("{status}: {body}"),
+            retryable: true,
+        }
+    } else {
+        ClientError::ServerError {
+            msg: format!("{status}: {body}"),
+        }
+    }
+}
+
+pub(crate) fn network_error(e: reqwest::Error) -> ClientError {
+    ClientError::NetworkError {
+        retryable: e.is_connect() || e.is_timeout() || e.is_request() || e.is_body(),
+        msg: e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::site_url;
+
+    #[test]
+    fn test_site_url_of_cloud_and_local_deployments() {
+        assert_eq!(
+            site_url("https://happy-otter-123.convex.cloud")
+                .unwrap()
+                .as_str(),
+            "https://happy-otter-123.convex.site/"
+        );
+        assert_eq!(
+            site_url("http://127.0.0.1:3210").unwrap().as_str(),
+            "http://127.0.0.1:3211/"
+        );
+        assert_eq!(
+            site_url("ws://192.168.1.20:3210").unwrap().as_str(),
+            "http://192.168.1.20:3211/"
+        );
+        assert!(site_url("https://convex.example.com").is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! A single-slot channel that only keeps the most recent value.
+//!
+//! Used to conflate updates for slow host callbacks: while the host is busy,
+//! newer values overwrite older ones and only the latest gets delivered.
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+struct Shared<T> {
+    value: Mutex<Option<T>>,
+    notify: Notify,
+    closed: AtomicBool,
+}
+
+/// Creates a new latest-value channel.
+pub(crate) fn channel<T>() -> (LatestSender<T>, LatestReceiver<T>) {
+    let shared = Arc::new(Shared {
+        value: Mutex::new(None),
+        notify: Notify::new(),
+        closed: AtomicBool::new(false),
+    });
+    (
+        LatestSender {
+            shared: shared.clone(),
+        },
+        LatestReceiver { shared },
+    )
+}
+
+pub(crate) struct LatestSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> LatestSender<T> {
+    /// Replaces any value that hasn't been received yet with `value`.
+    pub(crate) fn send(&self, value: T) {
+        *self.shared.value.lock() = Some(value);
+        self.shared.notify.notify_one();
+    }
+}
+
+impl<T> Drop for LatestSender<T> {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.notify.notify_one();
+    }
+}
+
+pub(crate) struct LatestReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> LatestReceiver<T> {
+    /// Waits for the next value.
+    ///
+    /// Returns [None] once the sender is dropped and the last value has been
+    /// received.
+    pub(crate) async fn recv(&mut self) -> Option<T> {
+        loop {
+            if let Some(value) = self.shared.value.lock().take() {
+                return Some(value);
+            }
+            if self.shared.closed.load(Ordering::Acquire) {
+                return None;
+            }
+            self.shared.notify.notified().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::channel;
+
+    #[tokio::test]
+    async fn test_only_latest_value_is_received() {
+        let (tx, mut rx) = channel();
+        tx.send(1);
+        tx.send(2);
+        tx.send(3);
+        assert_eq!(rx.recv().await, Some(3));
+        drop(tx);
+        assert_eq!(rx.recv().await, None);
+    }
+}
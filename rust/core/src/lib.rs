@@ -0,0 +1,2796 @@
+//! The platform-agnostic core of the Convex mobile client.
+//!
+//! This crate contains the plain Rust API that the FFI bindings in
+//! `convex-mobile-ffi` expose to Kotlin and Swift. UniFFI is optional: the
+//! `uniffi` feature derives and exports the bindings of the public types, and
+//! without it the crate can be used directly by Rust desktop/server
+//! applications or by other binding layers.
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_once_cell::OnceCell;
+use convex::{ConvexClient, FunctionResult, Value};
+use futures::{
+    channel::oneshot,
+    future::{self, join_all},
+    pin_mut, select_biased, stream, FutureExt, StreamExt,
+};
+use parking_lot::Mutex;
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tracing::{debug, info_span, warn, Instrument};
+
+pub use auth::{AuthErrorListener, AuthState, AuthStateListener, TokenStore};
+pub use batch::{FunctionCall, FunctionCallResult};
+pub use binary::{BinaryFormat, BinaryQuerySubscriber};
+pub use cache::StorageProvider;
+use cache::{CacheKey, QueryCache};
+use connection::{Auth, Connection};
+pub use connection::{
+    ConnectionState, ConnectionStateListener, DeploymentFailoverListener, WebSocketState,
+};
+use convex_auth::ConvexAuth;
+pub use convex_auth::ConvexAuthSignIn;
+pub use diagnostics::NetworkDiagnosis;
+pub use events::{
+    events_layer, FunctionLogListener, ServerNotice, ServerNoticeKind, ServerNoticeListener,
+};
+use events::{FunctionLogForwarder, NoticeForwarder, Origin};
+#[cfg(feature = "fault-injection")]
+use faults::Faults;
+use filter::JsonFilter;
+use hints::DeviceHints;
+pub use hints::NetworkType;
+pub use http::{tls_config, HttpActionResponse};
+pub use logging::{client_log_level, LogLevel};
+use mock::MockBackend;
+pub use mock::MockConvexClient;
+pub use optimistic::OptimisticUpdater;
+use optimistic::{OptimisticQueries, Patch, PatchState};
+pub use options::ClientOptions;
+use options::OwnedRuntime;
+pub use outbox::MutationOutboxListener;
+use outbox::Outbox;
+pub use pagination::{PaginatedQuerySubscriber, PaginatedSubscription, PaginationStatus};
+use pull::StreamSubscriber;
+pub use pull::{SubscriptionStream, SubscriptionUpdate};
+use recording::{Recorder, Source};
+use session::AnonymousSession;
+pub use stats::ClientStats;
+pub use storage::{DownloadSink, TransferProgressListener, UploadSource};
+use subscriptions::{Evicted, Subscriptions};
+pub use subscriptions::{
+    SubscriptionEvictionListener, SubscriptionHandle, SubscriptionInfo, SubscriptionOptions,
+};
+pub use timing::TimedResult;
+pub use values::{ConvexValue, ValueQuerySubscriber};
+use worker::{Call, Worker};
+
+mod auth;
+mod batch;
+mod binary;
+mod cache;
+mod connection;
+mod convex_auth;
+mod diagnostics;
+mod diff;
+mod events;
+#[cfg(feature = "fault-injection")]
+mod faults;
+mod filter;
+mod hints;
+mod http;
+mod latest;
+mod logging;
+mod mock;
+mod optimistic;
+mod options;
+mod outbox;
+mod pagination;
+mod pull;
+mod recording;
+mod session;
+mod stats;
+mod storage;
+mod subscriptions;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod timing;
+mod values;
+mod worker;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!("convexmobile");
+
+/// Exports the methods of an impl that take a `timeout_ms: Option<u64>`, with
+/// it defaulting to `None` in the bindings.
+///
+/// UniFFI reads the attributes of a method before `cfg_attr` is expanded, so
+/// the default can't be set behind the `uniffi` feature on the method itself.
+macro_rules! export_with_timeout {
+    (impl $ty:ty { $($(#[$attr:meta])* pub async fn $name:ident($($args:tt)*) -> $ret:ty $body:block)* }) => {
+        #[cfg(feature = "uniffi")]
+        #[uniffi::export]
+        impl $ty {
+            $(
+                $(#[$attr])*
+                #[uniffi::method(default(timeout_ms = None))]
+                pub async fn $name($($args)*) -> $ret $body
+            )*
+        }
+
+        #[cfg(not(feature = "uniffi"))]
+        impl $ty {
+            $($(#[$attr])* pub async fn $name($($args)*) -> $ret $body)*
+        }
+    };
+}
+
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum ClientError {
+    /// An error that occurs internally here in the mobile Convex client.
+    #[error("InternalError: {msg}")]
+    InternalError { msg: String },
+    /// An application specific error that is thrown in a remote Convex backend
+    /// function.
+    #[error("ConvexError: {data}")]
+    ConvexError { data: String },
+    /// An unexpected server error that is thrown in a remote Convex backend
+    /// function.
+    #[error("ServerError: {msg}")]
+    ServerError { msg: String },
+    /// The Convex backend couldn't be reached. The call may succeed if it's
+    /// `retryable` and tried again once the network is back.
+    #[error("NetworkError: {msg}")]
+    NetworkError { msg: String, retryable: bool },
+    /// Authentication with the Convex backend failed, e.g. because an
+    /// [AuthTokenProvider] couldn't get a token.
+    #[error("AuthError: {msg}")]
+    AuthError { msg: String },
+    /// A function argument passed by the host isn't valid JSON or can't be
+    /// represented as a Convex value.
+    #[error("InvalidArgument: {key}: {reason}")]
+    InvalidArgument { key: String, reason: String },
+    /// A call didn't finish within the timeout it was given.
+    #[error("Timeout: {msg}")]
+    Timeout { msg: String },
+}
+
+impl From<anyhow::Error> for ClientError {
+    fn from(value: anyhow::Error) -> Self {
+        match value.downcast::<ClientError>() {
+            Ok(error) => error,
+            Err(value) => Self::InternalError {
+                msg: value.to_string(),
+            },
+        }
+    }
+}
+
+/// Receives updates for a query subscription.
+///
+/// The next update isn't delivered until the future returned for the previous
+/// one completes, so a slow host applies backpressure instead of blocking
+/// tokio workers. Results that arrive in the meantime are conflated and only
+/// the newest is delivered, unless [SubscriptionOptions::conflate] is off.
+///
+/// Updates with `is_stale` set come from the [StorageProvider] cache and are
+/// delivered before the first result from the backend.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait QuerySubscriber: Send + Sync {
+    async fn on_update(&self, value: String, is_stale: bool) -> ();
+
+    async fn on_error(&self, message: String, value: Option<String>) -> ();
+
+    /// Called once the backend has acknowledged the subscription, right
+    /// before its first result (or error) is delivered. Until then, the
+    /// client may still be connecting.
+    async fn on_subscribed(&self) -> ();
+
+    /// Called when the host sets a new auth, after which the query is run
+    /// again as the new identity. Results delivered before the next one may
+    /// still be for the previous identity.
+    async fn on_resubscribed(&self) -> ();
+}
+
+/// Receives changes to the state of the WebSocket connection.
+///
+/// Like [QuerySubscriber], states are delivered one at a time and a slow host
+/// only receives the latest state once it's ready.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait WebSocketStateSubscriber: Send + Sync {
+    async fn on_state_change(&self, state: WebSocketState) -> ();
+}
+
+/// Fetches ID tokens for the client when it needs them, which is on the
+/// initial connect, on every reconnect (with `force_refresh`) and shortly
+/// before a JWT's `exp` (also with `force_refresh`).
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait AuthTokenProvider: Send + Sync {
+    async fn fetch_token(&self, force_refresh: bool) -> Result<Option<String>, ClientError>;
+}
+
+/// A wrapper around a [ConvexClient] and a [tokio::runtime::Runtime] used to
+/// asynchronously call Convex functions.
+///
+/// That enables easy async communication for mobile clients. They can call the
+/// various methods on [MobileConvexClient] and await results without blocking
+/// their main threads.
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct MobileConvexClient {
+    web_socket_state_subscriber: Option<Arc<dyn WebSocketStateSubscriber>>,
+    connection: Arc<Connection>,
+    worker: Worker,
+    /// Answers the calls that would reach the backend, for a
+    /// [MockConvexClient].
+    mock: Option<Arc<MockBackend>>,
+    #[cfg(feature = "fault-injection")]
+    faults: Arc<Faults>,
+    recorder: Arc<Recorder>,
+    states: Mutex<Option<mpsc::Receiver<convex::WebSocketState>>>,
+    connected: OnceCell<()>,
+    subscriptions: Arc<Subscriptions>,
+    eviction_listener: Mutex<Option<Arc<dyn SubscriptionEvictionListener>>>,
+    session: AnonymousSession,
+    cache: QueryCache,
+    outbox: Arc<Outbox>,
+    optimistic: Arc<OptimisticQueries>,
+    /// Overrides the URL that HTTP actions are served from.
+    http_actions_url: Mutex<Option<String>>,
+    notices: Arc<NoticeForwarder>,
+    function_logs: Arc<FunctionLogForwarder>,
+    connection_state_forwarder: Mutex<Option<JoinHandle<()>>>,
+    auth_state_forwarder: Mutex<Option<JoinHandle<()>>>,
+    auth_error_forwarder: Mutex<Option<JoinHandle<()>>>,
+    /// The Convex Auth session that provides the auth, if it's signed in.
+    convex_auth: Mutex<Option<Arc<ConvexAuth>>>,
+    connect_timeout: Option<Duration>,
+    /// Whether the host said the app is in the background.
+    in_background: watch::Sender<bool>,
+    hints: watch::Sender<DeviceHints>,
+    is_shut_down: AtomicBool,
+    /// The runtime that this client owns, unless it uses the shared one.
+    /// Taken by [MobileConvexClient::shutdown] to shut it down.
+    runtime: Mutex<Option<OwnedRuntime>>,
+    rt: Handle,
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl MobileConvexClient {
+    /// Creates a new [MobileConvexClient].
+    ///
+    /// The internal [ConvexClient] doesn't get created/connected until the
+    /// first public method call that hits the Convex backend.
+    ///
+    /// The `client_id` should be a string representing the name and version of
+    /// the foreign client.
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn new(
+        deployment_url: String,
+        client_id: String,
+        web_socket_state_subscriber: Option<Arc<dyn WebSocketStateSubscriber>>,
+    ) -> MobileConvexClient {
+        Self::with_options(
+            deployment_url,
+            client_id,
+            web_socket_state_subscriber,
+            ClientOptions::default(),
+        )
+    }
+
+    /// Creates a new [MobileConvexClient] tuned with [ClientOptions].
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn with_options(
+        deployment_url: String,
+        client_id: String,
+        web_socket_state_subscriber: Option<Arc<dyn WebSocketStateSubscriber>>,
+        options: ClientOptions,
+    ) -> MobileConvexClient {
+        let origin = Origin::new();
+        let (rt, runtime) = options.runtime(origin).unwrap();
+        let origin = runtime.is_some().then_some(origin);
+        let (connection, states) = Connection::new(
+            deployment_url,
+            client_id,
+            rt.clone(),
+            origin,
+            options.max_connection_attempts,
+            options.require_encrypted_connections,
+        );
+        let outbox = Arc::new(Outbox::default());
+        rt.spawn(outbox::replay(
+            Arc::downgrade(&outbox),
+            Arc::downgrade(&connection),
+            connection.watch_state(),
+        ));
+        MobileConvexClient {
+            web_socket_state_subscriber,
+            worker: Worker::spawn(&rt, connection.watch()),
+            mock: None,
+            #[cfg(feature = "fault-injection")]
+            faults: Arc::new(Faults::default()),
+            recorder: Arc::new(Recorder::default()),
+            connection,
+            states: Mutex::new(Some(states)),
+            connected: OnceCell::new(),
+            subscriptions: Arc::new(Subscriptions::default()),
+            eviction_listener: Mutex::new(None),
+            session: AnonymousSession::default(),
+            cache: QueryCache::default(),
+            outbox,
+            optimistic: Arc::new(OptimisticQueries::default()),
+            http_actions_url: Mutex::new(None),
+            notices: NoticeForwarder::new(rt.clone(), origin),
+            function_logs: FunctionLogForwarder::new(rt.clone(), origin),
+            connection_state_forwarder: Mutex::new(None),
+            auth_state_forwarder: Mutex::new(None),
+            auth_error_forwarder: Mutex::new(None),
+            convex_auth: Mutex::new(None),
+            connect_timeout: options.connect_timeout(),
+            in_background: watch::Sender::new(false),
+            hints: watch::Sender::new(DeviceHints::default()),
+            is_shut_down: AtomicBool::new(false),
+            runtime: Mutex::new(runtime),
+            rt,
+        }
+    }
+
+    /// Like [MobileConvexClient::query], but with [ConvexValue] arguments and
+    /// result instead of JSON strings.
+    pub async fn query_value(
+        &self,
+        name: String,
+        args: HashMap<String, ConvexValue>,
+    ) -> Result<ConvexValue, ClientError> {
+        catch_panic(async {
+            let args = self.value_args(args);
+            let result = self.internal_query(name, args).await?;
+            handle_value_function_result(result)
+        })
+        .await
+    }
+
+    /// Execute several one-shot queries, getting all of their results as of
+    /// the same backend timestamp so that they're consistent with each other.
+    ///
+    /// The results are in the order of `queries`. If any of them fails, so
+    /// does the whole batch.
+    pub async fn query_batch(
+        &self,
+        queries: Vec<FunctionCall>,
+    ) -> Result<Vec<String>, ClientError> {
+        catch_panic(async {
+            let queries = queries
+                .into_iter()
+                .map(|query| Ok((query.name, self.function_args(query.args)?)))
+                .collect::<Result<Vec<_>, ClientError>>()?;
+            let recorded = self.recorder.is_recording().then(|| queries.clone());
+            let results: Vec<FunctionResult> = match &self.mock {
+                Some(mock) => queries
+                    .into_iter()
+                    .map(|(name, args)| mock.call(name, args))
+                    .collect::<anyhow::Result<_>>()?,
+                None => batch::query_batch(&mut self.connected_client().await?, queries).await?,
+            };
+            for ((name, args), result) in recorded.iter().flatten().zip(&results) {
+                self.recorder
+                    .record(Source::Call(Call::Query), name, args, result);
+            }
+            results
+                .into_iter()
+                .map(handle_direct_function_result)
+                .collect()
+        })
+        .await
+    }
+
+    /// Like [MobileConvexClient::query], but also reporting how long the
+    /// query took.
+    pub async fn query_timed(
+        &self,
+        name: String,
+        args: HashMap<String, String>,
+    ) -> Result<TimedResult, ClientError> {
+        self.timed_call(Call::Query, name, args).await
+    }
+
+    /// Subscribe to updates to a query against the Convex backend.
+    ///
+    /// The [QuerySubscriber] will be called back with initial query results and
+    /// it will continue to get called as the underlying data changes.
+    ///
+    /// The returned [SubscriptionHandle] can be used to cancel the
+    /// subscription, which also gets cancelled once the handle is dropped.
+    ///
+    /// Subscriptions to the same query with the same arguments share a single
+    /// query on the backend, which the underlying client reference counts, so
+    /// there's no need for hosts to deduplicate them.
+    pub async fn subscribe(
+        &self,
+        name: String,
+        args: HashMap<String, String>,
+        subscriber: Arc<dyn QuerySubscriber>,
+    ) -> Result<Arc<SubscriptionHandle>, ClientError> {
+        catch_panic(async {
+            let args = self.function_args(args)?;
+            Ok(self
+                .internal_subscribe(
+                    name,
+                    args,
+                    SubscriptionOptions::default(),
+                    Subscriber::Json(subscriber),
+                )
+                .await?)
+        })
+        .await
+    }
+
+    /// Like [MobileConvexClient::subscribe], but with [SubscriptionOptions]
+    /// to tune how updates are delivered.
+    pub async fn subscribe_with_options(
+        &self,
+        name: String,
+        args: HashMap<String, String>,
+        options: SubscriptionOptions,
+        subscriber: Arc<dyn QuerySubscriber>,
+    ) -> Result<Arc<SubscriptionHandle>, ClientError> {
+        catch_panic(async {
+            let args = self.function_args(args)?;
+            let subscriber = match JsonFilter::new(&options) {
+                Some(filter) => Subscriber::Filtered {
+                    subscriber,
+                    filter: Arc::new(Mutex::new(filter)),
+                },
+                None => Subscriber::Json(subscriber),
+            };
+            Ok(self
+                .internal_subscribe(name, args, options, subscriber)
+                .await?)
+        })
+        .await
+    }
+
+    /// Like [MobileConvexClient::subscribe_with_options], but the host pulls
+    /// the updates from the returned [SubscriptionStream] at its own pace
+    /// instead of getting them pushed to a [QuerySubscriber].
+    ///
+    /// Only the latest update that hasn't been pulled yet is kept.
+    pub async fn subscribe_stream(
+        &self,
+        name: String,
+        args: HashMap<String, String>,
+        options: SubscriptionOptions,
+    ) -> Result<Arc<SubscriptionStream>, ClientError> {
+        let (subscriber, updates) = StreamSubscriber::new();
+        let handle = self
+            .subscribe_with_options(name, args, options, subscriber)
+            .await?;
+        Ok(Arc::new(SubscriptionStream::new(handle, updates)))
+    }
+
+    /// Like [MobileConvexClient::subscribe], but with the arguments as one
+    /// JSON object.
+    pub async fn subscribe_json(
+        &self,
+        name: String,
+        args: String,
+        subscriber: Arc<dyn QuerySubscriber>,
+    ) -> Result<Arc<SubscriptionHandle>, ClientError> {
+        catch_panic(async {
+            let args = self.json_object_args(args)?;
+            Ok(self
+                .internal_subscribe(
+                    name,
+                    args,
+                    SubscriptionOptions::default(),
+                    Subscriber::Json(subscriber),
+                )
+                .await?)
+        })
+        .await
+    }
+
+    /// Like [MobileConvexClient::subscribe], but with [ConvexValue] arguments
+    /// and results instead of JSON strings.
+    pub async fn subscribe_value(
+        &self,
+        name: String,
+        args: HashMap<String, ConvexValue>,
+        subscriber: Arc<dyn ValueQuerySubscriber>,
+    ) -> Result<Arc<SubscriptionHandle>, ClientError> {
+        catch_panic(async {
+            let args = self.value_args(args);
+            Ok(self
+                .internal_subscribe(
+                    name,
+                    args,
+                    SubscriptionOptions::default(),
+                    Subscriber::Value(subscriber),
+                )
+                .await?)
+        })
+        .await
+    }
+
+    /// Like [MobileConvexClient::subscribe], but with results encoded in a
+    /// [BinaryFormat] instead of as JSON strings.
+    pub async fn subscribe_binary(
+        &self,
+        name: String,
+        args: HashMap<String, String>,
+        format: BinaryFormat,
+        subscriber: Arc<dyn BinaryQuerySubscriber>,
+    ) -> Result<Arc<SubscriptionHandle>, ClientError> {
+        catch_panic(async {
+            let args = self.function_args(args)?;
+            Ok(self
+                .internal_subscribe(
+                    name,
+                    args,
+                    SubscriptionOptions::default(),
+                    Subscriber::Binary { subscriber, format },
+                )
+                .await?)
+        })
+        .await
+    }
+
+    /// Subscribe to a paginated query, starting with a first page of
+    /// `page_size` items.
+    ///
+    /// The query function must take a `paginationOpts` argument and return a
+    /// pagination result, like one using `.paginate()` does. The
+    /// [PaginatedQuerySubscriber] gets the items of all loaded pages merged
+    /// into one list, and more pages can be loaded with
+    /// [PaginatedSubscription::load_more].
+    pub async fn subscribe_paginated(
+        self: Arc<Self>,
+        name: String,
+        args: HashMap<String, String>,
+        page_size: u32,
+        subscriber: Arc<dyn PaginatedQuerySubscriber>,
+    ) -> Result<Arc<PaginatedSubscription>, ClientError> {
+        catch_panic(async { pagination::subscribe(self, name, args, page_size, subscriber).await })
+            .await
+    }
+
+    /// Set where subscription results get cached across app launches.
+    ///
+    /// With a [StorageProvider], each subscription first delivers the result
+    /// cached from the last time it ran, flagged as stale, and every result
+    /// from the backend is written through to the storage. The cache isn't
+    /// partitioned by user, so clear the storage on logout.
+    ///
+    /// Passing [None] turns off caching.
+    pub fn set_storage_provider(&self, storage: Option<Arc<dyn StorageProvider>>) {
+        self.cache.set_storage(storage);
+    }
+
+    /// Record the results of calls and subscription updates from now on, to
+    /// replay them with [MockConvexClient::replay], e.g. for UI tests or to
+    /// reproduce a bug report from the field.
+    ///
+    /// The recording is written through to `storage` under `key` after each
+    /// new result, as a JSON document that grows with the session. This
+    /// replaces the recording in progress, if any.
+    pub fn start_recording(&self, storage: Arc<dyn StorageProvider>, key: String) {
+        self.recorder.start(&self.rt, storage, key);
+    }
+
+    /// Stops the recording in progress, leaving what was recorded in storage.
+    pub fn stop_recording(&self) {
+        self.recorder.stop();
+    }
+
+    /// Limit the number of concurrent subscriptions.
+    ///
+    /// Once a new subscription would go over `max_subscriptions`, the least
+    /// recently used one (by creation or last delivered update) is cancelled
+    /// and the optional `listener` is told about it. This protects against
+    /// leaks in apps that subscribe per list row and never cancel.
+    ///
+    /// Passing [None] for `max_subscriptions` removes the limit.
+    pub fn set_subscription_limit(
+        &self,
+        max_subscriptions: Option<u32>,
+        listener: Option<Arc<dyn SubscriptionEvictionListener>>,
+    ) {
+        *self.eviction_listener.lock() = listener;
+        let evicted = self
+            .subscriptions
+            .set_limit(max_subscriptions.map(|max| max as usize));
+        self.notify_evicted(evicted);
+    }
+
+    /// Cancel every live subscription at once, e.g. on logout, including the
+    /// pages of paginated subscriptions.
+    pub fn unsubscribe_all(&self) {
+        self.subscriptions.clear();
+    }
+
+    /// List the live subscriptions, oldest first, e.g. for a debug screen or
+    /// to catch screens that never unsubscribe.
+    pub fn active_subscriptions(&self) -> Vec<SubscriptionInfo> {
+        self.subscriptions.list()
+    }
+
+    /// Get the current values of the client's counters, e.g. to graph them on
+    /// a diagnostics screen.
+    pub fn stats(&self) -> ClientStats {
+        ClientStats {
+            reconnects: self.connection.reconnects(),
+            active_subscriptions: self.subscriptions.len() as u32,
+            pending_requests: self.worker.pending() as u32,
+        }
+    }
+
+    /// Run several mutations in order, without waiting for each one to finish
+    /// before sending the next.
+    ///
+    /// The results are in the order of `mutations`. A mutation that fails
+    /// doesn't stop the ones after it from running.
+    pub async fn mutation_batch(
+        &self,
+        mutations: Vec<FunctionCall>,
+    ) -> Result<Vec<FunctionCallResult>, ClientError> {
+        catch_panic(async {
+            let mutations = mutations
+                .into_iter()
+                .map(|mutation| Ok((mutation.name, self.function_args(mutation.args)?)))
+                .collect::<Result<Vec<_>, ClientError>>()?;
+            self.ensure_connected().await?;
+            let results: Vec<_> = mutations
+                .into_iter()
+                .map(|(name, args)| self.post(Call::Mutation, name, args))
+                .collect();
+            Ok(join_all(results)
+                .await
+                .into_iter()
+                .map(FunctionCallResult::from)
+                .collect())
+        })
+        .await
+    }
+
+    /// Like [MobileConvexClient::mutation], but with [ConvexValue] arguments
+    /// and result instead of JSON strings.
+    pub async fn mutation_value(
+        &self,
+        name: String,
+        args: HashMap<String, ConvexValue>,
+    ) -> Result<ConvexValue, ClientError> {
+        catch_panic(async {
+            debug!("Running mutation: {}", name);
+            let args = self.value_args(args);
+            let result = self.internal_mutation(name, args).await?;
+            handle_value_function_result(result)
+        })
+        .await
+    }
+
+    /// Run a mutation against the Convex backend, optimistically updating live
+    /// subscriptions until it completes.
+    ///
+    /// Before the mutation is sent, `updater` is asked for the optimistic
+    /// result of each live subscription, given what it currently shows,
+    /// including the optimistic results of other mutations still in flight.
+    /// Subscribers get those right away, as if they came from the backend,
+    /// and backend results are held back until the mutation completes. Then
+    /// the subscriptions go back to the backend results, which include the
+    /// effects of the mutation if it succeeded, or roll back the optimistic
+    /// results if it failed.
+    pub async fn mutation_with_optimistic_update(
+        &self,
+        name: String,
+        args: HashMap<String, String>,
+        updater: Arc<dyn OptimisticUpdater>,
+    ) -> Result<String, ClientError> {
+        catch_panic(async {
+            let args = self.function_args(args)?;
+            let mut patched = vec![];
+            for query in self.optimistic.snapshot() {
+                if let Some(value) = updater
+                    .update_query(query.name, query.args, query.current)
+                    .await
+                {
+                    self.optimistic.send(query.id, Patch::Apply(value));
+                    patched.push(query.id);
+                }
+            }
+            debug!(
+                "Running mutation: {} with {} optimistic updates",
+                name,
+                patched.len()
+            );
+            let result = self.internal_mutation(name, args).await;
+            for id in patched {
+                self.optimistic.send(id, Patch::Release);
+            }
+            handle_direct_function_result(result?)
+        })
+        .await
+    }
+
+    /// Like [MobileConvexClient::mutation], but also reporting how long the
+    /// mutation took.
+    pub async fn mutation_timed(
+        &self,
+        name: String,
+        args: HashMap<String, String>,
+    ) -> Result<TimedResult, ClientError> {
+        debug!("Running mutation: {}", name);
+        self.timed_call(Call::Mutation, name, args).await
+    }
+
+    /// Turn on the mutation outbox, which persists mutations queued with
+    /// [MobileConvexClient::enqueue_mutation] in the [StorageProvider] and
+    /// runs them in order whenever the client is connected.
+    ///
+    /// Mutations queued before the app was last stopped are loaded and
+    /// replayed. The `listener` is told the result of each one. Requires a
+    /// [StorageProvider] to have been set.
+    pub async fn enable_mutation_outbox(
+        &self,
+        listener: Arc<dyn MutationOutboxListener>,
+    ) -> Result<(), ClientError> {
+        catch_panic(async {
+            let storage = self
+                .cache
+                .storage()
+                .ok_or_else(|| ClientError::InternalError {
+                    msg: "The mutation outbox needs a StorageProvider".into(),
+                })?;
+            self.outbox.enable(storage, listener).await?;
+            if let Err(e) = self.ensure_connected().await {
+                warn!("Queued mutations will run once connected: {e}");
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Queue a mutation in the outbox to run once connected, returning the ID
+    /// that the [MutationOutboxListener] gets its result under.
+    ///
+    /// The mutation is persisted before this returns, so it's run even if the
+    /// app is stopped before it gets back online. Mutations may run more than
+    /// once if the app is stopped while waiting for their results.
+    pub async fn enqueue_mutation(
+        &self,
+        name: String,
+        args: HashMap<String, String>,
+    ) -> Result<String, ClientError> {
+        catch_panic(async {
+            let args = self.function_args(args)?;
+            let id = self.outbox.enqueue(name, args).await?;
+            if let Err(e) = self.ensure_connected().await {
+                warn!("Queued mutation {id} will run once connected: {e}");
+            }
+            Ok(id)
+        })
+        .await
+    }
+
+    /// Like [MobileConvexClient::action], but with [ConvexValue] arguments and
+    /// result instead of JSON strings.
+    pub async fn action_value(
+        &self,
+        name: String,
+        args: HashMap<String, ConvexValue>,
+    ) -> Result<ConvexValue, ClientError> {
+        catch_panic(async {
+            debug!("Running action: {}", name);
+            let args = self.value_args(args);
+            let result = self.internal_action(name, args).await?;
+            handle_value_function_result(result)
+        })
+        .await
+    }
+
+    /// Like [MobileConvexClient::action], but also reporting how long the
+    /// action took.
+    pub async fn action_timed(
+        &self,
+        name: String,
+        args: HashMap<String, String>,
+    ) -> Result<TimedResult, ClientError> {
+        debug!("Running action: {}", name);
+        self.timed_call(Call::Action, name, args).await
+    }
+
+    /// Call an HTTP action of the deployment, attaching the current auth token
+    /// as a bearer token.
+    ///
+    /// The `path` is relative to the deployment's `.convex.site` URL, or to
+    /// the URL set with [MobileConvexClient::set_http_actions_url].
+    pub async fn call_http_action(
+        &self,
+        method: String,
+        path: String,
+        headers: HashMap<String, String>,
+        body: Option<Vec<u8>>,
+    ) -> Result<HttpActionResponse, ClientError> {
+        catch_panic(async {
+            let site_url = match self.http_actions_url.lock().clone() {
+                Some(url) => reqwest::Url::parse(&url).map_err(anyhow::Error::from)?,
+                None => http::site_url(&self.connection.active_url())?,
+            };
+            let token = self.connection.auth_token().await?;
+            let request = http::HttpActionRequest {
+                method,
+                path,
+                headers,
+                body,
+            };
+            self.rt
+                .spawn(http::http_action(site_url, request, token))
+                .await
+                .map_err(anyhow::Error::from)?
+        })
+        .await
+    }
+
+    /// Set the URL that HTTP actions are served from, for deployments with a
+    /// custom domain. Passing [None] goes back to deriving it from the
+    /// deployment URL.
+    pub fn set_http_actions_url(&self, url: Option<String>) {
+        *self.http_actions_url.lock() = url;
+    }
+
+    /// Probe the network path to the Convex deployment.
+    ///
+    /// Useful when the client is stuck in [WebSocketState::Connecting], to tell
+    /// a captive portal or TLS-intercepting proxy apart from a plain lack of
+    /// connectivity so the app can show the user what to do about it.
+    pub async fn diagnose_network(&self) -> Result<NetworkDiagnosis, ClientError> {
+        catch_panic(async {
+            let url = self.connection.active_url();
+            Ok(self
+                .rt
+                .spawn(async move { diagnostics::diagnose(&url).await })
+                .await
+                .map_err(anyhow::Error::from)??)
+        })
+        .await
+    }
+
+    /// Upload a file to Convex file storage, returning its storage ID.
+    ///
+    /// The `upload_url` comes from calling `ctx.storage.generateUploadUrl()`
+    /// in a mutation. The optional `progress` listener is told how many bytes
+    /// have been sent as the upload goes. Transient network failures are
+    /// retried a few times before giving up.
+    pub async fn upload_file(
+        &self,
+        upload_url: String,
+        source: UploadSource,
+        content_type: String,
+        progress: Option<Arc<dyn TransferProgressListener>>,
+    ) -> Result<String, ClientError> {
+        catch_panic(async {
+            self.rt
+                .spawn(async move {
+                    storage::upload(&upload_url, source, &content_type, progress).await
+                })
+                .await
+                .map_err(anyhow::Error::from)?
+        })
+        .await
+    }
+
+    /// Download a file from Convex file storage to `path`, returning its size.
+    ///
+    /// The `url` comes from `ctx.storage.getUrl()`. If `path` already holds
+    /// part of the file, from an earlier download that was interrupted, the
+    /// download resumes after it instead of starting over, as long as the
+    /// file on the server hasn't changed since. The version of the file it's
+    /// part of is kept next to it in `<path>.validator` until the download
+    /// completes. Connection drops during the download are resumed the same
+    /// way a few times before giving up.
+    pub async fn download_file(
+        &self,
+        url: String,
+        path: String,
+        progress: Option<Arc<dyn TransferProgressListener>>,
+    ) -> Result<u64, ClientError> {
+        catch_panic(async {
+            self.rt
+                .spawn(async move {
+                    let (destination, offset, validator) = storage::Destination::file(path).await?;
+                    storage::download(&url, destination, offset, validator, progress).await
+                })
+                .await
+                .map_err(anyhow::Error::from)?
+        })
+        .await
+    }
+
+    /// Download a file from Convex file storage into `sink`, returning its
+    /// size.
+    ///
+    /// Pass the number of bytes that the sink already got from an earlier,
+    /// interrupted download as `offset` to resume it. Those bytes can't be
+    /// checked against the file on the server, so only resume downloads of
+    /// files that don't change.
+    pub async fn download_to_sink(
+        &self,
+        url: String,
+        sink: Arc<dyn DownloadSink>,
+        offset: u64,
+        progress: Option<Arc<dyn TransferProgressListener>>,
+    ) -> Result<u64, ClientError> {
+        catch_panic(async {
+            self.rt
+                .spawn(async move {
+                    storage::download(
+                        &url,
+                        storage::Destination::Sink(sink),
+                        offset,
+                        None,
+                        progress,
+                    )
+                    .await
+                })
+                .await
+                .map_err(anyhow::Error::from)?
+        })
+        .await
+    }
+
+    /// Provide an OpenID Connect ID token to be associated with this client.
+    ///
+    /// Doing so will share that information with the Convex backend and a valid
+    /// token will give the backend knowledge of a logged in user.
+    ///
+    /// Passing [None] for the token will disassociate a previous token,
+    /// effectively returning to a logged out state.
+    pub async fn set_auth(&self, token: Option<String>) -> Result<(), ClientError> {
+        catch_panic(async { Ok(self.internal_set_auth(token).await?) }).await
+    }
+
+    /// Set an auth token fetcher callback.
+    ///
+    /// The callback is invoked immediately, again on every websocket
+    /// reconnect and a minute before the returned token expires, allowing
+    /// dynamic token refresh.
+    ///
+    /// Passing [None] clears the callback and logs out.
+    pub async fn set_auth_callback(
+        &self,
+        provider: Option<Arc<dyn AuthTokenProvider>>,
+    ) -> Result<(), ClientError> {
+        catch_panic(async { Ok(self.internal_set_auth_callback(provider).await?) }).await
+    }
+
+    /// Sign in with a Convex Auth (`@convex-dev/auth`) `provider`, like
+    /// `"password"` or `"github"`, passing it JSON encoded `params`.
+    ///
+    /// Once signed in, the client keeps itself authenticated: the refresh
+    /// token is kept in the [TokenStore] and traded for fresh access tokens
+    /// as they expire, so [MobileConvexClient::convex_auth_restore] can pick
+    /// the session back up after a restart.
+    pub async fn convex_auth_sign_in(
+        &self,
+        provider: String,
+        params: HashMap<String, String>,
+        store: Arc<dyn TokenStore>,
+    ) -> Result<ConvexAuthSignIn, ClientError> {
+        catch_panic(async {
+            let params = parse_json_args(params)?
+                .into_iter()
+                .map(|(k, v)| (k, serde_json::Value::from(v)))
+                .collect();
+            let auth = ConvexAuth::new(self.connection.active_url(), store);
+            let outcome = auth.sign_in(provider, params).await?;
+            if outcome == ConvexAuthSignIn::SignedIn {
+                self.use_convex_auth(auth).await?;
+            }
+            Ok(outcome)
+        })
+        .await
+    }
+
+    /// Pick up the Convex Auth session kept in the [TokenStore] by an earlier
+    /// [MobileConvexClient::convex_auth_sign_in], returning whether there was
+    /// one that's still valid.
+    pub async fn convex_auth_restore(
+        &self,
+        store: Arc<dyn TokenStore>,
+    ) -> Result<bool, ClientError> {
+        catch_panic(async {
+            let auth = ConvexAuth::new(self.connection.active_url(), store);
+            if !auth.refresh().await? {
+                return Ok(false);
+            }
+            self.use_convex_auth(auth).await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Sign out of the Convex Auth session, on the backend and in the
+    /// [TokenStore], and log the client out.
+    pub async fn convex_auth_sign_out(&self) -> Result<(), ClientError> {
+        catch_panic(async {
+            let auth = self.convex_auth.lock().take();
+            if let Some(auth) = auth {
+                // Signing out locally is what matters, so a backend that can't
+                // be reached doesn't stop it.
+                let signed_out = self
+                    .internal_action("auth:signOut".into(), BTreeMap::new())
+                    .await
+                    .map_err(ClientError::from)
+                    .and_then(handle_direct_function_result);
+                if let Err(e) = signed_out {
+                    warn!("Failed to sign out of Convex Auth on the backend: {e}");
+                }
+                auth.clear().await?;
+            }
+            Ok(self.internal_set_auth(None).await?)
+        })
+        .await
+    }
+
+    /// Authenticate as an admin of the deployment with a `deploy_key`, for
+    /// internal tools and test harnesses against dev deployments.
+    ///
+    /// Deploy keys grant full access to the deployment, so never ship one in
+    /// an app. Calling [MobileConvexClient::set_auth] or
+    /// [MobileConvexClient::set_auth_callback] replaces it.
+    pub async fn set_admin_auth(&self, deploy_key: String) -> Result<(), ClientError> {
+        catch_panic(async {
+            self.ensure_connected().await?;
+            self.connection.set_auth(Auth::Admin(deploy_key)).await;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Configure deployment URLs to fail over to when the active one is
+    /// unreachable.
+    ///
+    /// Once the client has been trying to connect for `unreachable_after_ms`,
+    /// it switches to the next URL in `fallback_urls`, wrapping back around
+    /// to the primary URL after the last one. Subscriptions and auth carry
+    /// over to the new deployment and the optional `listener` is told which
+    /// URL is now active.
+    pub fn set_fallback_urls(
+        &self,
+        fallback_urls: Vec<String>,
+        unreachable_after_ms: u64,
+        listener: Option<Arc<dyn DeploymentFailoverListener>>,
+    ) {
+        self.connection.set_failover(
+            fallback_urls,
+            Duration::from_millis(unreachable_after_ms),
+            listener,
+        );
+    }
+
+    /// Start an anonymous session identified by a client-generated ID.
+    ///
+    /// Until [MobileConvexClient::end_anonymous_session] is called, the session
+    /// ID is passed as the `sessionId` argument of every query, subscription,
+    /// mutation and action (unless the caller passes one explicitly), so
+    /// backend functions can keep per-device data for users who haven't signed
+    /// up. Keep using the same session after [MobileConvexClient::set_auth] to
+    /// let the backend link that data to the real user.
+    ///
+    /// Pass the ID returned by a previous session to resume it, or [None] to
+    /// generate a new one. The returned ID should be persisted by the host.
+    pub fn start_anonymous_session(&self, existing_session_id: Option<String>) -> String {
+        self.session.start(existing_session_id)
+    }
+
+    /// Stop passing the anonymous session ID to functions.
+    pub fn end_anonymous_session(&self) {
+        self.session.end()
+    }
+
+    /// The ID of the current anonymous session, if one was started.
+    pub fn anonymous_session_id(&self) -> Option<String> {
+        self.session.session_id()
+    }
+
+    /// Set a listener for transitions of the [ConnectionState], e.g. to show an
+    /// offline banner while [ConnectionState::Reconnecting].
+    ///
+    /// The listener is told about the current state right away. Passing [None]
+    /// removes the listener.
+    pub fn set_connection_state_listener(
+        &self,
+        listener: Option<Arc<dyn ConnectionStateListener>>,
+    ) {
+        let forwarder = listener.map(|listener| {
+            spawn_watch_forwarder(&self.rt, self.connection.watch_state(), move |state| {
+                let listener = listener.clone();
+                async move { listener.on_connection_state_change(state).await }
+            })
+        });
+        replace_forwarder(&self.connection_state_forwarder, forwarder);
+    }
+
+    /// Set a listener for changes to the [AuthState], to know when the backend
+    /// has actually accepted the auth passed to
+    /// [MobileConvexClient::set_auth] or
+    /// [MobileConvexClient::set_auth_callback].
+    ///
+    /// The listener is told about the current state right away. Passing [None]
+    /// removes the listener.
+    pub fn set_auth_state_listener(&self, listener: Option<Arc<dyn AuthStateListener>>) {
+        let forwarder = listener.map(|listener| {
+            spawn_watch_forwarder(&self.rt, self.connection.watch_auth_state(), move |state| {
+                let listener = listener.clone();
+                async move { listener.on_auth_state_change(state).await }
+            })
+        });
+        replace_forwarder(&self.auth_state_forwarder, forwarder);
+    }
+
+    /// The JSON encoded claims of the identity that the backend accepted for
+    /// this client, like `sub` and `email`, or [None] while it's
+    /// unauthenticated or the auth hasn't been accepted yet.
+    ///
+    /// The sync protocol doesn't send the identity back, so these are the
+    /// claims of the accepted JWT, which is what the backend's
+    /// `ctx.auth.getUserIdentity()` is made from. Auth that isn't a JWT, like
+    /// a deploy key, has no claims to return.
+    pub fn current_identity(&self) -> Option<String> {
+        match &*self.connection.watch_auth_state().borrow() {
+            AuthState::Authenticated { identity } => identity.clone(),
+            _ => None,
+        }
+    }
+
+    /// Set a listener for the backend rejecting the auth, e.g. because the
+    /// token expired, to send the user to log in again.
+    ///
+    /// The listener is told once for each auth set with
+    /// [MobileConvexClient::set_auth] or
+    /// [MobileConvexClient::set_auth_callback], even though the client keeps
+    /// retrying it on every reconnect, and right away if the current one
+    /// was already rejected. Passing [None] removes the listener.
+    ///
+    /// The underlying client only reports rejections through `tracing`, so
+    /// like server notices they're only noticed once `init_convex_logging`
+    /// has been called (or [events_layer] added to the global subscriber).
+    /// Clients on the shared runtime can't be told apart, so a rejection on
+    /// one of them is taken for every one of them whose auth is loading or
+    /// accepted.
+    pub fn set_auth_error_listener(&self, listener: Option<Arc<dyn AuthErrorListener>>) {
+        let forwarder = listener.map(|listener| {
+            spawn_watch_forwarder(
+                &self.rt,
+                self.connection.watch_auth_rejection(),
+                move |rejection| {
+                    let listener = listener.clone();
+                    async move {
+                        if let Some(message) = rejection {
+                            listener.on_auth_error(message).await
+                        }
+                    }
+                },
+            )
+        });
+        replace_forwarder(&self.auth_error_forwarder, forwarder);
+    }
+
+    /// Set a listener for non-fatal notices from the Convex backend, like
+    /// deprecation warnings for this client's version.
+    ///
+    /// The underlying client only reports these through `tracing`, so they're
+    /// only delivered once `init_convex_logging` has been called (or
+    /// [events_layer] added to the global subscriber). They're attributed to a
+    /// client by the threads of its runtime, so clients with
+    /// [ClientOptions::use_shared_runtime] all get each other's notices.
+    /// The sync protocol doesn't currently carry quota or maintenance notices.
+    ///
+    /// Passing [None] removes the listener.
+    pub fn set_server_notice_listener(&self, listener: Option<Arc<dyn ServerNoticeListener>>) {
+        self.notices.set_listener(listener);
+    }
+
+    /// Set a listener for the `console` log lines of backend functions, like
+    /// the browser client prints to the developer console. Only dev
+    /// deployments send them.
+    ///
+    /// Like server notices, these only come through `tracing`, so the same
+    /// setup is needed and clients on the shared runtime get each other's log
+    /// lines. Passing [None] removes the listener.
+    pub fn set_function_log_listener(&self, listener: Option<Arc<dyn FunctionLogListener>>) {
+        self.function_logs.set_listener(listener);
+    }
+
+    /// Try to reconnect right away, e.g. when the platform reports that the
+    /// network is back, instead of waiting for the next attempt of the
+    /// client's backoff.
+    ///
+    /// Subscriptions carry over to the new connection. This also gets the
+    /// client going again after it's been paused or has given up in
+    /// [ConnectionState::Failed].
+    pub async fn reconnect(&self) -> Result<(), ClientError> {
+        catch_panic(async {
+            self.connection.start_over();
+            // Connecting for the first time is all there is to do before the
+            // first connection, and fails once shut down.
+            if self.connected.get().is_none() || self.is_shut_down.load(Ordering::SeqCst) {
+                return Ok(self.ensure_connected().await?);
+            }
+            self.connection
+                .reconnect()
+                .await
+                .map_err(|e| ClientError::NetworkError {
+                    msg: e.to_string(),
+                    retryable: true,
+                })
+        })
+        .await
+    }
+
+    /// Let the client know that the app went to the background.
+    ///
+    /// Subscriptions stop calling their subscribers until
+    /// [MobileConvexClient::notify_app_foreground], which then get only the
+    /// latest result of each, so a backgrounded app isn't woken up for every
+    /// change. The connection stays up, heartbeats and all, as the underlying
+    /// client doesn't let those be tuned; see [MobileConvexClient::pause] to
+    /// drop it too.
+    pub fn notify_app_background(&self) {
+        self.in_background.send_replace(true);
+    }
+
+    /// Let the client know that the app is back in the foreground, delivering
+    /// the subscription results that came in while it was in the background.
+    pub fn notify_app_foreground(&self) {
+        self.in_background.send_replace(false);
+    }
+
+    /// Let the client know how the device is connected to the network, and
+    /// whether the connection is `metered`.
+    ///
+    /// On a metered or cellular network, subscription results are debounced
+    /// for at least half a second, so a query that changes many times a second
+    /// doesn't call back for every change. What the backend sends can't be cut
+    /// down, as the sync protocol sends every change to every subscribed query.
+    pub fn set_network_type(&self, network_type: NetworkType, metered: bool) {
+        self.hints.send_modify(|hints| {
+            hints.network_type = network_type;
+            hints.metered = metered;
+        });
+    }
+
+    /// Turn low power mode on or off, e.g. when the device's battery saver or
+    /// Low Data Mode is.
+    ///
+    /// In low power mode, subscription results are debounced for at least a
+    /// second, which wakes the app up less often for busy queries. The
+    /// WebSocket heartbeats are fixed by the underlying client, so they carry
+    /// on as usual.
+    pub fn set_low_power_mode(&self, enabled: bool) {
+        self.hints.send_modify(|hints| hints.low_power = enabled);
+    }
+
+    /// Disconnect from the deployment until [MobileConvexClient::resume], e.g.
+    /// while the app is in the background.
+    ///
+    /// Subscriptions are kept and get their results again once resumed. Calls
+    /// fail with a retryable [ClientError::NetworkError] in the meantime.
+    pub fn pause(&self) {
+        self.connection.pause();
+    }
+
+    /// Reconnect after [MobileConvexClient::pause], resubscribing to every
+    /// subscription.
+    pub async fn resume(&self) -> Result<(), ClientError> {
+        self.reconnect().await
+    }
+
+    /// Start connecting to the deployment, and authenticating with the auth
+    /// that's been set, right away instead of on the first call.
+    ///
+    /// This returns without waiting for the connection, so it can be called
+    /// at app launch to have the connection ready by the time the first
+    /// screen needs it.
+    pub fn connect(self: Arc<Self>) {
+        let rt = self.rt.clone();
+        rt.spawn(async move {
+            if let Err(e) = self.ensure_connected().await {
+                warn!("Failed to connect: {e}");
+            }
+        });
+    }
+
+    /// Shut the client down, cancelling all subscriptions, disconnecting from
+    /// the deployment and stopping its worker threads.
+    ///
+    /// Calls that are still in flight fail, as does every call afterwards.
+    /// With [ClientOptions::use_shared_runtime] the runtime keeps running for
+    /// the other clients.
+    /// Shutting down a client that's already shut down does nothing. Dropping
+    /// the client shuts it down too, but hosts with a garbage collector only
+    /// drop it whenever that gets to it. This is named so it doesn't clash
+    /// with the `close()` that the bindings generate to release the host's
+    /// reference to the client.
+    pub fn shutdown(&self) {
+        if self.is_shut_down.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        debug!("Shutting down client");
+        self.subscriptions.clear();
+        self.connection.close();
+        replace_forwarder(&self.connection_state_forwarder, None);
+        replace_forwarder(&self.auth_state_forwarder, None);
+        replace_forwarder(&self.auth_error_forwarder, None);
+        let runtime = self.runtime.lock().take();
+        if let Some(runtime) = runtime {
+            runtime.shutdown();
+        }
+    }
+
+    /// The deployment URL that the client is currently using.
+    pub fn active_deployment_url(&self) -> String {
+        self.connection.active_url()
+    }
+}
+
+export_with_timeout! {
+    impl MobileConvexClient {
+        /// Execute a one-shot query against the Convex backend.
+        ///
+        /// Arguments and results use Convex's JSON format, where `Int64` values
+        /// are encoded as `{"$integer": "<base64 little-endian bytes>"}` and plain
+        /// numbers are `Float64`s. Integer arguments too large to be an exact
+        /// `Float64` are rejected rather than rounded.
+        ///
+        /// The underlying client runs a one-shot query as a subscription that's
+        /// dropped after its first result, so the result has the same consistency
+        /// as a [MobileConvexClient::subscribe] would, including reflecting the
+        /// mutations that were already made by this client.
+        ///
+        /// With a `timeout_ms`, the query fails with a [ClientError::Timeout] if
+        /// it hasn't finished by then, counting the time taken to connect.
+        ///
+        /// Cancelling the call from the host, e.g. by cancelling the coroutine or
+        /// task awaiting it, abandons it without waiting for its result. The same
+        /// goes for mutations and actions, but those may still run on the backend
+        /// once they were sent.
+        pub async fn query(
+            &self,
+            name: String,
+            args: HashMap<String, String>,
+            timeout_ms: Option<u64>,
+        ) -> Result<String, ClientError> {
+            catch_panic(with_timeout(&self.rt, timeout_ms, async {
+                let args = self.function_args(args)?;
+                let result = self.internal_query(name, args).await?;
+                handle_direct_function_result(result)
+            }))
+            .await
+        }
+
+        /// Like [MobileConvexClient::query], but with the arguments as one JSON
+        /// object, like `{"a": 1, "b": "x"}`, e.g. a serialized data class.
+        pub async fn query_json(
+            &self,
+            name: String,
+            args: String,
+            timeout_ms: Option<u64>,
+        ) -> Result<String, ClientError> {
+            catch_panic(with_timeout(&self.rt, timeout_ms, async {
+                let args = self.json_object_args(args)?;
+                let result = self.internal_query(name, args).await?;
+                handle_direct_function_result(result)
+            }))
+            .await
+        }
+
+        /// Like [MobileConvexClient::query], but with the result encoded in a
+        /// [BinaryFormat] instead of as a JSON string, which is quicker for large
+        /// results.
+        pub async fn query_binary(
+            &self,
+            name: String,
+            args: HashMap<String, String>,
+            format: BinaryFormat,
+            timeout_ms: Option<u64>,
+        ) -> Result<Vec<u8>, ClientError> {
+            catch_panic(with_timeout(&self.rt, timeout_ms, async {
+                let args = self.function_args(args)?;
+                let result = self.internal_query(name, args).await?;
+                handle_binary_function_result(result, format)
+            }))
+            .await
+        }
+
+        /// Run a mutation against the Convex backend.
+        ///
+        /// With a `timeout_ms`, the call fails with a [ClientError::Timeout] if it
+        /// hasn't finished by then. The mutation may still run after that.
+        pub async fn mutation(
+            &self,
+            name: String,
+            args: HashMap<String, String>,
+            timeout_ms: Option<u64>,
+        ) -> Result<String, ClientError> {
+            catch_panic(with_timeout(&self.rt, timeout_ms, async {
+                debug!("Running mutation: {}", name);
+                let args = self.function_args(args)?;
+                let result = self.internal_mutation(name, args).await?;
+
+                handle_direct_function_result(result)
+            }))
+            .await
+        }
+
+        /// Like [MobileConvexClient::mutation], but with the arguments as one
+        /// JSON object.
+        pub async fn mutation_json(
+            &self,
+            name: String,
+            args: String,
+            timeout_ms: Option<u64>,
+        ) -> Result<String, ClientError> {
+            catch_panic(with_timeout(&self.rt, timeout_ms, async {
+                debug!("Running mutation: {}", name);
+                let args = self.json_object_args(args)?;
+                let result = self.internal_mutation(name, args).await?;
+                handle_direct_function_result(result)
+            }))
+            .await
+        }
+
+        /// Like [MobileConvexClient::mutation], but with the result encoded in a
+        /// [BinaryFormat] instead of as a JSON string.
+        pub async fn mutation_binary(
+            &self,
+            name: String,
+            args: HashMap<String, String>,
+            format: BinaryFormat,
+            timeout_ms: Option<u64>,
+        ) -> Result<Vec<u8>, ClientError> {
+            catch_panic(with_timeout(&self.rt, timeout_ms, async {
+                debug!("Running mutation: {}", name);
+                let args = self.function_args(args)?;
+                let result = self.internal_mutation(name, args).await?;
+                handle_binary_function_result(result, format)
+            }))
+            .await
+        }
+
+        /// Run an action on the Convex backend.
+        ///
+        /// With a `timeout_ms`, the call fails with a [ClientError::Timeout] if it
+        /// hasn't finished by then. The action may still run after that.
+        pub async fn action(
+            &self,
+            name: String,
+            args: HashMap<String, String>,
+            timeout_ms: Option<u64>,
+        ) -> Result<String, ClientError> {
+            catch_panic(with_timeout(&self.rt, timeout_ms, async {
+                debug!("Running action: {}", name);
+                let args = self.function_args(args)?;
+                let result = self.internal_action(name, args).await?;
+                handle_direct_function_result(result)
+            }))
+            .await
+        }
+
+        /// Like [MobileConvexClient::action], but with the arguments as one JSON
+        /// object.
+        pub async fn action_json(
+            &self,
+            name: String,
+            args: String,
+            timeout_ms: Option<u64>,
+        ) -> Result<String, ClientError> {
+            catch_panic(with_timeout(&self.rt, timeout_ms, async {
+                debug!("Running action: {}", name);
+                let args = self.json_object_args(args)?;
+                let result = self.internal_action(name, args).await?;
+                handle_direct_function_result(result)
+            }))
+            .await
+        }
+
+        /// Like [MobileConvexClient::action], but with the result encoded in a
+        /// [BinaryFormat] instead of as a JSON string.
+        pub async fn action_binary(
+            &self,
+            name: String,
+            args: HashMap<String, String>,
+            format: BinaryFormat,
+            timeout_ms: Option<u64>,
+        ) -> Result<Vec<u8>, ClientError> {
+            catch_panic(with_timeout(&self.rt, timeout_ms, async {
+                debug!("Running action: {}", name);
+                let args = self.function_args(args)?;
+                let result = self.internal_action(name, args).await?;
+                handle_binary_function_result(result, format)
+            }))
+            .await
+        }
+
+        /// Wait until the client is connected to the deployment and the backend
+        /// has accepted or rejected the auth that's been set, connecting first if
+        /// it hasn't yet.
+        ///
+        /// With a `timeout_ms`, this fails with a [ClientError::Timeout] if that
+        /// takes any longer, so e.g. a splash screen can wait for the client to
+        /// be ready without waiting forever.
+        pub async fn wait_for_connection(&self, timeout_ms: Option<u64>) -> Result<(), ClientError> {
+            catch_panic(with_timeout(&self.rt, timeout_ms, async {
+                self.ensure_connected().await?;
+                if self.mock.is_some() {
+                    return Ok(());
+                }
+                let state = *self
+                    .connection
+                    .watch_state()
+                    .wait_for(|state| {
+                        matches!(state, ConnectionState::Connected | ConnectionState::Failed)
+                    })
+                    .await
+                    .map_err(anyhow::Error::from)?;
+                if state == ConnectionState::Failed {
+                    return Err(gave_up());
+                }
+                self.connection
+                    .watch_auth_state()
+                    .wait_for(|state| *state != AuthState::AuthLoading)
+                    .await
+                    .map_err(anyhow::Error::from)?;
+                Ok(())
+            }))
+            .await
+        }
+
+        /// Wait until the backend has accepted or rejected the auth last set with
+        /// [MobileConvexClient::set_auth] or [MobileConvexClient::set_auth_callback],
+        /// after which queries run as the new identity.
+        ///
+        /// Fails with [ClientError::AuthError] if the backend rejected it, or with
+        /// [ClientError::Timeout] if it hasn't decided within `timeout_ms`.
+        ///
+        /// Rejections are noticed the same way as by
+        /// [MobileConvexClient::set_auth_error_listener], so without
+        /// `init_convex_logging` a rejected auth keeps this waiting until the
+        /// timeout, or forever without one.
+        pub async fn wait_for_auth(&self, timeout_ms: Option<u64>) -> Result<(), ClientError> {
+            catch_panic(with_timeout(&self.rt, timeout_ms, async {
+                self.ensure_connected().await?;
+                if self.mock.is_some() {
+                    return Ok(());
+                }
+                let state = self
+                    .connection
+                    .watch_auth_state()
+                    .wait_for(|state| *state != AuthState::AuthLoading)
+                    .await
+                    .map_err(anyhow::Error::from)?
+                    .clone();
+                match state {
+                    AuthState::Unauthenticated { error: Some(msg) } => {
+                        Err(ClientError::AuthError { msg })
+                    },
+                    _ => Ok(()),
+                }
+            }))
+            .await
+        }
+    }
+}
+
+#[cfg(feature = "fault-injection")]
+impl MobileConvexClient {
+    /// Cuts the connection off like a network failure would, so the client
+    /// goes through [ConnectionState::Reconnecting] and resubscribes. Does
+    /// nothing before the first connection.
+    pub async fn inject_connection_drop(&self) -> Result<(), ClientError> {
+        if self.mock.is_some() || self.connected.get().is_none() {
+            return Ok(());
+        }
+        self.connection
+            .drop_connection()
+            .await
+            .map_err(|e| ClientError::NetworkError {
+                msg: e.to_string(),
+                retryable: true,
+            })
+    }
+
+    /// Holds back the results of calls and the updates of subscriptions by
+    /// `delay_ms` from now on, or stops delaying them if it's 0.
+    pub fn inject_delay(&self, delay_ms: u64) {
+        self.faults.set_delay(Duration::from_millis(delay_ms));
+    }
+
+    /// Makes the next query, mutation or action fail with `error` without
+    /// reaching the backend. Errors injected one after another fail the calls
+    /// that follow in the same order.
+    pub fn inject_error(&self, error: ClientError) {
+        self.faults.push_error(error);
+    }
+}
+
+impl MobileConvexClient {
+    /// Creates the client of a [MockConvexClient], which never connects.
+    fn mocked(backend: Arc<MockBackend>) -> MobileConvexClient {
+        let options = ClientOptions {
+            use_shared_runtime: true,
+            ..Default::default()
+        };
+        let mut client = Self::with_options(
+            "https://mock.convex.cloud".into(),
+            "mock".into(),
+            None,
+            options,
+        );
+        client.mock = Some(backend);
+        client
+    }
+
+    /// Returns a connected [ConvexClient].
+    ///
+    /// The first call is guaranteed to create the client object and subsequent
+    /// calls will return clones of the current client, which may have been
+    /// replaced by one for a fallback deployment URL.
+    async fn connected_client(&self) -> anyhow::Result<ConvexClient> {
+        self.ensure_connected().await?;
+        self.connection
+            .current()
+            .ok_or_else(|| anyhow::anyhow!("Client is not connected"))
+    }
+
+    /// Creates and connects the client on the first call.
+    ///
+    /// Returns a [ClientError::NetworkError] if the first connection to the
+    /// deployment can't be established, or if the WebSocket doesn't connect
+    /// within the [ClientOptions::connect_timeout_ms].
+    async fn ensure_connected(&self) -> anyhow::Result<()> {
+        if self.is_shut_down.load(Ordering::SeqCst) {
+            return Err(ClientError::InternalError {
+                msg: "The client was shut down".into(),
+            }
+            .into());
+        }
+        if self.mock.is_some() {
+            return Ok(());
+        }
+        match *self.connection.watch_state().borrow() {
+            ConnectionState::Failed => return Err(gave_up().into()),
+            ConnectionState::Paused => {
+                return Err(ClientError::NetworkError {
+                    msg: "The client is paused".into(),
+                    retryable: true,
+                }
+                .into())
+            },
+            _ => {},
+        }
+        self.connected
+            .get_or_try_init(async {
+                self.connection
+                    .connect()
+                    .await
+                    .map_err(|e| ClientError::NetworkError {
+                        msg: e.to_string(),
+                        retryable: true,
+                    })?;
+                let host = self.web_socket_state_subscriber.clone().map(|subscriber| {
+                    let (latest_tx, mut latest_rx) = latest::channel();
+                    self.rt.spawn(async move {
+                        while let Some(state) = latest_rx.recv().await {
+                            subscriber.on_state_change(state).await;
+                        }
+                    });
+                    latest_tx
+                });
+                if let Some(states) = self.states.lock().take() {
+                    self.rt.spawn(connection::monitor(
+                        Arc::downgrade(&self.connection),
+                        states,
+                        host,
+                    ));
+                }
+                anyhow::Ok(())
+            })
+            .await?;
+        if let Some(timeout) = self.connect_timeout {
+            let mut states = self.connection.watch_state();
+            let connected = self
+                .rt
+                .spawn(async move {
+                    tokio::time::timeout(
+                        timeout,
+                        states.wait_for(|state| *state == ConnectionState::Connected),
+                    )
+                    .await
+                    .is_ok_and(|state| state.is_ok())
+                })
+                .await
+                .unwrap_or(false);
+            if !connected {
+                return Err(ClientError::NetworkError {
+                    msg: format!("Not connected after {timeout:?}"),
+                    retryable: true,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses the arguments for a function call, adding the anonymous session
+    /// ID if there is one.
+    fn function_args(
+        &self,
+        raw_args: HashMap<String, String>,
+    ) -> Result<BTreeMap<String, Value>, ClientError> {
+        Ok(self.with_session(parse_json_args(raw_args)?))
+    }
+
+    /// Converts the arguments of a function call given as one JSON object,
+    /// adding the anonymous session ID if there is one.
+    fn json_object_args(&self, args: String) -> Result<BTreeMap<String, Value>, ClientError> {
+        Ok(self.with_session(parse_json_object_args(&args)?))
+    }
+
+    /// Converts typed arguments for a function call, adding the anonymous
+    /// session ID if there is one.
+    fn value_args(&self, args: HashMap<String, ConvexValue>) -> BTreeMap<String, Value> {
+        self.with_session(args.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+
+    fn with_session(&self, mut args: BTreeMap<String, Value>) -> BTreeMap<String, Value> {
+        self.session.apply(&mut args);
+        args
+    }
+
+    /// Posts a call to the worker, or has the [MockBackend] answer it.
+    fn post(
+        &self,
+        call: Call,
+        name: String,
+        args: BTreeMap<String, Value>,
+    ) -> impl Future<Output = anyhow::Result<FunctionResult>> {
+        #[cfg(feature = "fault-injection")]
+        if let Some(error) = self.faults.take_error() {
+            return future::ready(Err(error.into())).boxed();
+        }
+        let recorded = self
+            .recorder
+            .is_recording()
+            .then(|| (self.recorder.clone(), name.clone(), args.clone()));
+        let span = info_span!("convex_call", call = ?call, name = %name);
+        let result = match &self.mock {
+            Some(mock) => future::ready(mock.call(name, args)).left_future(),
+            None => self.worker.post(call, name, args).right_future(),
+        };
+        let result = async move {
+            let result = result.await;
+            if let (Ok(result), Some((recorder, name, args))) = (&result, recorded) {
+                recorder.record(Source::Call(call), &name, &args, result);
+            }
+            result
+        }
+        .instrument(span);
+        #[cfg(feature = "fault-injection")]
+        let result = {
+            let faults = self.faults.clone();
+            async move {
+                let result = result.await;
+                faults.delay().await;
+                result
+            }
+            .boxed()
+        };
+        result
+    }
+
+    async fn internal_query(
+        &self,
+        name: String,
+        args: BTreeMap<String, Value>,
+    ) -> anyhow::Result<FunctionResult> {
+        self.ensure_connected().await?;
+        self.post(Call::Query, name, args).await
+    }
+
+    async fn internal_subscribe(
+        &self,
+        name: String,
+        args: BTreeMap<String, Value>,
+        options: SubscriptionOptions,
+        subscriber: Subscriber,
+    ) -> anyhow::Result<Arc<SubscriptionHandle>> {
+        // Show the cached result while connecting, which can take a while
+        // right after a cold start.
+        let cache_key = self.cache.key(&name, &args);
+        let cached = cache_key.clone().map(|cache_key| {
+            let subscriber = subscriber.clone();
+            self.rt.spawn(async move {
+                if let Some(value) = cache_key.get().await {
+                    subscriber.on_json_update(value, true).await;
+                }
+            })
+        });
+        let mut subscription = match &self.mock {
+            Some(mock) => mock.subscribe(&name, &args),
+            None => {
+                let mut client = self.connected_client().await?;
+                debug!("New subscription to {}", name);
+                client.subscribe(name.as_str(), args.clone()).await?.boxed()
+            },
+        };
+        let mut clients = self.connection.watch();
+        let mut in_background = self.in_background.subscribe();
+        let hints = self.hints.subscribe();
+        let mut auth_changes = self.connection.watch_auth_changes();
+        let (cancel_sender, cancel_receiver) = oneshot::channel::<()>();
+        let (running, running_receiver) = watch::channel(());
+        let (id, evicted) =
+            self.subscriptions
+                .insert(name.clone(), args_json(&args), cancel_sender);
+        self.notify_evicted(evicted);
+        let mut patches = self.optimistic.register(id, name.clone(), args_json(&args));
+        let subscriptions = self.subscriptions.clone();
+        let optimistic = self.optimistic.clone();
+        #[cfg(feature = "fault-injection")]
+        let faults = self.faults.clone();
+        let recorder = self.recorder.clone();
+        self.rt.spawn(async move {
+            let run = async {
+                let cancel_fut = cancel_receiver.fuse();
+                pin_mut!(cancel_fut);
+                if let Some(cached) = cached {
+                    let _ = cached.await;
+                }
+                let mut patch_state = PatchState::default();
+                let mut latest: Option<FunctionResult> = None;
+                // The backend acknowledges a subscription by sending its
+                // first result.
+                let mut subscribed = false;
+                // Whether the latest result is waiting for the app to come to
+                // the foreground.
+                let mut deferred = false;
+                loop {
+                    let update = select_biased! {
+                        new_val = subscription.next().fuse() => {
+                            let Some(mut result) = new_val else {
+                                // Let the subscriber know that no more updates
+                                // are coming, unless it's being cancelled.
+                                if cancel_fut.as_mut().now_or_never().is_none() {
+                                    debug!("Subscription to {} ended", name);
+                                    subscriber
+                                        .on_error(SUBSCRIPTION_ENDED.into(), None)
+                                        .await;
+                                }
+                                break
+                            };
+                            if !subscribed {
+                                subscribed = true;
+                                subscriber.on_subscribed().await;
+                            }
+                            if options.conflate {
+                                // Skip the results that arrived while the host
+                                // was busy with the previous one.
+                                while let Some(Some(newer)) = subscription.next().now_or_never() {
+                                    result = newer;
+                                }
+                            }
+                            let min_debounce = hints.borrow().min_debounce();
+                            if let Some(window) = options.debounce().max(min_debounce) {
+                                let deadline = tokio::time::Instant::now() + window;
+                                while let Ok(Some(newer)) =
+                                    tokio::time::timeout_at(deadline, subscription.next()).await
+                                {
+                                    result = newer;
+                                }
+                            }
+                            subscriptions.touch(id);
+                            if let FunctionResult::Value(value) = &result {
+                                optimistic
+                                    .set_latest(id, serde_json::Value::from(value.clone()).to_string());
+                            }
+                            recorder.record(Source::Subscription, &name, &args, &result);
+                            latest = Some(result.clone());
+                            if patch_state.is_held() {
+                                continue
+                            }
+                            if *in_background.borrow_and_update() {
+                                deferred = true;
+                                continue
+                            }
+                            Update::Result(result)
+                        },
+                        _ = foreground(&mut in_background, deferred).fuse() => {
+                            deferred = false;
+                            match latest.clone() {
+                                Some(result) if !patch_state.is_held() => Update::Result(result),
+                                _ => continue,
+                            }
+                        },
+                        patch = patches.recv().fuse() => {
+                            let Some(patch) = patch else {
+                                break
+                            };
+                            let restore = patch_state.apply(&patch);
+                            match (patch, latest.clone()) {
+                                (Patch::Apply(value), _) => Update::Optimistic(value),
+                                (Patch::Release, Some(result)) if restore => Update::Result(result),
+                                (Patch::Release, _) => continue,
+                            }
+                        },
+                        changed = clients.changed().fuse() => {
+                            if changed.is_err() {
+                                break
+                            }
+                            // The client was replaced, e.g. after failing over, so
+                            // carry the subscription over to it. Without a client,
+                            // e.g. while paused, the subscription waits for the
+                            // next one.
+                            let Some(mut client) = clients.borrow_and_update().clone() else {
+                                subscription = stream::pending().boxed();
+                                continue
+                            };
+                            match client.subscribe(name.as_str(), args.clone()).await {
+                                Ok(resubscribed) => subscription = resubscribed.boxed(),
+                                Err(e) => {
+                                    subscriber.on_error(e.to_string(), None).await;
+                                    break
+                                },
+                            }
+                            continue
+                        },
+                        changed = auth_changes.changed().fuse() => {
+                            if changed.is_err() {
+                                break
+                            }
+                            subscriber.on_resubscribed().await;
+                            continue
+                        },
+                        _ = cancel_fut => {
+                            break
+                        },
+                    };
+                    // Waiting on the host here is what applies backpressure; the
+                    // results that queue up meanwhile are conflated above.
+                    let delivery = async {
+                        #[cfg(feature = "fault-injection")]
+                        faults.delay().await;
+                        update.deliver(&subscriber, cache_key.as_ref()).await
+                    }
+                    .fuse();
+                    pin_mut!(delivery);
+                    select_biased! {
+                        _ = delivery => {},
+                        _ = cancel_fut => break,
+                    }
+                }
+            };
+            // A bug here shouldn't take down the host, and the subscriber
+            // should hear about it instead of just not getting updates.
+            if let Err(panic) = AssertUnwindSafe(run).catch_unwind().await {
+                subscriber.on_error(panic_message(panic), None).await;
+            }
+            subscriptions.remove(id);
+            optimistic.unregister(id);
+            drop(running);
+            debug!("Subscription canceled");
+        });
+        Ok(Arc::new(SubscriptionHandle::new(
+            id,
+            &self.subscriptions,
+            running_receiver,
+        )))
+    }
+
+    fn notify_evicted(&self, evicted: Vec<Evicted>) {
+        if evicted.is_empty() {
+            return;
+        }
+        let Some(listener) = self.eviction_listener.lock().clone() else {
+            return;
+        };
+        self.rt.spawn(async move {
+            for Evicted { id, name } in evicted {
+                debug!("Evicted subscription {id} to {name}");
+                listener.on_evicted(id, name).await;
+            }
+        });
+    }
+
+    async fn internal_mutation(
+        &self,
+        name: String,
+        args: BTreeMap<String, Value>,
+    ) -> anyhow::Result<FunctionResult> {
+        self.ensure_connected().await?;
+        self.post(Call::Mutation, name, args).await
+    }
+
+    async fn internal_action(
+        &self,
+        name: String,
+        args: BTreeMap<String, Value>,
+    ) -> anyhow::Result<FunctionResult> {
+        self.ensure_connected().await?;
+        self.post(Call::Action, name, args).await
+    }
+
+    /// Makes a call once connected, timing it from when it's handed to the
+    /// worker, so the time taken to connect doesn't count.
+    async fn timed_call(
+        &self,
+        call: Call,
+        name: String,
+        args: HashMap<String, String>,
+    ) -> Result<TimedResult, ClientError> {
+        catch_panic(async {
+            let args = self.function_args(args)?;
+            self.ensure_connected().await?;
+            let started = Instant::now();
+            let result = self.post(call, name, args).await?;
+            let round_trip = started.elapsed();
+            Ok(TimedResult {
+                value: handle_direct_function_result(result)?,
+                round_trip_ms: round_trip.as_millis() as u64,
+            })
+        })
+        .await
+    }
+
+    async fn internal_set_auth(&self, token: Option<String>) -> anyhow::Result<()> {
+        self.ensure_connected().await?;
+        let auth = match token {
+            Some(token) => Auth::Token(token),
+            None => Auth::None,
+        };
+        self.connection.set_auth(auth).await;
+        Ok(())
+    }
+
+    async fn internal_set_auth_callback(
+        &self,
+        provider: Option<Arc<dyn AuthTokenProvider>>,
+    ) -> anyhow::Result<()> {
+        self.ensure_connected().await?;
+        let auth = match provider {
+            Some(provider) => Auth::Provider(provider),
+            None => Auth::None,
+        };
+        self.connection.set_auth(auth).await;
+        Ok(())
+    }
+
+    async fn use_convex_auth(&self, auth: Arc<ConvexAuth>) -> anyhow::Result<()> {
+        self.ensure_connected().await?;
+        *self.convex_auth.lock() = Some(auth.clone());
+        self.connection.set_auth(Auth::Provider(auth)).await;
+        Ok(())
+    }
+}
+
+impl Drop for MobileConvexClient {
+    /// Shuts the client down once the host lets go of it, so its tasks and
+    /// worker threads don't outlive it.
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// The error that subscribers get when their subscription ends without being
+/// cancelled, e.g. because the underlying client stopped.
+const SUBSCRIPTION_ENDED: &str = "The subscription ended because the client disconnected";
+
+/// The error for calls made once the connection is [ConnectionState::Failed].
+fn gave_up() -> ClientError {
+    ClientError::NetworkError {
+        msg: "The client gave up on connecting".into(),
+        retryable: false,
+    }
+}
+
+/// Fails `call` with a [ClientError::Timeout] if it takes longer than
+/// `timeout_ms`.
+///
+/// The host's executor polls the calls, so the timer runs on `rt`, which has
+/// the time driver that tokio's timers need.
+async fn with_timeout<T>(
+    rt: &Handle,
+    timeout_ms: Option<u64>,
+    call: impl Future<Output = Result<T, ClientError>>,
+) -> Result<T, ClientError> {
+    let Some(timeout_ms) = timeout_ms else {
+        return call.await;
+    };
+    let timeout = Duration::from_millis(timeout_ms);
+    let mut timer = rt.spawn(async move { tokio::time::sleep(timeout).await });
+    let call = call.fuse();
+    pin_mut!(call);
+    let result = select_biased! {
+        result = call => result,
+        _ = (&mut timer).fuse() => Err(ClientError::Timeout {
+            msg: format!("The call didn't finish within {timeout_ms}ms"),
+        }),
+    };
+    timer.abort();
+    result
+}
+
+/// Runs the body of a public method, turning a panic into a
+/// [ClientError::InternalError] rather than unwinding into the host.
+async fn catch_panic<T>(
+    body: impl Future<Output = Result<T, ClientError>>,
+) -> Result<T, ClientError> {
+    AssertUnwindSafe(body)
+        .catch_unwind()
+        .await
+        .unwrap_or_else(|panic| {
+            Err(ClientError::InternalError {
+                msg: panic_message(panic),
+            })
+        })
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown cause".into());
+    format!("Panicked: {message}")
+}
+
+/// Spawns a task that calls `deliver` with the current value of `values` and
+/// then every newer one, once the previous delivery completes.
+fn spawn_watch_forwarder<T, F, Fut>(
+    rt: &Handle,
+    mut values: watch::Receiver<T>,
+    deliver: F,
+) -> JoinHandle<()>
+where
+    T: Clone + Send + Sync + 'static,
+    F: Fn(T) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    rt.spawn(async move {
+        loop {
+            let value = values.borrow_and_update().clone();
+            deliver(value).await;
+            if values.changed().await.is_err() {
+                break;
+            }
+        }
+    })
+}
+
+/// Waits for the app to come to the foreground, if there's a result `deferred`
+/// until then.
+async fn foreground(in_background: &mut watch::Receiver<bool>, deferred: bool) {
+    if !deferred
+        || in_background
+            .wait_for(|in_background| !in_background)
+            .await
+            .is_err()
+    {
+        future::pending().await
+    }
+}
+
+fn replace_forwarder(slot: &Mutex<Option<JoinHandle<()>>>, forwarder: Option<JoinHandle<()>>) {
+    if let Some(previous) = std::mem::replace(&mut *slot.lock(), forwarder) {
+        previous.abort();
+    }
+}
+
+/// The host's subscriber to a query, which gets results either as JSON, as
+/// JSON passed through a [JsonFilter] or as [ConvexValue]s.
+#[derive(Clone)]
+enum Subscriber {
+    Json(Arc<dyn QuerySubscriber>),
+    Filtered {
+        subscriber: Arc<dyn QuerySubscriber>,
+        filter: Arc<Mutex<JsonFilter>>,
+    },
+    Value(Arc<dyn ValueQuerySubscriber>),
+    Binary {
+        subscriber: Arc<dyn BinaryQuerySubscriber>,
+        format: BinaryFormat,
+    },
+}
+
+impl Subscriber {
+    async fn on_update(&self, value: Value, is_stale: bool) {
+        match self {
+            Subscriber::Json(subscriber) => {
+                subscriber
+                    .on_update(serde_json::Value::from(value).to_string(), is_stale)
+                    .await
+            },
+            Subscriber::Filtered { subscriber, filter } => {
+                let update = filter.lock().apply(value.into(), is_stale);
+                if let Some(update) = update {
+                    subscriber.on_update(update, is_stale).await
+                }
+            },
+            Subscriber::Value(subscriber) => subscriber.on_update(value.into(), is_stale).await,
+            Subscriber::Binary { subscriber, format } => {
+                subscriber
+                    .on_update(binary::encode(*format, value), is_stale)
+                    .await
+            },
+        }
+    }
+
+    /// Delivers a JSON encoded result, like one from the cache or an
+    /// [OptimisticUpdater].
+    async fn on_json_update(&self, value: String, is_stale: bool) {
+        match self {
+            Subscriber::Json(subscriber) => subscriber.on_update(value, is_stale).await,
+            Subscriber::Filtered { subscriber, filter } => {
+                match serde_json::from_str::<serde_json::Value>(&value) {
+                    Ok(value) => {
+                        let update = filter.lock().apply(value, is_stale);
+                        if let Some(update) = update {
+                            subscriber.on_update(update, is_stale).await
+                        }
+                    },
+                    Err(e) => self.on_error(e.to_string(), None).await,
+                }
+            },
+            // Cached results are already in the format it delivers.
+            Subscriber::Binary {
+                subscriber,
+                format: BinaryFormat::Json,
+            } => subscriber.on_update(value.into_bytes(), is_stale).await,
+            Subscriber::Value(_) | Subscriber::Binary { .. } => match json_value(&value) {
+                Ok(value) => self.on_update(value, is_stale).await,
+                Err(e) => self.on_error(e.to_string(), None).await,
+            },
+        }
+    }
+
+    async fn on_subscribed(&self) {
+        match self {
+            Subscriber::Json(subscriber) | Subscriber::Filtered { subscriber, .. } => {
+                subscriber.on_subscribed().await
+            },
+            Subscriber::Value(subscriber) => subscriber.on_subscribed().await,
+            Subscriber::Binary { subscriber, .. } => subscriber.on_subscribed().await,
+        }
+    }
+
+    async fn on_resubscribed(&self) {
+        match self {
+            Subscriber::Json(subscriber) | Subscriber::Filtered { subscriber, .. } => {
+                subscriber.on_resubscribed().await
+            },
+            Subscriber::Value(subscriber) => subscriber.on_resubscribed().await,
+            Subscriber::Binary { subscriber, .. } => subscriber.on_resubscribed().await,
+        }
+    }
+
+    async fn on_error(&self, message: String, data: Option<Value>) {
+        if let Subscriber::Filtered { filter, .. } = self {
+            filter.lock().reset();
+        }
+        match self {
+            Subscriber::Json(subscriber) | Subscriber::Filtered { subscriber, .. } => {
+                subscriber
+                    .on_error(
+                        message,
+                        data.map(|data| serde_json::Value::from(data).to_string()),
+                    )
+                    .await
+            },
+            Subscriber::Value(subscriber) => {
+                subscriber.on_error(message, data.map(Into::into)).await
+            },
+            Subscriber::Binary { subscriber, format } => {
+                subscriber
+                    .on_error(message, data.map(|data| binary::encode(*format, data)))
+                    .await
+            },
+        }
+    }
+}
+
+/// Something to deliver to a [Subscriber].
+enum Update {
+    Result(FunctionResult),
+    Optimistic(String),
+}
+
+impl Update {
+    async fn deliver(self, subscriber: &Subscriber, cache_key: Option<&CacheKey>) {
+        match self {
+            Update::Result(result) => deliver_result(subscriber, cache_key, result).await,
+            Update::Optimistic(value) => subscriber.on_json_update(value, false).await,
+        }
+    }
+}
+
+/// The JSON encoding of function `args`, which is the same for equal args
+/// because they're sorted by key.
+fn args_json(args: &BTreeMap<String, Value>) -> String {
+    let args: serde_json::Map<_, _> = args
+        .iter()
+        .map(|(k, v)| (k.clone(), serde_json::Value::from(v.clone())))
+        .collect();
+    serde_json::Value::Object(args).to_string()
+}
+
+/// Delivers a result from the backend, writing it through to the cache.
+async fn deliver_result(
+    subscriber: &Subscriber,
+    cache_key: Option<&CacheKey>,
+    result: FunctionResult,
+) {
+    if let Some(cache_key) = cache_key {
+        match &result {
+            FunctionResult::Value(value) => {
+                cache_key
+                    .put(serde_json::Value::from(value.clone()).to_string())
+                    .await
+            },
+            _ => cache_key.delete().await,
+        }
+    }
+    match result {
+        FunctionResult::Value(value) => subscriber.on_update(value, false).await,
+        FunctionResult::ErrorMessage(message) => subscriber.on_error(message, None).await,
+        FunctionResult::ConvexError(error) => {
+            subscriber.on_error(error.message, Some(error.data)).await
+        },
+    }
+}
+
+fn json_value(json: &str) -> anyhow::Result<Value> {
+    Value::try_from(serde_json::from_str::<serde_json::Value>(json)?)
+}
+
+fn parse_json_args(
+    raw_args: HashMap<String, String>,
+) -> Result<BTreeMap<String, Value>, ClientError> {
+    raw_args
+        .into_iter()
+        .map(|(k, v)| {
+            let json = serde_json::from_str::<serde_json::Value>(&v).map_err(|e| {
+                ClientError::InvalidArgument {
+                    key: k.clone(),
+                    reason: invalid_json(&v, &e),
+                }
+            })?;
+            let value = json_arg(&k, json, || serde_json::from_str(&v).ok())?;
+            Ok((k, value))
+        })
+        .collect()
+}
+
+/// Like [parse_json_args], but for arguments given as one JSON object.
+fn parse_json_object_args(args_json: &str) -> Result<BTreeMap<String, Value>, ClientError> {
+    let invalid = |reason: String| ClientError::InvalidArgument {
+        key: "args".into(),
+        reason,
+    };
+    let json = serde_json::from_str::<serde_json::Value>(args_json)
+        .map_err(|e| invalid(invalid_json(args_json, &e)))?;
+    let serde_json::Value::Object(args) = json else {
+        return Err(invalid(format!("Expected a JSON object, got {json}")));
+    };
+    args.into_iter()
+        .map(|(k, v)| {
+            let value = json_arg(&k, v, || {
+                let json = serde_json::from_str::<serde_json::Value>(args_json).ok()?;
+                json.get(&k).cloned()
+            })?;
+            Ok((k, value))
+        })
+        .collect()
+}
+
+/// Converts the JSON of the argument `key` to a [Value].
+///
+/// The errors point at the part of the JSON that's at fault, by its path
+/// from the argument, e.g. `.items[2]`, and a snippet of it. Conversion errors
+/// don't say where they are, so they're found again in the `original` JSON,
+/// which is only parsed again then.
+fn json_arg(
+    key: &str,
+    json: serde_json::Value,
+    original: impl FnOnce() -> Option<serde_json::Value>,
+) -> Result<Value, ClientError> {
+    let invalid = |reason: String| ClientError::InvalidArgument {
+        key: key.into(),
+        reason,
+    };
+    check_exact_numbers(&json).map_err(invalid)?;
+    Value::try_from(json).map_err(|e| {
+        let Some(json) = original() else {
+            return invalid(format!("Invalid Convex value: {e}"));
+        };
+        let (path, part) = invalid_part(&json, String::new());
+        let error = Value::try_from(part.clone()).err().map(|e| e.to_string());
+        invalid(format!(
+            "Invalid Convex value {}{}: {}",
+            snippet(&part.to_string()),
+            location(&path),
+            error.unwrap_or_default(),
+        ))
+    })
+}
+
+/// The innermost part of `json` that isn't a valid Convex value, with its
+/// path from `path`.
+fn invalid_part(json: &serde_json::Value, path: String) -> (String, &serde_json::Value) {
+    let children: Vec<(String, &serde_json::Value)> = match json {
+        serde_json::Value::Array(values) => values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (format!("{path}[{i}]"), value))
+            .collect(),
+        serde_json::Value::Object(fields) => fields
+            .iter()
+            .map(|(field, value)| (format!("{path}.{field}"), value))
+            .collect(),
+        _ => Vec::new(),
+    };
+    for (path, child) in children {
+        if Value::try_from(child.clone()).is_err() {
+            return invalid_part(child, path);
+        }
+    }
+    (path, json)
+}
+
+/// Describes a JSON syntax error in `raw`, quoting the text up to it.
+fn invalid_json(raw: &str, error: &serde_json::Error) -> String {
+    let line: Vec<char> = raw
+        .lines()
+        .nth(error.line().saturating_sub(1))
+        .unwrap_or_default()
+        .chars()
+        .collect();
+    let end = error.column().min(line.len());
+    let start = end.saturating_sub(SNIPPET_CHARS);
+    let near: String = line[start..end].iter().collect();
+    format!("Invalid JSON near `{near}`: {error}")
+}
+
+/// How much of the offending JSON errors quote.
+const SNIPPET_CHARS: usize = 40;
+
+fn snippet(json: &str) -> String {
+    match json.char_indices().nth(SNIPPET_CHARS) {
+        Some((end, _)) => format!("`{}…`", &json[..end]),
+        None => format!("`{json}`"),
+    }
+}
+
+fn location(path: &str) -> String {
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!(" at {path}")
+    }
+}
+
+/// The largest integer that every smaller integer can be represented exactly
+/// as a [Value::Float64] up to.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Rejects JSON integers that would silently lose precision as the
+/// [Value::Float64] that plain JSON numbers become. Such values need to be
+/// passed as `{"$integer": ...}` to be sent as [Value::Int64]s.
+fn check_exact_numbers(json: &serde_json::Value) -> Result<(), String> {
+    match inexact_number(json) {
+        Some((path, n)) => Err(format!(
+            "Inexact number `{n}`{}: integer out of the Float64-safe range, pass it as an \
+             $integer",
+            location(&path)
+        )),
+        None => Ok(()),
+    }
+}
+
+/// The first number in `json` that isn't exact as a [Value::Float64], with
+/// its path. The path is only built on the way back up from one.
+fn inexact_number(json: &serde_json::Value) -> Option<(String, &serde_json::Number)> {
+    match json {
+        serde_json::Value::Number(n) => {
+            let exact = match (n.as_u64(), n.as_i64()) {
+                (Some(n), _) => n <= MAX_SAFE_INTEGER,
+                (None, Some(n)) => n.unsigned_abs() <= MAX_SAFE_INTEGER,
+                (None, None) => true,
+            };
+            (!exact).then(|| (String::new(), n))
+        },
+        serde_json::Value::Array(values) => values.iter().enumerate().find_map(|(i, value)| {
+            inexact_number(value).map(|(path, n)| (format!("[{i}]{path}"), n))
+        }),
+        serde_json::Value::Object(fields) => fields.iter().find_map(|(field, value)| {
+            inexact_number(value).map(|(path, n)| (format!(".{field}{path}"), n))
+        }),
+        _ => None,
+    }
+}
+
+fn handle_direct_function_result(result: FunctionResult) -> Result<String, ClientError> {
+    match result {
+        FunctionResult::Value(v) => serde_json::to_string(&serde_json::Value::from(v))
+            .map_err(|e| ClientError::InternalError { msg: e.to_string() }),
+        FunctionResult::ConvexError(e) => Err(ClientError::ConvexError {
+            data: serde_json::ser::to_string(&serde_json::Value::from(e.data)).unwrap(),
+        }),
+        FunctionResult::ErrorMessage(msg) => Err(ClientError::ServerError { msg }),
+    }
+}
+
+fn handle_value_function_result(result: FunctionResult) -> Result<ConvexValue, ClientError> {
+    match result {
+        FunctionResult::Value(v) => Ok(v.into()),
+        other => handle_direct_function_result(other).map(|_| ConvexValue::Null),
+    }
+}
+
+fn handle_binary_function_result(
+    result: FunctionResult,
+    format: BinaryFormat,
+) -> Result<Vec<u8>, ClientError> {
+    match result {
+        FunctionResult::Value(v) => Ok(binary::encode(format, v)),
+        other => handle_direct_function_result(other).map(|_| Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use convex::{FunctionResult, Value};
+    use maplit::btreemap;
+
+    use crate::{
+        catch_panic, convex_auth::ConvexAuth, handle_direct_function_result, parse_json_args,
+        parse_json_object_args, with_timeout, ClientError, MockConvexClient, TokenStore,
+    };
+
+    struct NoTokens;
+
+    #[async_trait::async_trait]
+    impl TokenStore for NoTokens {
+        async fn load(&self) -> Result<Option<String>, ClientError> {
+            Ok(None)
+        }
+
+        async fn save(&self, _material: String) -> Result<(), ClientError> {
+            Ok(())
+        }
+
+        async fn clear(&self) -> Result<(), ClientError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_boolean_values_in_json_args() {
+        let mut m = HashMap::new();
+        m.insert(String::from("a"), String::from("false"));
+
+        assert_eq!(
+            parse_json_args(m).unwrap().get(&String::from("a")),
+            Some(&Value::Boolean(false))
+        )
+    }
+
+    #[test]
+    fn test_number_values_in_json_args() {
+        let mut m = HashMap::new();
+        m.insert(String::from("a"), String::from("42"));
+        m.insert(String::from("b"), String::from("42.42"));
+
+        let result = parse_json_args(m).unwrap();
+        assert_eq!(result.get(&String::from("a")), Some(&Value::Float64(42.0)));
+        assert_eq!(result.get(&String::from("b")), Some(&Value::Float64(42.42)))
+    }
+
+    #[test]
+    fn test_list_values_in_json_args() {
+        let mut m = HashMap::new();
+        m.insert(String::from("a"), String::from("[1,2,3]"));
+        m.insert(String::from("b"), String::from("[\"a\",\"b\",\"c\"]"));
+
+        let result = parse_json_args(m).unwrap();
+        assert_eq!(
+            result.get(&String::from("a")),
+            Some(&Value::Array(vec![
+                Value::Float64(1.0),
+                Value::Float64(2.0),
+                Value::Float64(3.0)
+            ]))
+        );
+        assert_eq!(
+            result.get(&String::from("b")),
+            Some(&Value::Array(vec![
+                Value::String(String::from("a")),
+                Value::String(String::from("b")),
+                Value::String(String::from("c"))
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_object_values_in_json_args() {
+        let mut m = HashMap::new();
+        m.insert(String::from("a"), String::from("{\"a\":1,\"b\":\"foo\"}"));
+
+        let result = parse_json_args(m).unwrap();
+        assert_eq!(
+            result.get(&String::from("a")),
+            Some(&Value::Object(btreemap! {
+                String::from("a") => Value::Float64(1.0),
+                String::from("b") => Value::String(String::from("foo")),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_int64_values_in_json_args() {
+        let mut m = HashMap::new();
+        // 2^53 + 1, which a Float64 can't hold.
+        m.insert(
+            String::from("a"),
+            String::from(r#"{"$integer":"AQAAAAAAIAA="}"#),
+        );
+
+        let result = parse_json_args(m).unwrap();
+        assert_eq!(
+            result.get(&String::from("a")),
+            Some(&Value::Int64(9007199254740993))
+        );
+
+        let mut m = HashMap::new();
+        m.insert(String::from("a"), String::from("[9007199254740993]"));
+        assert!(matches!(
+            parse_json_args(m),
+            Err(ClientError::InvalidArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn test_special_floats_round_trip_through_json() {
+        for n in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, -0.0] {
+            let json =
+                handle_direct_function_result(FunctionResult::Value(Value::Float64(n))).unwrap();
+            assert!(json.contains("$float"), "{json}");
+
+            let mut m = HashMap::new();
+            m.insert(String::from("a"), json);
+            match parse_json_args(m).unwrap().remove("a") {
+                Some(Value::Float64(result)) => assert_eq!(result.to_bits(), n.to_bits()),
+                other => panic!("expected a Float64, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_panics_become_internal_errors() {
+        let result: Result<(), _> = futures::executor::block_on(catch_panic(async {
+            panic!("boom");
+        }));
+        match result {
+            Err(ClientError::InternalError { msg }) => assert_eq!(msg, "Panicked: boom"),
+            other => panic!("expected InternalError, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_invalid_json_args_name_the_key() {
+        let mut m = HashMap::new();
+        m.insert(String::from("a"), String::from("1"));
+        m.insert(String::from("b"), String::from("{not json"));
+
+        match parse_json_args(m) {
+            Err(ClientError::InvalidArgument { key, .. }) => assert_eq!(key, "b"),
+            other => panic!("expected InvalidArgument, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_json_object_args() {
+        let args =
+            parse_json_object_args(r#"{"a": 1, "b": "x", "c": {"$integer": "AQAAAAAAAAA="}}"#)
+                .unwrap();
+        assert_eq!(args["a"], Value::Float64(1.0));
+        assert_eq!(args["b"], Value::String("x".into()));
+        assert_eq!(args["c"], Value::Int64(1));
+
+        assert!(matches!(
+            parse_json_object_args("[1]"),
+            Err(ClientError::InvalidArgument { key, .. }) if key == "args"
+        ));
+        assert!(matches!(
+            parse_json_object_args(r#"{"a": 9007199254740993}"#),
+            Err(ClientError::InvalidArgument { key, .. }) if key == "a"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_args_point_at_the_offending_part() {
+        let reason = |args: &str| match parse_json_object_args(args) {
+            Err(ClientError::InvalidArgument { reason, .. }) => reason,
+            other => panic!("expected InvalidArgument, got {other:?}"),
+        };
+        assert_eq!(
+            reason(r#"{"a": {"items": [1, 9007199254740993]}}"#),
+            "Inexact number `9007199254740993` at .items[1]: integer out of the Float64-safe \
+             range, pass it as an $integer"
+        );
+        assert_eq!(
+            reason(r#"{"a": [1, {"n": {"$integer": 5}}]}"#),
+            "Invalid Convex value `{\"$integer\":5}` at [1].n: invalid type: integer `5`, \
+             expected a string"
+        );
+        assert!(reason(r#"{"a": tru}"#).starts_with("Invalid JSON near `{\"a\": tru}`"));
+
+        let m = HashMap::from([("b".to_string(), r#"{"$bytes": 1}"#.to_string())]);
+        match parse_json_args(m) {
+            Err(ClientError::InvalidArgument { key, reason }) => {
+                assert_eq!(key, "b");
+                assert!(
+                    reason.starts_with("Invalid Convex value `{\"$bytes\":1}`:"),
+                    "{reason}"
+                );
+            },
+            other => panic!("expected InvalidArgument, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_timeouts_work_outside_of_the_runtime() {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let result = futures::executor::block_on(with_timeout(
+            rt.handle(),
+            Some(10),
+            futures::future::pending::<Result<(), ClientError>>(),
+        ));
+        assert!(matches!(result, Err(ClientError::Timeout { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_convex_auth_sign_out_is_an_action_like_any_other() {
+        let mock = MockConvexClient::new();
+        mock.set_result("auth:signOut".into(), "null".into())
+            .unwrap();
+        let client = mock.client();
+        *client.convex_auth.lock() = Some(ConvexAuth::new(
+            client.connection.active_url(),
+            Arc::new(NoTokens),
+        ));
+
+        client.convex_auth_sign_out().await.unwrap();
+        // The mock only answers calls that take the same path as actions.
+        assert_eq!(mock.calls()[0].name, "auth:signOut");
+    }
+}
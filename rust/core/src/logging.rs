@@ -0,0 +1,59 @@
+//! The levels that clients log at.
+//!
+//! Logging itself is set up by the FFI crate, which consults
+//! [client_log_level] so each client can log at a level of its own.
+use std::cell::Cell;
+
+use tracing::Level;
+
+/// The severity of a log line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => Level::ERROR,
+            LogLevel::Warn => Level::WARN,
+            LogLevel::Info => Level::INFO,
+            LogLevel::Debug => Level::DEBUG,
+            LogLevel::Trace => Level::TRACE,
+        }
+    }
+}
+
+impl From<&Level> for LogLevel {
+    fn from(level: &Level) -> Self {
+        match *level {
+            Level::ERROR => LogLevel::Error,
+            Level::WARN => LogLevel::Warn,
+            Level::INFO => LogLevel::Info,
+            Level::DEBUG => LogLevel::Debug,
+            Level::TRACE => LogLevel::Trace,
+        }
+    }
+}
+
+thread_local! {
+    static CLIENT_LEVEL: Cell<Option<LogLevel>> = const { Cell::new(None) };
+}
+
+/// Makes `level` the [client_log_level] of the current thread, for as long as
+/// it runs.
+pub(crate) fn enter(level: Option<LogLevel>) {
+    CLIENT_LEVEL.set(level);
+}
+
+/// The [crate::ClientOptions::log_level] of the client that the current thread
+/// belongs to, if it's one of a client's runtime threads and that client has
+/// a level of its own.
+pub fn client_log_level() -> Option<LogLevel> {
+    CLIENT_LEVEL.get()
+}
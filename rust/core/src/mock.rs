@@ -0,0 +1,285 @@
+//! A client that serves canned results instead of talking to a backend, for
+//! the unit tests of host apps.
+//!
+//! [MockConvexClient::client] is a real [MobileConvexClient], so tests go
+//! through the same bindings, argument handling and subscription delivery as
+//! the app. Only what would reach the backend is answered by the mock, with
+//! the results set by the test or those of a session recorded with
+//! [MobileConvexClient::start_recording].
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use convex::{ConvexError, FunctionResult, Value};
+use futures::stream::{self, BoxStream, StreamExt};
+use parking_lot::Mutex;
+use tokio::sync::watch;
+
+use crate::{
+    json_value, recording::Replay, ClientError, FunctionCall, MobileConvexClient, StorageProvider,
+};
+
+/// The results that the functions of a [MockConvexClient] return, by name.
+#[derive(Default)]
+pub(crate) struct MockBackend {
+    results: Mutex<HashMap<String, watch::Sender<Option<FunctionResult>>>>,
+    calls: Mutex<Vec<FunctionCall>>,
+    replay: Mutex<Replay>,
+}
+
+impl MockBackend {
+    fn result(&self, name: &str) -> watch::Receiver<Option<FunctionResult>> {
+        self.results
+            .lock()
+            .entry(name.into())
+            .or_insert_with(|| watch::Sender::new(None))
+            .subscribe()
+    }
+
+    fn set(&self, name: String, result: FunctionResult) {
+        self.results
+            .lock()
+            .entry(name)
+            .or_insert_with(|| watch::Sender::new(None))
+            .send_replace(Some(result));
+    }
+
+    /// Answers a query, mutation or action with the replayed result for
+    /// `name` and `args`, or else the one set for `name`.
+    pub(crate) fn call(
+        &self,
+        name: String,
+        args: BTreeMap<String, Value>,
+    ) -> anyhow::Result<FunctionResult> {
+        let replayed = self.replay.lock().next_call(&name, &args);
+        let result = replayed.or_else(|| self.result(&name).borrow().clone());
+        self.calls.lock().push(FunctionCall {
+            name: name.clone(),
+            args: args
+                .into_iter()
+                .map(|(key, value)| (key, serde_json::Value::from(value).to_string()))
+                .collect(),
+        });
+        result.ok_or_else(|| {
+            ClientError::InternalError {
+                msg: format!("No mock result was set for {name}"),
+            }
+            .into()
+        })
+    }
+
+    /// The replayed updates for `name` and `args`, followed by the results
+    /// set for `name`, starting with the current one, if any.
+    pub(crate) fn subscribe(
+        &self,
+        name: &str,
+        args: &BTreeMap<String, Value>,
+    ) -> BoxStream<'static, FunctionResult> {
+        let replayed = self.replay.lock().updates(name, args);
+        let mut results = self.result(name);
+        results.mark_changed();
+        let results = stream::unfold(results, |mut results| async move {
+            loop {
+                results.changed().await.ok()?;
+                let result = results.borrow_and_update().clone();
+                if let Some(result) = result {
+                    return Some((result, results));
+                }
+            }
+        });
+        // Replayed updates come one at a time like the recorded ones did,
+        // rather than being conflated into the last.
+        let replayed = stream::iter(replayed).then(|result| async {
+            tokio::task::yield_now().await;
+            result
+        });
+        replayed.chain(results).boxed()
+    }
+}
+
+/// A [MobileConvexClient] for tests whose functions return the results set
+/// here, without a backend.
+///
+/// Calls to a function that has no result fail, and subscriptions to it wait
+/// until it gets one. Setting a result again delivers it to the
+/// subscriptions, which is how tests script their updates.
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct MockConvexClient {
+    backend: Arc<MockBackend>,
+    client: Arc<MobileConvexClient>,
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl MockConvexClient {
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn new() -> Self {
+        let backend = Arc::new(MockBackend::default());
+        MockConvexClient {
+            client: Arc::new(MobileConvexClient::mocked(backend.clone())),
+            backend,
+        }
+    }
+
+    /// The client to hand to the code under test.
+    pub fn client(&self) -> Arc<MobileConvexClient> {
+        self.client.clone()
+    }
+
+    /// Makes the function `name` return the JSON encoded `value`.
+    pub fn set_result(&self, name: String, value: String) -> Result<(), ClientError> {
+        let value = json_value(&value).map_err(|e| ClientError::InvalidArgument {
+            key: "value".into(),
+            reason: e.to_string(),
+        })?;
+        self.backend.set(name, FunctionResult::Value(value));
+        Ok(())
+    }
+
+    /// Makes the function `name` fail with `message`, as a `ConvexError` with
+    /// the JSON encoded `data` if there is some.
+    pub fn set_error(
+        &self,
+        name: String,
+        message: String,
+        data: Option<String>,
+    ) -> Result<(), ClientError> {
+        let result = match data {
+            Some(data) => FunctionResult::ConvexError(ConvexError {
+                message,
+                data: json_value(&data).map_err(|e| ClientError::InvalidArgument {
+                    key: "data".into(),
+                    reason: e.to_string(),
+                })?,
+            }),
+            None => FunctionResult::ErrorMessage(message),
+        };
+        self.backend.set(name, result);
+        Ok(())
+    }
+
+    /// Answers with the results of the session recorded under `key` in
+    /// `storage`, instead of those set here.
+    ///
+    /// Calls get the recorded results of the same function and args in the
+    /// order they were recorded, with the last one repeating, and
+    /// subscriptions get all of the recorded updates. Functions that weren't
+    /// recorded fall back to the results set here.
+    pub async fn replay(
+        &self,
+        storage: Arc<dyn StorageProvider>,
+        key: String,
+    ) -> Result<(), ClientError> {
+        let document =
+            storage
+                .get(key.clone())
+                .await?
+                .ok_or_else(|| ClientError::InvalidArgument {
+                    key: "key".into(),
+                    reason: format!("Nothing was recorded under {key}"),
+                })?;
+        *self.backend.replay.lock() = Replay::parse(&document)?;
+        Ok(())
+    }
+
+    /// The queries, mutations and actions that were called, oldest first.
+    pub fn calls(&self) -> Vec<FunctionCall> {
+        self.backend.calls.lock().clone()
+    }
+}
+
+impl Default for MockConvexClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use tokio::sync::mpsc;
+
+    use super::MockConvexClient;
+    use crate::{ClientError, QuerySubscriber};
+
+    struct Updates(mpsc::UnboundedSender<String>);
+
+    #[async_trait::async_trait]
+    impl QuerySubscriber for Updates {
+        async fn on_update(&self, value: String, _is_stale: bool) {
+            let _ = self.0.send(value);
+        }
+
+        async fn on_error(&self, message: String, _data: Option<String>) {
+            let _ = self.0.send(message);
+        }
+
+        async fn on_subscribed(&self) {}
+
+        async fn on_resubscribed(&self) {}
+    }
+
+    #[tokio::test]
+    async fn test_mock_serves_results_and_records_calls() {
+        let mock = MockConvexClient::new();
+        let client = mock.client();
+        let args = HashMap::from([("body".to_string(), "\"hi\"".to_string())]);
+        assert!(matches!(
+            client
+                .mutation("messages:send".into(), args.clone(), None)
+                .await,
+            Err(ClientError::InternalError { .. })
+        ));
+
+        mock.set_result("messages:send".into(), "null".into())
+            .unwrap();
+        assert_eq!(
+            client
+                .mutation("messages:send".into(), args, None)
+                .await
+                .unwrap(),
+            "null"
+        );
+        mock.set_error(
+            "messages:send".into(),
+            "Too long".into(),
+            Some("{\"max\":1}".into()),
+        )
+        .unwrap();
+        assert!(matches!(
+            client
+                .mutation("messages:send".into(), HashMap::new(), None)
+                .await,
+            Err(ClientError::ConvexError { .. })
+        ));
+        let calls = mock.calls();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[0].args["body"], "\"hi\"");
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_get_every_result_that_is_set() {
+        let mock = MockConvexClient::new();
+        let (sender, mut updates) = mpsc::unbounded_channel();
+        let _handle = mock
+            .client()
+            .subscribe(
+                "messages:list".into(),
+                HashMap::new(),
+                Arc::new(Updates(sender)),
+            )
+            .await
+            .unwrap();
+
+        mock.set_result("messages:list".into(), "[]".into())
+            .unwrap();
+        assert_eq!(updates.recv().await.unwrap(), "[]");
+        mock.set_result("messages:list".into(), "[\"hi\"]".into())
+            .unwrap();
+        assert_eq!(updates.recv().await.unwrap(), "[\"hi\"]");
+        mock.set_error("messages:list".into(), "Boom".into(), None)
+            .unwrap();
+        assert_eq!(updates.recv().await.unwrap(), "Boom");
+    }
+}
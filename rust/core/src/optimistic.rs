@@ -0,0 +1,189 @@
+//! Optimistic updates of query subscriptions while a mutation is in flight.
+//!
+//! Before the mutation is sent, the host's [OptimisticUpdater] gets to patch
+//! the current result of every live subscription, which includes the patches
+//! of the mutations that are still in flight. Patched subscriptions hold
+//! back results from the backend until the mutation completes, at which point
+//! they go back to the latest backend result. By then that result already
+//! reflects the mutation, because the underlying client only completes a
+//! mutation once the backend has sent the transition that includes it.
+use std::collections::HashMap;
+
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+
+/// Produces optimistic results for queries affected by a mutation.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait OptimisticUpdater: Send + Sync {
+    /// Returns the optimistic result of the query `name` with `args` (both
+    /// JSON encoded), given its `current` result, or [None] to leave it alone.
+    async fn update_query(
+        &self,
+        name: String,
+        args: String,
+        current: Option<String>,
+    ) -> Option<String>;
+}
+
+/// A change to the result of a subscription.
+#[derive(Debug)]
+pub(crate) enum Patch {
+    /// Deliver `value` in place of backend results.
+    Apply(String),
+    /// The mutation behind an earlier [Patch::Apply] completed.
+    Release,
+}
+
+struct Query {
+    name: String,
+    args: String,
+    latest: Option<String>,
+    /// The last patched value, while any mutation is still patching it.
+    patched: Option<String>,
+    /// How many mutations are patching it.
+    held: usize,
+    patches: mpsc::UnboundedSender<Patch>,
+}
+
+/// A live subscription as seen by an [OptimisticUpdater].
+pub(crate) struct QuerySnapshot {
+    pub(crate) id: u64,
+    pub(crate) name: String,
+    pub(crate) args: String,
+    /// What the subscriber is seeing, patched or from the backend.
+    pub(crate) current: Option<String>,
+}
+
+/// The live subscriptions that optimistic updates can apply to.
+#[derive(Default)]
+pub(crate) struct OptimisticQueries {
+    queries: Mutex<HashMap<u64, Query>>,
+}
+
+impl OptimisticQueries {
+    /// Tracks the subscription `id`, returning the receiver of its patches.
+    pub(crate) fn register(
+        &self,
+        id: u64,
+        name: String,
+        args: String,
+    ) -> mpsc::UnboundedReceiver<Patch> {
+        let (patches, receiver) = mpsc::unbounded_channel();
+        self.queries.lock().insert(
+            id,
+            Query {
+                name,
+                args,
+                latest: None,
+                patched: None,
+                held: 0,
+                patches,
+            },
+        );
+        receiver
+    }
+
+    pub(crate) fn unregister(&self, id: u64) {
+        self.queries.lock().remove(&id);
+    }
+
+    /// Records the latest result of the subscription `id` from the backend.
+    pub(crate) fn set_latest(&self, id: u64, value: String) {
+        if let Some(query) = self.queries.lock().get_mut(&id) {
+            query.latest = Some(value);
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<QuerySnapshot> {
+        self.queries
+            .lock()
+            .iter()
+            .map(|(id, query)| QuerySnapshot {
+                id: *id,
+                name: query.name.clone(),
+                args: query.args.clone(),
+                current: query.patched.clone().or_else(|| query.latest.clone()),
+            })
+            .collect()
+    }
+
+    pub(crate) fn send(&self, id: u64, patch: Patch) {
+        if let Some(query) = self.queries.lock().get_mut(&id) {
+            match &patch {
+                Patch::Apply(value) => {
+                    query.patched = Some(value.clone());
+                    query.held += 1;
+                },
+                Patch::Release => {
+                    query.held = query.held.saturating_sub(1);
+                    if query.held == 0 {
+                        query.patched = None;
+                    }
+                },
+            }
+            let _ = query.patches.send(patch);
+        }
+    }
+}
+
+/// What a subscription is showing, given the patches applied to it.
+#[derive(Default)]
+pub(crate) struct PatchState {
+    /// How many mutations are still patching the subscription.
+    held: usize,
+}
+
+impl PatchState {
+    /// Whether results from the backend are being held back.
+    pub(crate) fn is_held(&self) -> bool {
+        self.held > 0
+    }
+
+    /// Applies `patch`, returning whether the latest backend result should be
+    /// delivered again because the last patch was released.
+    pub(crate) fn apply(&mut self, patch: &Patch) -> bool {
+        match patch {
+            Patch::Apply(_) => {
+                self.held += 1;
+                false
+            },
+            Patch::Release => {
+                self.held = self.held.saturating_sub(1);
+                self.held == 0
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OptimisticQueries, Patch, PatchState};
+
+    #[test]
+    fn test_backend_results_are_restored_after_the_last_release() {
+        let mut state = PatchState::default();
+        assert!(!state.apply(&Patch::Apply("a".into())));
+        assert!(!state.apply(&Patch::Apply("b".into())));
+        assert!(!state.apply(&Patch::Release));
+        assert!(state.is_held());
+        assert!(state.apply(&Patch::Release));
+        assert!(!state.is_held());
+    }
+
+    #[test]
+    fn test_updates_build_on_the_patches_in_flight() {
+        let queries = OptimisticQueries::default();
+        let _patches = queries.register(1, "messages:list".into(), "{}".into());
+        queries.set_latest(1, "[]".into());
+        queries.send(1, Patch::Apply("[\"a\"]".into()));
+        let current = |queries: &OptimisticQueries| queries.snapshot().remove(0).current;
+        assert_eq!(current(&queries).as_deref(), Some("[\"a\"]"));
+
+        queries.send(1, Patch::Apply("[\"a\",\"b\"]".into()));
+        queries.send(1, Patch::Release);
+        assert_eq!(current(&queries).as_deref(), Some("[\"a\",\"b\"]"));
+        queries.send(1, Patch::Release);
+        assert_eq!(current(&queries).as_deref(), Some("[]"));
+    }
+}
@@ -0,0 +1,215 @@
+//! Settings for tuning a [crate::MobileConvexClient] when it's created.
+use std::{sync::OnceLock, thread::JoinHandle, time::Duration};
+
+use tokio::{
+    runtime::{Builder, Handle, Runtime},
+    sync::oneshot,
+};
+
+use crate::{events::Origin, logging, LogLevel};
+
+/// How long [OwnedRuntime::shutdown] waits for tasks to stop at an await
+/// point before leaving them behind.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Options for [crate::MobileConvexClient::with_options]. The default of every
+/// option is what [crate::MobileConvexClient::new] uses.
+///
+/// The delay between reconnection attempts isn't one of them: the underlying
+/// client backs off exponentially with jitter from 100ms up to 15s, and
+/// doesn't take a policy of its own.
+///
+/// Neither is a keepalive interval. The client doesn't ping the backend; the
+/// backend pings the client, on a schedule of its own, which is what keeps the
+/// connection from looking idle to NATs along the way.
+///
+/// Nor are extra root certificates. The WebSocket trusts the Mozilla roots
+/// bundled with the underlying client, which doesn't take any others, and
+/// HTTP requests trust the same ones so both work on the same networks.
+///
+/// Nor is an HTTP(S) or SOCKS5 proxy: the underlying client opens its
+/// WebSocket straight to the deployment, ignoring the system proxy settings.
+/// A VPN that routes all of the app's traffic does work.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct ClientOptions {
+    /// The number of worker threads of the client's runtime. Defaults to one
+    /// per CPU core.
+    #[cfg_attr(feature = "uniffi", uniffi(default = None))]
+    pub worker_threads: Option<u32>,
+    /// How long calls wait for the WebSocket to connect before failing with a
+    /// retryable [crate::ClientError::NetworkError]. By default they wait
+    /// until it connects.
+    #[cfg_attr(feature = "uniffi", uniffi(default = None))]
+    pub connect_timeout_ms: Option<u64>,
+    /// A name for the client's worker threads, to tell apart the threads of
+    /// several clients in profilers and crash reports.
+    #[cfg_attr(feature = "uniffi", uniffi(default = None))]
+    pub client_label: Option<String>,
+    /// Run the client on a runtime shared by every client in the process that
+    /// sets this, instead of giving it a thread pool of its own. The shared
+    /// runtime has the default number of threads, so `worker_threads` and
+    /// `client_label` don't apply to it.
+    #[cfg_attr(feature = "uniffi", uniffi(default = false))]
+    pub use_shared_runtime: bool,
+    /// Run the client on a single-threaded runtime, driven by one thread of
+    /// its own, for memory-constrained devices that only exchange a few
+    /// messages a second. `worker_threads` doesn't apply to it.
+    #[cfg_attr(feature = "uniffi", uniffi(default = false))]
+    pub single_threaded: bool,
+    /// How many connection attempts may fail in a row before the client gives
+    /// up and goes into [crate::ConnectionState::Failed], so the app can
+    /// switch to an offline mode. By default it keeps trying forever.
+    #[cfg_attr(feature = "uniffi", uniffi(default = None))]
+    pub max_connection_attempts: Option<u32>,
+    /// Refuse to connect to deployment URLs without TLS, unless they're on
+    /// the device itself. By default `http://` and `ws://` URLs work for any
+    /// host, like a LAN IP or the emulator's `10.0.2.2` when developing
+    /// against a local backend, so set this in release builds to fail fast on
+    /// a misconfigured URL instead.
+    #[cfg_attr(feature = "uniffi", uniffi(default = false))]
+    pub require_encrypted_connections: bool,
+    /// The most verbose level that's logged on the client's threads, instead
+    /// of the level set for the whole process, e.g. to debug one client among
+    /// several. It doesn't apply to the shared runtime, whose threads don't
+    /// belong to any one client.
+    #[cfg_attr(feature = "uniffi", uniffi(default = None))]
+    pub log_level: Option<LogLevel>,
+}
+
+impl ClientOptions {
+    pub(crate) fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout_ms.map(Duration::from_millis)
+    }
+
+    /// The handle of the runtime that the client should run on, along with
+    /// that runtime if the client owns it, whose threads are then marked with
+    /// `origin`.
+    pub(crate) fn runtime(
+        &self,
+        origin: Origin,
+    ) -> std::io::Result<(Handle, Option<OwnedRuntime>)> {
+        if self.use_shared_runtime {
+            return Ok((shared_runtime().handle().clone(), None));
+        }
+        if self.single_threaded {
+            let runtime = Builder::new_current_thread().enable_all().build()?;
+            let handle = runtime.handle().clone();
+            let (stop, stopped) = oneshot::channel::<()>();
+            let log_level = self.log_level;
+            let thread = std::thread::Builder::new()
+                .name(self.thread_name())
+                .spawn(move || {
+                    origin.enter();
+                    logging::enter(log_level);
+                    let _ = runtime.block_on(stopped);
+                })?;
+            return Ok((handle, Some(OwnedRuntime::CurrentThread { stop, thread })));
+        }
+        let log_level = self.log_level;
+        let mut builder = Builder::new_multi_thread();
+        builder
+            .enable_all()
+            .thread_name(self.thread_name())
+            .on_thread_start(move || {
+                origin.enter();
+                logging::enter(log_level);
+            });
+        if let Some(worker_threads) = self.worker_threads {
+            builder.worker_threads(worker_threads.max(1) as usize);
+        }
+        let runtime = builder.build()?;
+        Ok((
+            runtime.handle().clone(),
+            Some(OwnedRuntime::MultiThread(runtime)),
+        ))
+    }
+
+    fn thread_name(&self) -> String {
+        match &self.client_label {
+            Some(label) => format!("convex-{label}"),
+            None => "convex".into(),
+        }
+    }
+}
+
+/// A runtime that belongs to a single client.
+pub(crate) enum OwnedRuntime {
+    MultiThread(Runtime),
+    /// A current-thread runtime, run on `thread` until `stop` is sent.
+    CurrentThread {
+        stop: oneshot::Sender<()>,
+        thread: JoinHandle<()>,
+    },
+}
+
+impl OwnedRuntime {
+    /// Stops the runtime, dropping its tasks.
+    ///
+    /// Waiting for that would panic or deadlock if this is the last reference
+    /// to the client going away on one of its own tasks, so in async contexts
+    /// it's left to happen in the background.
+    pub(crate) fn shutdown(self) {
+        let in_async_context = Handle::try_current().is_ok();
+        match self {
+            OwnedRuntime::MultiThread(runtime) if in_async_context => runtime.shutdown_background(),
+            OwnedRuntime::MultiThread(runtime) => runtime.shutdown_timeout(SHUTDOWN_TIMEOUT),
+            OwnedRuntime::CurrentThread { stop, thread } => {
+                let _ = stop.send(());
+                if !in_async_context {
+                    let _ = thread.join();
+                }
+            },
+        }
+    }
+}
+
+/// The runtime for clients with [ClientOptions::use_shared_runtime], which is
+/// created for the first one and lives as long as the process.
+fn shared_runtime() -> &'static Runtime {
+    static SHARED: OnceLock<Runtime> = OnceLock::new();
+    SHARED.get_or_init(|| {
+        Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("convex-shared")
+            .build()
+            .unwrap()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClientOptions;
+    use crate::{client_log_level, events::Origin, LogLevel};
+
+    #[test]
+    fn test_single_threaded_runtime_runs_tasks_until_shut_down() {
+        let options = ClientOptions {
+            single_threaded: true,
+            ..Default::default()
+        };
+        let (handle, runtime) = options.runtime(Origin::new()).unwrap();
+        let task = handle.spawn(async { 1 + 1 });
+        assert_eq!(futures::executor::block_on(task).unwrap(), 2);
+
+        runtime.unwrap().shutdown();
+        assert!(futures::executor::block_on(handle.spawn(async {})).is_err());
+    }
+
+    #[test]
+    fn test_the_client_log_level_applies_on_the_client_threads() {
+        let options = ClientOptions {
+            worker_threads: Some(1),
+            log_level: Some(LogLevel::Debug),
+            ..Default::default()
+        };
+        let (handle, runtime) = options.runtime(Origin::new()).unwrap();
+        let task = handle.spawn(async { client_log_level() });
+        assert_eq!(
+            futures::executor::block_on(task).unwrap(),
+            Some(LogLevel::Debug)
+        );
+        assert_eq!(client_log_level(), None);
+        runtime.unwrap().shutdown();
+    }
+}
@@ -0,0 +1,253 @@
+//! A persistent queue of mutations that get run in order once connected.
+//!
+//! Queued mutations are saved to the host's [StorageProvider] before they're
+//! acknowledged, so they survive the app being killed while offline and get
+//! replayed on the next launch. They run at least once: if the app dies after
+//! a mutation ran but before its result arrived, it runs again.
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+};
+
+use convex::{FunctionResult, Value};
+use parking_lot::Mutex;
+use tokio::sync::{watch, Notify};
+use tracing::{debug, warn};
+
+use crate::{
+    connection::{Connection, ConnectionState},
+    ClientError, StorageProvider,
+};
+
+/// The key of the [StorageProvider] entry that holds the queue.
+const OUTBOX_KEY: &str = "convex:outbox";
+
+/// Gets told how each queued mutation turned out.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait MutationOutboxListener: Send + Sync {
+    async fn on_mutation_succeeded(&self, id: String, value: String) -> ();
+
+    async fn on_mutation_failed(&self, id: String, message: String, data: Option<String>) -> ();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    id: String,
+    name: String,
+    args: BTreeMap<String, Value>,
+}
+
+impl Entry {
+    fn to_json(&self) -> serde_json::Value {
+        let args: serde_json::Map<_, _> = self
+            .args
+            .iter()
+            .map(|(k, v)| (k.clone(), serde_json::Value::from(v.clone())))
+            .collect();
+        serde_json::json!({ "id": self.id, "name": self.name, "args": args })
+    }
+
+    fn from_json(json: &serde_json::Value) -> anyhow::Result<Self> {
+        let field = |name: &str| {
+            json.get(name)
+                .and_then(|v| v.as_str())
+                .map(str::to_owned)
+                .ok_or_else(|| anyhow::anyhow!("Outbox entry has no {name}"))
+        };
+        let args = json
+            .get("args")
+            .and_then(|args| args.as_object())
+            .ok_or_else(|| anyhow::anyhow!("Outbox entry has no args"))?
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), Value::try_from(v.clone())?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Entry {
+            id: field("id")?,
+            name: field("name")?,
+            args,
+        })
+    }
+}
+
+fn encode(entries: &[Entry]) -> String {
+    serde_json::Value::Array(entries.iter().map(Entry::to_json).collect()).to_string()
+}
+
+fn decode(stored: &str) -> anyhow::Result<Vec<Entry>> {
+    let json: serde_json::Value = serde_json::from_str(stored)?;
+    json.as_array()
+        .ok_or_else(|| anyhow::anyhow!("Outbox isn't an array"))?
+        .iter()
+        .map(Entry::from_json)
+        .collect()
+}
+
+struct Enabled {
+    storage: Arc<dyn StorageProvider>,
+    listener: Arc<dyn MutationOutboxListener>,
+}
+
+#[derive(Default)]
+pub(crate) struct Outbox {
+    enabled: Mutex<Option<Enabled>>,
+    /// Held while the queue is modified and saved, so saves happen in order.
+    entries: tokio::sync::Mutex<Vec<Entry>>,
+    /// Wakes up [replay] when there's something new to run.
+    kick: Notify,
+}
+
+impl Outbox {
+    /// Turns on the outbox, loading the mutations that were queued before the
+    /// app was last stopped.
+    pub(crate) async fn enable(
+        &self,
+        storage: Arc<dyn StorageProvider>,
+        listener: Arc<dyn MutationOutboxListener>,
+    ) -> Result<(), ClientError> {
+        let mut entries = self.entries.lock().await;
+        let stored = match storage.get(OUTBOX_KEY.to_string()).await? {
+            Some(stored) => decode(&stored)?,
+            None => vec![],
+        };
+        if !stored.is_empty() {
+            debug!("Restored {} queued mutations", stored.len());
+        }
+        *entries = stored;
+        *self.enabled.lock() = Some(Enabled { storage, listener });
+        self.kick.notify_one();
+        Ok(())
+    }
+
+    /// Queues the mutation `name` with `args`, returning the ID that the
+    /// listener will be told its result under.
+    pub(crate) async fn enqueue(
+        &self,
+        name: String,
+        args: BTreeMap<String, Value>,
+    ) -> Result<String, ClientError> {
+        let storage = self.storage().ok_or_else(|| ClientError::InternalError {
+            msg: "The mutation outbox isn't enabled".into(),
+        })?;
+        let mut entries = self.entries.lock().await;
+        let id = uuid::Uuid::new_v4().to_string();
+        entries.push(Entry {
+            id: id.clone(),
+            name,
+            args,
+        });
+        if let Err(e) = storage.put(OUTBOX_KEY.to_string(), encode(&entries)).await {
+            entries.pop();
+            return Err(e);
+        }
+        self.kick.notify_one();
+        Ok(id)
+    }
+
+    fn storage(&self) -> Option<Arc<dyn StorageProvider>> {
+        self.enabled.lock().as_ref().map(|e| e.storage.clone())
+    }
+
+    fn listener(&self) -> Option<Arc<dyn MutationOutboxListener>> {
+        self.enabled.lock().as_ref().map(|e| e.listener.clone())
+    }
+
+    /// Removes the mutation that just ran from the front of the queue.
+    async fn pop(&self, id: &str) {
+        let mut entries = self.entries.lock().await;
+        if entries.first().is_some_and(|entry| entry.id == id) {
+            entries.remove(0);
+        }
+        if let Some(storage) = self.storage() {
+            if let Err(e) = storage.put(OUTBOX_KEY.to_string(), encode(&entries)).await {
+                warn!("Failed to save the mutation outbox: {e}");
+            }
+        }
+    }
+}
+
+/// Runs the queued mutations of `outbox` one at a time, in order, whenever
+/// `connection` is connected.
+pub(crate) async fn replay(
+    outbox: Weak<Outbox>,
+    connection: Weak<Connection>,
+    mut states: watch::Receiver<ConnectionState>,
+) {
+    loop {
+        let Some(current) = outbox.upgrade() else {
+            break;
+        };
+        let connected = *states.borrow_and_update() == ConnectionState::Connected;
+        let next = current.entries.lock().await.first().cloned();
+        let client = connection.upgrade().and_then(|c| c.current());
+        if let (true, Some(entry), Some(mut client), Some(listener)) =
+            (connected, next, client, current.listener())
+        {
+            match client.mutation(&entry.name, entry.args.clone()).await {
+                Ok(result) => {
+                    current.pop(&entry.id).await;
+                    deliver(listener.as_ref(), entry.id, result).await;
+                    continue;
+                },
+                // Try again once the connection comes back.
+                Err(e) => warn!("Queued mutation {} failed to run: {e}", entry.name),
+            }
+        }
+        let kicked = current.kick.notified();
+        tokio::select! {
+            _ = kicked => {},
+            changed = states.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+            },
+        }
+    }
+}
+
+async fn deliver(listener: &dyn MutationOutboxListener, id: String, result: FunctionResult) {
+    match result {
+        FunctionResult::Value(value) => {
+            listener
+                .on_mutation_succeeded(id, serde_json::Value::from(value).to_string())
+                .await
+        },
+        FunctionResult::ErrorMessage(message) => {
+            listener.on_mutation_failed(id, message, None).await
+        },
+        FunctionResult::ConvexError(error) => {
+            listener
+                .on_mutation_failed(
+                    id,
+                    error.message,
+                    Some(serde_json::Value::from(error.data).to_string()),
+                )
+                .await
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use convex::Value;
+    use maplit::btreemap;
+
+    use super::{decode, encode, Entry};
+
+    #[test]
+    fn test_entries_round_trip_through_storage() {
+        let entries = vec![
+            Entry {
+                id: "1".into(),
+                name: "messages:send".into(),
+                args: btreemap! { String::from("body") => Value::String("hi".into()) },
+            },
+            Entry {
+                id: "2".into(),
+                name: "messages:clear".into(),
+                args: btreemap! {},
+            },
+        ];
+        assert_eq!(decode(&encode(&entries)).unwrap(), entries);
+    }
+}
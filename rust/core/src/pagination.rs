@@ -0,0 +1,460 @@
+//! Subscriptions to paginated queries, like `usePaginatedQuery` in React.
+//!
+//! Each page is its own query subscription, passing the `continueCursor` of
+//! the previous page as the `cursor` of its `paginationOpts`. Once the page
+//! after it starts loading, a page is resubscribed with that cursor as its
+//! `endCursor`, so items that are inserted or deleted later don't shift the
+//! boundary between them. The results of the loaded pages are merged into one
+//! list for the host.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Weak},
+};
+
+use parking_lot::Mutex;
+use tracing::debug;
+
+use crate::{
+    catch_panic,
+    latest::{self, LatestSender},
+    ClientError, MobileConvexClient, QuerySubscriber, Subscriber, SubscriptionHandle,
+    SubscriptionOptions,
+};
+
+/// The argument that paginated queries take their page options in.
+const PAGINATION_OPTS_ARG: &str = "paginationOpts";
+
+/// Where a [PaginatedQuerySubscriber]'s results stand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum PaginationStatus {
+    LoadingFirstPage,
+    /// More pages can be loaded with [PaginatedSubscription::load_more].
+    CanLoadMore,
+    LoadingMore,
+    /// All pages have been loaded.
+    Exhausted,
+}
+
+/// Receives the merged results of all loaded pages of a paginated query.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait PaginatedQuerySubscriber: Send + Sync {
+    /// Gets the JSON encoded array of the items of all loaded pages, in order.
+    async fn on_update(&self, items: String, status: PaginationStatus) -> ();
+
+    async fn on_error(&self, message: String, value: Option<String>) -> ();
+}
+
+struct PageResult {
+    items: Vec<serde_json::Value>,
+    is_done: bool,
+    continue_cursor: String,
+}
+
+impl PageResult {
+    fn parse(value: &str) -> anyhow::Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(value)?;
+        let items = value
+            .get("page")
+            .and_then(|page| page.as_array())
+            .ok_or_else(|| anyhow::anyhow!("Paginated query result has no page"))?
+            .clone();
+        Ok(PageResult {
+            items,
+            is_done: value
+                .get("isDone")
+                .and_then(|d| d.as_bool())
+                .unwrap_or(true),
+            continue_cursor: value
+                .get("continueCursor")
+                .and_then(|c| c.as_str())
+                .unwrap_or_default()
+                .to_owned(),
+        })
+    }
+}
+
+#[derive(Default)]
+struct Page {
+    /// Where the page starts. It's `None` for the first page, and for later
+    /// pages that wait for the page before them to load.
+    cursor: Option<String>,
+    /// Where the page ends, once the page after it started loading.
+    end_cursor: Option<String>,
+    num_items: u32,
+    handle: Option<Arc<SubscriptionHandle>>,
+    result: Option<PageResult>,
+}
+
+impl Page {
+    fn cancel(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.cancel();
+        }
+    }
+}
+
+enum Delivery {
+    Update(String, PaginationStatus),
+    Error(String, Option<String>),
+}
+
+struct Paginator {
+    name: String,
+    args: HashMap<String, String>,
+    client: Weak<MobileConvexClient>,
+    pages: Mutex<Vec<Page>>,
+    host: LatestSender<Delivery>,
+}
+
+impl Paginator {
+    fn page_args(
+        &self,
+        num_items: u32,
+        cursor: Option<&str>,
+        end_cursor: Option<&str>,
+    ) -> HashMap<String, String> {
+        let mut opts = serde_json::json!({ "numItems": num_items, "cursor": cursor });
+        if let Some(end_cursor) = end_cursor {
+            opts["endCursor"] = end_cursor.into();
+        }
+        let mut args = self.args.clone();
+        args.insert(PAGINATION_OPTS_ARG.to_string(), opts.to_string());
+        args
+    }
+
+    /// Subscribes to the page at `index` between its current cursors,
+    /// replacing the subscription it had.
+    async fn subscribe_page(
+        self: &Arc<Self>,
+        client: &MobileConvexClient,
+        index: usize,
+    ) -> Result<(), ClientError> {
+        let Some((cursor, end_cursor, num_items)) = self
+            .pages
+            .lock()
+            .get(index)
+            .map(|page| (page.cursor.clone(), page.end_cursor.clone(), page.num_items))
+        else {
+            return Ok(());
+        };
+        let args = client.function_args(self.page_args(
+            num_items,
+            cursor.as_deref(),
+            end_cursor.as_deref(),
+        ))?;
+        let subscriber = Subscriber::Json(Arc::new(PageSubscriber {
+            paginator: Arc::downgrade(self),
+            index,
+            cursor: cursor.clone(),
+            end_cursor: end_cursor.clone(),
+        }));
+        let handle = client
+            .internal_subscribe(
+                self.name.clone(),
+                args,
+                SubscriptionOptions::default(),
+                subscriber,
+            )
+            .await?;
+        match self.pages.lock().get_mut(index) {
+            Some(page) if page.cursor == cursor && page.end_cursor == end_cursor => {
+                if let Some(previous) = page.handle.replace(handle) {
+                    previous.cancel();
+                }
+            },
+            // Cancelled or restarted while subscribing.
+            _ => handle.cancel(),
+        }
+        Ok(())
+    }
+
+    fn on_page(self: &Arc<Self>, subscriber: &PageSubscriber, value: &str) {
+        let result = match PageResult::parse(value) {
+            Ok(result) => result,
+            Err(e) => {
+                self.host.send(Delivery::Error(e.to_string(), None));
+                return;
+            },
+        };
+        let index = subscriber.index;
+        let mut pages = self.pages.lock();
+        let continue_cursor = result.continue_cursor.clone();
+        match pages.get_mut(index) {
+            Some(page)
+                if page.cursor == subscriber.cursor && page.end_cursor == subscriber.end_cursor =>
+            {
+                page.result = Some(result);
+            },
+            // From a subscription that was replaced by one between other cursors.
+            _ => return,
+        }
+        let mut restart = vec![];
+        if let Some(next) = pages.get(index + 1) {
+            if next.cursor.as_ref() != Some(&continue_cursor) {
+                if next.cursor.is_some() {
+                    debug!(
+                        "Page {index} of {} moved its end, restarting the pages after it",
+                        self.name
+                    );
+                    for page in &mut pages[index + 1..] {
+                        page.cancel();
+                        page.cursor = None;
+                        page.end_cursor = None;
+                        page.result = None;
+                    }
+                }
+                restart = start_after(&mut pages, index);
+            }
+        }
+        let (items, status) = merge(&pages);
+        self.host.send(Delivery::Update(items, status));
+        drop(pages);
+
+        let Some(client) = self.client.upgrade() else {
+            return;
+        };
+        if restart.is_empty() {
+            return;
+        }
+        let paginator = self.clone();
+        client.rt.clone().spawn(async move {
+            for index in restart {
+                if let Err(e) = paginator.subscribe_page(&client, index).await {
+                    paginator.host.send(Delivery::Error(e.to_string(), None));
+                    return;
+                }
+            }
+        });
+    }
+}
+
+/// Starts the page after the one at `index` where that one ends now, and pins
+/// its end there. Returns the pages that need to be (re)subscribed, in order.
+fn start_after(pages: &mut Vec<Page>, index: usize) -> Vec<usize> {
+    let Some(result) = &pages[index].result else {
+        return vec![];
+    };
+    if result.is_done {
+        for mut page in pages.drain(index + 1..) {
+            page.cancel();
+        }
+        return vec![];
+    }
+    let cursor = Some(result.continue_cursor.clone());
+    let mut subscribe = vec![];
+    if pages[index].end_cursor != cursor {
+        pages[index].end_cursor = cursor.clone();
+        subscribe.push(index);
+    }
+    if let Some(next) = pages.get_mut(index + 1) {
+        next.cursor = cursor;
+        subscribe.push(index + 1);
+    }
+    subscribe
+}
+
+/// The items of the pages that have loaded so far, in order, and the status
+/// of the pagination. A page that doesn't start where the page before it
+/// ends is still being restarted and counts as loading.
+fn merge(pages: &[Page]) -> (String, PaginationStatus) {
+    let mut items = vec![];
+    let mut status = PaginationStatus::LoadingFirstPage;
+    let mut end_cursor = None;
+    for (index, page) in pages.iter().enumerate() {
+        match &page.result {
+            Some(result) if index == 0 || page.cursor.as_ref() == end_cursor => {
+                items.extend(result.items.iter().cloned());
+                status = if result.is_done {
+                    PaginationStatus::Exhausted
+                } else {
+                    PaginationStatus::CanLoadMore
+                };
+                end_cursor = Some(&result.continue_cursor);
+            },
+            _ => {
+                if status != PaginationStatus::LoadingFirstPage {
+                    status = PaginationStatus::LoadingMore;
+                }
+                break;
+            },
+        }
+    }
+    (serde_json::Value::Array(items).to_string(), status)
+}
+
+struct PageSubscriber {
+    paginator: Weak<Paginator>,
+    index: usize,
+    cursor: Option<String>,
+    end_cursor: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl QuerySubscriber for PageSubscriber {
+    async fn on_update(&self, value: String, _is_stale: bool) {
+        if let Some(paginator) = self.paginator.upgrade() {
+            paginator.on_page(self, &value);
+        }
+    }
+
+    async fn on_error(&self, message: String, value: Option<String>) {
+        if let Some(paginator) = self.paginator.upgrade() {
+            paginator.host.send(Delivery::Error(message, value));
+        }
+    }
+
+    async fn on_subscribed(&self) {}
+
+    async fn on_resubscribed(&self) {}
+}
+
+/// A handle to a paginated query subscription.
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct PaginatedSubscription {
+    paginator: Arc<Paginator>,
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl PaginatedSubscription {
+    /// Loads the next page with `num_items` items.
+    ///
+    /// Returns `false` without doing anything if a page is still loading or
+    /// all pages have been loaded.
+    pub async fn load_more(&self, num_items: u32) -> Result<bool, ClientError> {
+        catch_panic(async {
+            let Some(client) = self.paginator.client.upgrade() else {
+                return Ok(false);
+            };
+            let (index, end_cursor, subscribe) = {
+                let mut pages = self.paginator.pages.lock();
+                let Some(last) = pages.len().checked_sub(1) else {
+                    return Ok(false);
+                };
+                let Some(PageResult { is_done: false, .. }) = pages[last].result else {
+                    return Ok(false);
+                };
+                let end_cursor = pages[last].end_cursor.clone();
+                pages.push(Page {
+                    num_items,
+                    ..Page::default()
+                });
+                let subscribe = start_after(&mut pages, last);
+                let (items, status) = merge(&pages);
+                self.paginator.host.send(Delivery::Update(items, status));
+                (last + 1, end_cursor, subscribe)
+            };
+            debug!("Loading page {index} of {}", self.paginator.name);
+            for page in subscribe {
+                if let Err(e) = self.paginator.subscribe_page(&client, page).await {
+                    let mut pages = self.paginator.pages.lock();
+                    pages.truncate(index);
+                    if page < index {
+                        if let Some(last) = pages.last_mut() {
+                            last.end_cursor = end_cursor;
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Cancels the subscriptions of all pages.
+    pub fn cancel(&self) {
+        for mut page in self.paginator.pages.lock().drain(..) {
+            page.cancel();
+        }
+    }
+}
+
+/// Subscribes to the first page of the paginated query `name`.
+pub(crate) async fn subscribe(
+    client: Arc<MobileConvexClient>,
+    name: String,
+    args: HashMap<String, String>,
+    page_size: u32,
+    subscriber: Arc<dyn PaginatedQuerySubscriber>,
+) -> Result<Arc<PaginatedSubscription>, ClientError> {
+    let (host, mut deliveries) = latest::channel();
+    client.rt.spawn(async move {
+        while let Some(delivery) = deliveries.recv().await {
+            match delivery {
+                Delivery::Update(items, status) => subscriber.on_update(items, status).await,
+                Delivery::Error(message, value) => subscriber.on_error(message, value).await,
+            }
+        }
+    });
+    let paginator = Arc::new(Paginator {
+        name,
+        args,
+        client: Arc::downgrade(&client),
+        pages: Mutex::new(vec![Page {
+            num_items: page_size,
+            ..Page::default()
+        }]),
+        host,
+    });
+    paginator.subscribe_page(&client, 0).await?;
+    Ok(Arc::new(PaginatedSubscription { paginator }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, start_after, Page, PageResult, PaginationStatus};
+
+    fn page(items: &[i32], is_done: bool) -> Page {
+        Page {
+            cursor: Some("next".into()),
+            result: Some(PageResult {
+                items: items.iter().map(|i| (*i).into()).collect(),
+                is_done,
+                continue_cursor: "next".into(),
+            }),
+            ..Page::default()
+        }
+    }
+
+    #[test]
+    fn test_loaded_pages_are_merged_in_order() {
+        assert_eq!(
+            merge(&[Page::default()]),
+            ("[]".into(), PaginationStatus::LoadingFirstPage)
+        );
+        assert_eq!(
+            merge(&[page(&[1, 2], false), Page::default()]),
+            ("[1,2]".into(), PaginationStatus::LoadingMore)
+        );
+        assert_eq!(
+            merge(&[page(&[1, 2], false), page(&[3], true)]),
+            ("[1,2,3]".into(), PaginationStatus::Exhausted)
+        );
+    }
+
+    #[test]
+    fn test_pages_after_a_shifted_page_are_restarted() {
+        // An item was inserted into the first page, so it ends before the
+        // second page starts, which would repeat its last item.
+        let mut shifted = page(&[1, 2], false);
+        shifted.result.as_mut().unwrap().continue_cursor = "2".into();
+        let mut pages = vec![shifted, page(&[2, 3], false)];
+        assert_eq!(
+            merge(&pages),
+            ("[1,2]".into(), PaginationStatus::LoadingMore)
+        );
+
+        pages[1].result = None;
+        assert_eq!(start_after(&mut pages, 0), vec![0, 1]);
+        assert_eq!(pages[0].end_cursor.as_deref(), Some("2"));
+        assert_eq!(pages[1].cursor.as_deref(), Some("2"));
+
+        pages[1].result = page(&[3], true).result;
+        assert_eq!(
+            merge(&pages),
+            ("[1,2,3]".into(), PaginationStatus::Exhausted)
+        );
+    }
+}
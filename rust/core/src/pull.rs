@@ -0,0 +1,98 @@
+//! Subscriptions whose updates the host pulls instead of having them pushed.
+//!
+//! A [StreamSubscriber] takes the place of the host's [QuerySubscriber] and
+//! only keeps the latest update that hasn't been pulled yet, so a host that
+//! pulls slowly skips the updates it had no time for rather than falling
+//! behind.
+use std::sync::Arc;
+
+use crate::{
+    latest::{self, LatestReceiver, LatestSender},
+    QuerySubscriber, SubscriptionHandle,
+};
+
+/// An update of a [SubscriptionStream].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum SubscriptionUpdate {
+    /// A new JSON encoded result, which is `is_stale` if it's cached from an
+    /// earlier run.
+    Value { value: String, is_stale: bool },
+    /// The query failed, with the data of a `ConvexError` if it threw one.
+    Error {
+        message: String,
+        data: Option<String>,
+    },
+}
+
+pub(crate) struct StreamSubscriber {
+    updates: LatestSender<SubscriptionUpdate>,
+}
+
+impl StreamSubscriber {
+    pub(crate) fn new() -> (Arc<Self>, LatestReceiver<SubscriptionUpdate>) {
+        let (updates, receiver) = latest::channel();
+        (Arc::new(StreamSubscriber { updates }), receiver)
+    }
+}
+
+#[async_trait::async_trait]
+impl QuerySubscriber for StreamSubscriber {
+    async fn on_update(&self, value: String, is_stale: bool) {
+        self.updates
+            .send(SubscriptionUpdate::Value { value, is_stale });
+    }
+
+    async fn on_error(&self, message: String, data: Option<String>) {
+        self.updates
+            .send(SubscriptionUpdate::Error { message, data });
+    }
+
+    async fn on_subscribed(&self) {}
+
+    async fn on_resubscribed(&self) {}
+}
+
+/// A query subscription that the host pulls updates from with
+/// [SubscriptionStream::next_update], e.g. to back a Kotlin `Flow` or a Swift
+/// `AsyncSequence`.
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct SubscriptionStream {
+    handle: Arc<SubscriptionHandle>,
+    updates: tokio::sync::Mutex<LatestReceiver<SubscriptionUpdate>>,
+}
+
+impl SubscriptionStream {
+    pub(crate) fn new(
+        handle: Arc<SubscriptionHandle>,
+        updates: LatestReceiver<SubscriptionUpdate>,
+    ) -> Self {
+        SubscriptionStream {
+            handle,
+            updates: tokio::sync::Mutex::new(updates),
+        }
+    }
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl SubscriptionStream {
+    /// Waits for the next update, which is the latest one if several arrived
+    /// since the last call.
+    ///
+    /// Returns `None` once the subscription has stopped and its last update
+    /// has been pulled.
+    pub async fn next_update(&self) -> Option<SubscriptionUpdate> {
+        self.updates.lock().await.recv().await
+    }
+
+    /// The [SubscriptionHandle::id] of the subscription.
+    pub fn id(&self) -> u64 {
+        self.handle.id()
+    }
+
+    /// Stops the subscription. Updates that were already pulled stay valid,
+    /// and [SubscriptionStream::next_update] returns `None` once it stopped.
+    pub fn cancel(&self) {
+        self.handle.cancel();
+    }
+}
@@ -0,0 +1,322 @@
+//! Recording the results that a client gets from the backend, to replay them
+//! later with a [MockConvexClient](crate::MockConvexClient) and no network.
+//!
+//! A recording is a JSON document that's written through to a
+//! [StorageProvider] as results come in, so that it survives a crash and can
+//! be attached to a bug report:
+//!
+//! ```json
+//! {"version": 1, "entries": [
+//!   {"kind": "subscription", "name": "messages:list", "args": {}, "value": []},
+//!   {"kind": "mutation", "name": "messages:send", "args": {"body": "hi"}, "error": "Too long", "data": {"max": 1}}
+//! ]}
+//! ```
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Arc,
+};
+
+use convex::{ConvexError, FunctionResult, Value};
+use parking_lot::Mutex;
+use serde_json::json;
+use tokio::{runtime::Handle, sync::mpsc};
+use tracing::warn;
+
+use crate::{args_json, worker::Call, ClientError, StorageProvider};
+
+const VERSION: u64 = 1;
+
+/// What a recorded result was for.
+#[derive(Clone, Copy)]
+pub(crate) enum Source {
+    Call(Call),
+    Subscription,
+}
+
+impl Source {
+    fn kind(self) -> &'static str {
+        match self {
+            Source::Call(Call::Query) => "query",
+            Source::Call(Call::Mutation) => "mutation",
+            Source::Call(Call::Action) => "action",
+            Source::Subscription => "subscription",
+        }
+    }
+}
+
+/// Sends the results of a client to the task that writes its recording, if
+/// it's being recorded.
+#[derive(Default)]
+pub(crate) struct Recorder {
+    entries: Mutex<Option<mpsc::UnboundedSender<serde_json::Value>>>,
+}
+
+impl Recorder {
+    /// Starts a new recording under `key`, ending the current one.
+    pub(crate) fn start(&self, rt: &Handle, storage: Arc<dyn StorageProvider>, key: String) {
+        let (sender, entries) = mpsc::unbounded_channel();
+        rt.spawn(write(storage, key, entries));
+        *self.entries.lock() = Some(sender);
+    }
+
+    pub(crate) fn stop(&self) {
+        self.entries.lock().take();
+    }
+
+    pub(crate) fn is_recording(&self) -> bool {
+        self.entries.lock().is_some()
+    }
+
+    pub(crate) fn record(
+        &self,
+        source: Source,
+        name: &str,
+        args: &BTreeMap<String, Value>,
+        result: &FunctionResult,
+    ) {
+        let entries = self.entries.lock();
+        let Some(entries) = &*entries else {
+            return;
+        };
+        let mut entry = json!({
+            "kind": source.kind(),
+            "name": name,
+            "args": args
+                .iter()
+                .map(|(key, value)| (key.clone(), serde_json::Value::from(value.clone())))
+                .collect::<serde_json::Map<_, _>>(),
+        });
+        match result {
+            FunctionResult::Value(value) => {
+                entry["value"] = value.clone().into();
+            },
+            FunctionResult::ErrorMessage(message) => {
+                entry["error"] = message.clone().into();
+            },
+            FunctionResult::ConvexError(error) => {
+                entry["error"] = error.message.clone().into();
+                entry["data"] = error.data.clone().into();
+            },
+        }
+        let _ = entries.send(entry);
+    }
+}
+
+/// Writes the whole recording again after each batch of new entries, until
+/// the recording stops.
+async fn write(
+    storage: Arc<dyn StorageProvider>,
+    key: String,
+    mut entries: mpsc::UnboundedReceiver<serde_json::Value>,
+) {
+    let mut recorded = Vec::new();
+    loop {
+        let document = json!({"version": VERSION, "entries": recorded}).to_string();
+        if let Err(e) = storage.put(key.clone(), document).await {
+            warn!("Failed to write the recording {key}: {e}");
+        }
+        let Some(entry) = entries.recv().await else {
+            break;
+        };
+        recorded.push(entry);
+        while let Ok(entry) = entries.try_recv() {
+            recorded.push(entry);
+        }
+    }
+}
+
+/// The results of a recording, by function name and JSON encoded args.
+#[derive(Default)]
+pub(crate) struct Replay {
+    calls: HashMap<(String, String), VecDeque<FunctionResult>>,
+    updates: HashMap<(String, String), Vec<FunctionResult>>,
+}
+
+impl Replay {
+    /// Parses a recording written by a [Recorder].
+    pub(crate) fn parse(document: &str) -> Result<Replay, ClientError> {
+        let invalid = |reason: String| ClientError::InvalidArgument {
+            key: "recording".into(),
+            reason,
+        };
+        let document: serde_json::Value =
+            serde_json::from_str(document).map_err(|e| invalid(e.to_string()))?;
+        if document["version"] != VERSION {
+            return Err(invalid(format!(
+                "Unsupported version {}",
+                document["version"]
+            )));
+        }
+        let entries = document["entries"]
+            .as_array()
+            .ok_or_else(|| invalid("The entries are missing".into()))?;
+        let mut replay = Replay::default();
+        for entry in entries {
+            let (Some(kind), Some(name)) = (entry["kind"].as_str(), entry["name"].as_str()) else {
+                return Err(invalid(format!("Entry without a kind and name: {entry}")));
+            };
+            let key = (name.to_string(), entry["args"].to_string());
+            let result = match (&entry["error"], entry.get("data")) {
+                (serde_json::Value::String(message), Some(data)) => {
+                    FunctionResult::ConvexError(ConvexError {
+                        message: message.clone(),
+                        data: value(data.clone()).map_err(&invalid)?,
+                    })
+                },
+                (serde_json::Value::String(message), None) => {
+                    FunctionResult::ErrorMessage(message.clone())
+                },
+                _ => FunctionResult::Value(
+                    value(entry.get("value").cloned().unwrap_or_default()).map_err(&invalid)?,
+                ),
+            };
+            match kind {
+                "subscription" => replay.updates.entry(key).or_default().push(result),
+                _ => replay.calls.entry(key).or_default().push_back(result),
+            }
+        }
+        Ok(replay)
+    }
+
+    /// The next recorded result of calling `name` with `args`. The last one
+    /// repeats once the others have been used up.
+    pub(crate) fn next_call(
+        &mut self,
+        name: &str,
+        args: &BTreeMap<String, Value>,
+    ) -> Option<FunctionResult> {
+        let results = self.calls.get_mut(&(name.to_string(), args_json(args)))?;
+        if results.len() > 1 {
+            results.pop_front()
+        } else {
+            results.front().cloned()
+        }
+    }
+
+    /// All the recorded updates of a subscription to `name` with `args`.
+    pub(crate) fn updates(
+        &self,
+        name: &str,
+        args: &BTreeMap<String, Value>,
+    ) -> Vec<FunctionResult> {
+        self.updates
+            .get(&(name.to_string(), args_json(args)))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+fn value(json: serde_json::Value) -> Result<Value, String> {
+    Value::try_from(json).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc, time::Duration};
+
+    use parking_lot::Mutex;
+    use tokio::sync::mpsc;
+
+    use crate::{ClientError, MockConvexClient, QuerySubscriber, StorageProvider};
+
+    #[derive(Default)]
+    struct Memory(Mutex<HashMap<String, String>>);
+
+    #[async_trait::async_trait]
+    impl StorageProvider for Memory {
+        async fn get(&self, key: String) -> Result<Option<String>, ClientError> {
+            Ok(self.0.lock().get(&key).cloned())
+        }
+
+        async fn put(&self, key: String, value: String) -> Result<(), ClientError> {
+            self.0.lock().insert(key, value);
+            Ok(())
+        }
+
+        async fn delete(&self, key: String) -> Result<(), ClientError> {
+            self.0.lock().remove(&key);
+            Ok(())
+        }
+    }
+
+    struct Updates(mpsc::UnboundedSender<String>);
+
+    #[async_trait::async_trait]
+    impl QuerySubscriber for Updates {
+        async fn on_update(&self, value: String, _is_stale: bool) {
+            let _ = self.0.send(value);
+        }
+
+        async fn on_error(&self, message: String, _data: Option<String>) {
+            let _ = self.0.send(message);
+        }
+
+        async fn on_subscribed(&self) {}
+
+        async fn on_resubscribed(&self) {}
+    }
+
+    #[tokio::test]
+    async fn test_replays_a_recorded_session() {
+        let storage = Arc::new(Memory::default());
+        let recorded = MockConvexClient::new();
+        recorded
+            .set_result("messages:list".into(), "[]".into())
+            .unwrap();
+        recorded
+            .set_error(
+                "messages:send".into(),
+                "Too long".into(),
+                Some("\"long\"".into()),
+            )
+            .unwrap();
+        let client = recorded.client();
+        client.start_recording(storage.clone(), "session".into());
+
+        let args = HashMap::from([("body".to_string(), "\"hi\"".to_string())]);
+        assert!(client
+            .mutation("messages:send".into(), args.clone(), None)
+            .await
+            .is_err());
+        let (sender, mut updates) = mpsc::unbounded_channel();
+        let _handle = client
+            .subscribe(
+                "messages:list".into(),
+                HashMap::new(),
+                Arc::new(Updates(sender)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(updates.recv().await.unwrap(), "[]");
+        recorded
+            .set_result("messages:list".into(), "[\"hi\"]".into())
+            .unwrap();
+        assert_eq!(updates.recv().await.unwrap(), "[\"hi\"]");
+        client.stop_recording();
+        while !storage.0.lock()["session"].contains("[\"hi\"]") {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let replayed = MockConvexClient::new();
+        replayed
+            .replay(storage.clone(), "session".into())
+            .await
+            .unwrap();
+        let client = replayed.client();
+        assert!(matches!(
+            client.mutation("messages:send".into(), args, None).await,
+            Err(ClientError::ConvexError { data }) if data == "\"long\""
+        ));
+        let (sender, mut updates) = mpsc::unbounded_channel();
+        let _handle = client
+            .subscribe(
+                "messages:list".into(),
+                HashMap::new(),
+                Arc::new(Updates(sender)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(updates.recv().await.unwrap(), "[]");
+        assert_eq!(updates.recv().await.unwrap(), "[\"hi\"]");
+    }
+}
@@ -0,0 +1,77 @@
+//! Anonymous, per-device sessions.
+//!
+//! Convex auth needs a token from an identity provider, so anonymous users are
+//! instead identified by a client-generated session ID that gets passed to
+//! every function, following the `convex-helpers` sessions convention. Backend
+//! functions can key data on it and link it to the real user once `set_auth`
+//! is called.
+use std::collections::BTreeMap;
+
+use convex::Value;
+use parking_lot::Mutex;
+
+/// The argument that carries the session ID, matching `convex-helpers`.
+pub(crate) const SESSION_ID_ARG: &str = "sessionId";
+
+#[derive(Default)]
+pub(crate) struct AnonymousSession {
+    session_id: Mutex<Option<String>>,
+}
+
+impl AnonymousSession {
+    /// Starts a session with `existing_session_id`, or a freshly generated ID
+    /// if there isn't one, returning the ID so the host can persist it.
+    pub(crate) fn start(&self, existing_session_id: Option<String>) -> String {
+        let session_id = existing_session_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        *self.session_id.lock() = Some(session_id.clone());
+        session_id
+    }
+
+    pub(crate) fn end(&self) {
+        *self.session_id.lock() = None;
+    }
+
+    pub(crate) fn session_id(&self) -> Option<String> {
+        self.session_id.lock().clone()
+    }
+
+    /// Adds the session ID to `args` unless the caller passed one explicitly.
+    pub(crate) fn apply(&self, args: &mut BTreeMap<String, Value>) {
+        if let Some(session_id) = self.session_id() {
+            args.entry(SESSION_ID_ARG.to_string())
+                .or_insert(Value::String(session_id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use convex::Value;
+
+    use super::{AnonymousSession, SESSION_ID_ARG};
+
+    #[test]
+    fn test_session_id_is_added_without_overriding_explicit_args() {
+        let session = AnonymousSession::default();
+        let id = session.start(None);
+
+        let mut args = BTreeMap::new();
+        session.apply(&mut args);
+        assert_eq!(args.get(SESSION_ID_ARG), Some(&Value::String(id)));
+
+        let mut args = BTreeMap::new();
+        args.insert(SESSION_ID_ARG.to_string(), Value::String("mine".into()));
+        session.apply(&mut args);
+        assert_eq!(
+            args.get(SESSION_ID_ARG),
+            Some(&Value::String("mine".into()))
+        );
+
+        session.end();
+        let mut args = BTreeMap::new();
+        session.apply(&mut args);
+        assert!(args.is_empty());
+    }
+}
@@ -0,0 +1,18 @@
+//! Counters that describe what a client is doing.
+
+/// A snapshot of the counters of a client, as returned by
+/// [crate::MobileConvexClient::stats].
+///
+/// There are no byte or message counts, as the WebSocket is owned by the
+/// underlying client and neither its traffic nor its frames are visible from
+/// here.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct ClientStats {
+    /// How many times the connection was lost and had to be re-established.
+    pub reconnects: u64,
+    pub active_subscriptions: u32,
+    /// Queries, mutations and actions that were sent and are waiting for
+    /// their results.
+    pub pending_requests: u32,
+}
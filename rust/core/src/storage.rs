@@ -0,0 +1,436 @@
+//! Transfers of files to and from Convex file storage.
+//!
+//! Uploads go to the URLs returned by `ctx.storage.generateUploadUrl()` in a
+//! mutation and downloads come from `ctx.storage.getUrl()`. Both are
+//! pre-authorized, so they don't need the client's auth.
+use std::{sync::Arc, time::Duration};
+
+use futures::{stream, Stream, StreamExt};
+use reqwest::{
+    header::{HeaderMap, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE},
+    Body, StatusCode,
+};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, warn};
+
+use crate::{
+    http::{self, network_error, status_error},
+    ClientError,
+};
+
+/// How much of the file gets read and sent at a time, which is also how often
+/// progress is reported.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How many times a transfer is attempted before giving up on a retryable
+/// failure.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Appended to the path of a download to get the file that keeps the
+/// validator of the partly downloaded file, until the download completes.
+const VALIDATOR_SUFFIX: &str = ".validator";
+
+/// Where the bytes of an upload come from.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum UploadSource {
+    Bytes {
+        data: Vec<u8>,
+    },
+    /// A file on the device, read in chunks so it doesn't have to fit in
+    /// memory.
+    File {
+        path: String,
+    },
+}
+
+/// Gets told how far along a file transfer is.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait TransferProgressListener: Send + Sync {
+    async fn on_progress(&self, bytes_transferred: u64, total_bytes: Option<u64>) -> ();
+}
+
+/// Receives the bytes of a download.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait DownloadSink: Send + Sync {
+    async fn write(&self, chunk: Vec<u8>) -> Result<(), ClientError>;
+
+    /// The server ignored the request to resume and is sending the file from
+    /// the start, so everything written so far should be discarded.
+    async fn reset(&self) -> Result<(), ClientError>;
+}
+
+/// Where a download gets written.
+pub(crate) enum Destination {
+    File { path: String, file: tokio::fs::File },
+    Sink(Arc<dyn DownloadSink>),
+}
+
+impl Destination {
+    /// Opens the file at `path` for appending, returning how much of it
+    /// already exists to resume from and the validator of the file it's part
+    /// of.
+    ///
+    /// A partial file without a validator can't be checked against what the
+    /// server has now, so it's downloaded again from the start.
+    pub(crate) async fn file(path: String) -> Result<(Self, u64, Option<String>), ClientError> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|e| file_error(&path, e))?;
+        let mut len = file
+            .metadata()
+            .await
+            .map_err(|e| file_error(&path, e))?
+            .len();
+        let validator = match len {
+            0 => None,
+            _ => tokio::fs::read_to_string(format!("{path}{VALIDATOR_SUFFIX}"))
+                .await
+                .ok(),
+        };
+        if len > 0 && validator.is_none() {
+            debug!("{path} has no validator, downloading it again");
+            file.set_len(0).await.map_err(|e| file_error(&path, e))?;
+            len = 0;
+        }
+        Ok((Destination::File { path, file }, len, validator))
+    }
+
+    /// Remembers the validator of the file being downloaded, so a later
+    /// download can resume it.
+    async fn set_validator(&mut self, validator: Option<&str>) -> Result<(), ClientError> {
+        let Destination::File { path, .. } = self else {
+            return Ok(());
+        };
+        let validator_path = format!("{path}{VALIDATOR_SUFFIX}");
+        let result = match validator {
+            Some(validator) => tokio::fs::write(&validator_path, validator).await,
+            None => remove_if_exists(&validator_path).await,
+        };
+        result.map_err(|e| file_error(&validator_path, e))
+    }
+
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), ClientError> {
+        match self {
+            Destination::File { path, file } => {
+                file.write_all(chunk).await.map_err(|e| file_error(path, e))
+            },
+            Destination::Sink(sink) => sink.write(chunk.to_vec()).await,
+        }
+    }
+
+    async fn reset(&mut self) -> Result<(), ClientError> {
+        match self {
+            Destination::File { path, file } => {
+                file.set_len(0).await.map_err(|e| file_error(path, e))
+            },
+            Destination::Sink(sink) => sink.reset().await,
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), ClientError> {
+        match self {
+            Destination::File { path, file } => file.flush().await.map_err(|e| file_error(path, e)),
+            Destination::Sink(_) => Ok(()),
+        }
+    }
+}
+
+/// Downloads `url` into `destination`, starting `offset` bytes in, and
+/// returns the size of the whole file.
+///
+/// Interrupted attempts are resumed from where they stopped with a range
+/// request. When the `validator` (an `ETag` or `Last-Modified` date) of the
+/// bytes received so far is known, the server only resumes if the file still
+/// matches it, and sends it again from the start otherwise.
+pub(crate) async fn download(
+    url: &str,
+    mut destination: Destination,
+    mut offset: u64,
+    mut validator: Option<String>,
+    progress: Option<Arc<dyn TransferProgressListener>>,
+) -> Result<u64, ClientError> {
+    let client = http::client_builder()?
+        .build()
+        .map_err(anyhow::Error::from)?;
+    let mut attempt = 1;
+    loop {
+        let result = download_from(
+            &client,
+            url,
+            &mut destination,
+            &mut offset,
+            &mut validator,
+            progress.as_deref(),
+        )
+        .await;
+        match result {
+            Ok(()) => {
+                destination.set_validator(None).await?;
+                return Ok(offset);
+            },
+            Err(error) if is_retryable(&error) && attempt < MAX_ATTEMPTS => {
+                warn!("Download attempt {attempt} failed at byte {offset}, resuming: {error}");
+                tokio::time::sleep(backoff(attempt)).await;
+                attempt += 1;
+            },
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+async fn download_from(
+    client: &reqwest::Client,
+    url: &str,
+    destination: &mut Destination,
+    offset: &mut u64,
+    validator: &mut Option<String>,
+    progress: Option<&dyn TransferProgressListener>,
+) -> Result<(), ClientError> {
+    let response = loop {
+        let mut request = client.get(url);
+        if *offset > 0 {
+            request = request.header(RANGE, format!("bytes={offset}-"));
+            if let Some(validator) = validator {
+                request = request.header(IF_RANGE, validator.as_str());
+            }
+        }
+        let response = request.send().await.map_err(network_error)?;
+        let (start, total) = content_range(response.headers());
+        let restart = match response.status() {
+            StatusCode::PARTIAL_CONTENT => start != Some(*offset),
+            // Everything up to `offset` is all there is, if it's as long as
+            // the whole file.
+            StatusCode::RANGE_NOT_SATISFIABLE if *offset > 0 => {
+                if total == Some(*offset) {
+                    return Ok(());
+                }
+                true
+            },
+            status if status.is_success() => *offset > 0,
+            _ => return Err(status_error(response).await),
+        };
+        if !restart {
+            break response;
+        }
+        debug!("Server can't resume the download at byte {offset}, restarting it");
+        destination.reset().await?;
+        *offset = 0;
+    };
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        *validator = response_validator(response.headers());
+        destination.set_validator(validator.as_deref()).await?;
+    }
+    let total = match content_range(response.headers()) {
+        (_, Some(total)) => Some(total),
+        _ => response.content_length().map(|len| len + *offset),
+    };
+    let mut body = response.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(network_error)?;
+        destination.write(&chunk).await?;
+        *offset += chunk.len() as u64;
+        if let Some(progress) = progress {
+            progress.on_progress(*offset, total).await;
+        }
+    }
+    destination.flush().await
+}
+
+/// The first byte and the size of the whole file in the `Content-Range`
+/// header of a response, like `bytes 100-199/200` or `bytes */200`.
+fn content_range(headers: &HeaderMap) -> (Option<u64>, Option<u64>) {
+    let Some(range) = headers
+        .get(CONTENT_RANGE)
+        .and_then(|range| range.to_str().ok())
+        .and_then(|range| range.strip_prefix("bytes "))
+    else {
+        return (None, None);
+    };
+    let (bytes, total) = range.split_once('/').unwrap_or((range, "*"));
+    let start = bytes
+        .split_once('-')
+        .and_then(|(start, _)| start.parse().ok());
+    (start, total.parse().ok())
+}
+
+/// What identifies the version of the file in a response, to check that a
+/// resumed download continues the same file. Weak `ETag`s can't be used for
+/// that.
+fn response_validator(headers: &HeaderMap) -> Option<String> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    header(ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(LAST_MODIFIED))
+        .map(str::to_owned)
+}
+
+/// Uploads `source` to `upload_url`, returning the ID of the stored file.
+pub(crate) async fn upload(
+    upload_url: &str,
+    source: UploadSource,
+    content_type: &str,
+    progress: Option<Arc<dyn TransferProgressListener>>,
+) -> Result<String, ClientError> {
+    let client = http::client_builder()?
+        .build()
+        .map_err(anyhow::Error::from)?;
+    let total = source_len(&source).await?;
+    let mut attempt = 1;
+    loop {
+        let body = Body::wrap_stream(chunks(&source, total, progress.clone()).await?);
+        let result = client
+            .post(upload_url)
+            .header(CONTENT_TYPE, content_type)
+            .body(body)
+            .send()
+            .await;
+        let error = match result {
+            Ok(response) if response.status().is_success() => {
+                return storage_id(response).await;
+            },
+            Ok(response) => status_error(response).await,
+            Err(e) => network_error(e),
+        };
+        if !is_retryable(&error) || attempt >= MAX_ATTEMPTS {
+            return Err(error);
+        }
+        warn!("Upload attempt {attempt} failed, retrying: {error}");
+        tokio::time::sleep(backoff(attempt)).await;
+        attempt += 1;
+    }
+}
+
+async fn source_len(source: &UploadSource) -> Result<u64, ClientError> {
+    Ok(match source {
+        UploadSource::Bytes { data } => data.len() as u64,
+        UploadSource::File { path } => tokio::fs::metadata(path)
+            .await
+            .map_err(|e| file_error(path, e))?
+            .len(),
+    })
+}
+
+/// The body of an upload, reporting progress as each chunk is handed off.
+async fn chunks(
+    source: &UploadSource,
+    total: u64,
+    progress: Option<Arc<dyn TransferProgressListener>>,
+) -> Result<impl Stream<Item = std::io::Result<Vec<u8>>> + Send + 'static, ClientError> {
+    let chunks: stream::BoxStream<'static, std::io::Result<Vec<u8>>> = match source {
+        UploadSource::Bytes { data } => {
+            let chunks: Vec<_> = data.chunks(CHUNK_SIZE).map(|c| Ok(c.to_vec())).collect();
+            stream::iter(chunks).boxed()
+        },
+        UploadSource::File { path } => {
+            let file = tokio::fs::File::open(path)
+                .await
+                .map_err(|e| file_error(path, e))?;
+            stream::unfold(file, |mut file| async move {
+                use tokio::io::AsyncReadExt;
+                let mut chunk = vec![0; CHUNK_SIZE];
+                match file.read(&mut chunk).await {
+                    Ok(0) => None,
+                    Ok(n) => {
+                        chunk.truncate(n);
+                        Some((Ok(chunk), file))
+                    },
+                    Err(e) => Some((Err(e), file)),
+                }
+            })
+            .boxed()
+        },
+    };
+    Ok(chunks.scan(0u64, move |sent, chunk| {
+        let progress = progress.clone();
+        if let Ok(chunk) = &chunk {
+            *sent += chunk.len() as u64;
+        }
+        let sent = *sent;
+        async move {
+            if let Some(progress) = progress {
+                progress.on_progress(sent, Some(total)).await;
+            }
+            Some(chunk)
+        }
+    }))
+}
+
+async fn storage_id(response: reqwest::Response) -> Result<String, ClientError> {
+    let body: serde_json::Value = response.json().await.map_err(network_error)?;
+    let storage_id = body
+        .get("storageId")
+        .and_then(|id| id.as_str())
+        .ok_or_else(|| ClientError::InternalError {
+            msg: format!("Upload response has no storageId: {body}"),
+        })?;
+    debug!("Uploaded file {storage_id}");
+    Ok(storage_id.to_owned())
+}
+
+async fn remove_if_exists(path: &str) -> std::io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn file_error(path: &str, e: std::io::Error) -> ClientError {
+    ClientError::InternalError {
+        msg: format!("{path}: {e}"),
+    }
+}
+
+pub(crate) fn is_retryable(error: &ClientError) -> bool {
+    matches!(
+        error,
+        ClientError::NetworkError {
+            retryable: true,
+            ..
+        }
+    )
+}
+
+pub(crate) fn backoff(attempt: u32) -> Duration {
+    Duration::from_millis(500 * 2u64.pow(attempt - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use reqwest::header::{HeaderMap, CONTENT_RANGE};
+
+    use super::{chunks, content_range, UploadSource, CHUNK_SIZE};
+
+    #[tokio::test]
+    async fn test_bytes_are_uploaded_in_chunks() {
+        let source = UploadSource::Bytes {
+            data: vec![7; CHUNK_SIZE * 2 + 1],
+        };
+        let sizes: Vec<_> = chunks(&source, 0, None)
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap().len())
+            .collect()
+            .await;
+        assert_eq!(sizes, vec![CHUNK_SIZE, CHUNK_SIZE, 1]);
+    }
+
+    #[test]
+    fn test_content_range_is_parsed() {
+        let range = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_RANGE, value.parse().unwrap());
+            content_range(&headers)
+        };
+        assert_eq!(range("bytes 100-199/200"), (Some(100), Some(200)));
+        assert_eq!(range("bytes 100-199/*"), (Some(100), None));
+        assert_eq!(range("bytes */200"), (None, Some(200)));
+        assert_eq!(content_range(&HeaderMap::new()), (None, None));
+    }
+}
@@ -0,0 +1,321 @@
+//! Bookkeeping for the query subscriptions that a [crate::MobileConvexClient]
+//! has handed out.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
+
+use futures::channel::oneshot::Sender;
+use parking_lot::Mutex;
+use tokio::sync::watch;
+
+/// Gets told when a subscription is cancelled to stay under the limit set with
+/// [crate::MobileConvexClient::set_subscription_limit].
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait SubscriptionEvictionListener: Send + Sync {
+    async fn on_evicted(&self, subscription_id: u64, name: String) -> ();
+}
+
+/// Options for [crate::MobileConvexClient::subscribe_with_options].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct SubscriptionOptions {
+    /// Deliver only the newest of the results that arrived while the host was
+    /// busy with the previous one, rather than every one of them in turn, so a
+    /// slow host doesn't fall behind. On by default.
+    #[cfg_attr(feature = "uniffi", uniffi(default = true))]
+    pub conflate: bool,
+    /// Hold each result for this long and deliver only the newest of the
+    /// results that arrive in the meantime, so rapidly changing queries like
+    /// typing indicators or live counters don't call back for every change.
+    #[cfg_attr(feature = "uniffi", uniffi(default = None))]
+    pub debounce_ms: Option<u64>,
+    /// Deliver each result after the first as an RFC 6902 JSON Patch from the
+    /// previous one, to cut the size of updates to big results that change a
+    /// little at a time. `on_update` then gets `{"snapshot": <result>}` or
+    /// `{"patch": [<operations>]}`, and a snapshot follows any error.
+    #[cfg_attr(feature = "uniffi", uniffi(default = false))]
+    pub json_patch: bool,
+    /// With `json_patch`, send a full snapshot after this many patches in a
+    /// row, or never when it's 0.
+    #[cfg_attr(feature = "uniffi", uniffi(default = 20))]
+    pub json_patch_snapshot_interval: u32,
+    /// A JSON Pointer, like `/items/0/status`, to the part of the result to
+    /// deliver instead of all of it. Updates that don't change that part are
+    /// skipped, and `null` is delivered while it doesn't exist.
+    #[cfg_attr(feature = "uniffi", uniffi(default = None))]
+    pub select: Option<String>,
+    /// Skip results that are the same as the previous one, like the ones the
+    /// server sends again after reconnecting.
+    #[cfg_attr(feature = "uniffi", uniffi(default = false))]
+    pub distinct: bool,
+}
+
+impl SubscriptionOptions {
+    pub(crate) fn debounce(&self) -> Option<Duration> {
+        self.debounce_ms
+            .filter(|&ms| ms > 0)
+            .map(Duration::from_millis)
+    }
+}
+
+impl Default for SubscriptionOptions {
+    fn default() -> Self {
+        SubscriptionOptions {
+            conflate: true,
+            debounce_ms: None,
+            json_patch: false,
+            json_patch_snapshot_interval: 20,
+            select: None,
+            distinct: false,
+        }
+    }
+}
+
+/// A live subscription, as listed by
+/// [crate::MobileConvexClient::active_subscriptions].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct SubscriptionInfo {
+    pub subscription_id: u64,
+    pub name: String,
+    /// The JSON encoded arguments of the query.
+    pub args: String,
+    /// How long ago the subscription was created.
+    pub age_ms: u64,
+}
+
+/// A subscription that was cancelled to make room for a newer one.
+pub(crate) struct Evicted {
+    pub(crate) id: u64,
+    pub(crate) name: String,
+}
+
+struct Entry {
+    name: String,
+    args: String,
+    created: Instant,
+    /// The value of [Inner::clock] when the subscription was last created or
+    /// delivered an update; the smallest one is the least recently used.
+    last_used: u64,
+    cancel_sender: Sender<()>,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: u64,
+    clock: u64,
+    max_subscriptions: Option<usize>,
+    entries: HashMap<u64, Entry>,
+}
+
+impl Inner {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn evict_over_limit(&mut self, limit: usize) -> Vec<Evicted> {
+        let mut evicted = vec![];
+        while self.entries.len() > limit {
+            let Some(id) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(id, _)| *id)
+            else {
+                break;
+            };
+            let entry = self.entries.remove(&id).expect("id was just found");
+            let _ = entry.cancel_sender.send(());
+            evicted.push(Evicted {
+                id,
+                name: entry.name,
+            });
+        }
+        evicted
+    }
+}
+
+/// All of the live subscriptions of a client.
+#[derive(Default)]
+pub(crate) struct Subscriptions {
+    inner: Mutex<Inner>,
+}
+
+impl Subscriptions {
+    /// Registers a new subscription that `cancel_sender` stops, evicting the
+    /// least recently used ones if that goes over the limit.
+    pub(crate) fn insert(
+        &self,
+        name: String,
+        args: String,
+        cancel_sender: Sender<()>,
+    ) -> (u64, Vec<Evicted>) {
+        let mut inner = self.inner.lock();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        let last_used = inner.tick();
+        inner.entries.insert(
+            id,
+            Entry {
+                name,
+                args,
+                created: Instant::now(),
+                last_used,
+                cancel_sender,
+            },
+        );
+        let evicted = match inner.max_subscriptions {
+            Some(limit) => inner.evict_over_limit(limit),
+            None => vec![],
+        };
+        (id, evicted)
+    }
+
+    /// Marks the subscription as recently used.
+    pub(crate) fn touch(&self, id: u64) {
+        let mut inner = self.inner.lock();
+        let now = inner.tick();
+        if let Some(entry) = inner.entries.get_mut(&id) {
+            entry.last_used = now;
+        }
+    }
+
+    /// Stops tracking the subscription, cancelling it if it's still running.
+    pub(crate) fn remove(&self, id: u64) {
+        if let Some(entry) = self.inner.lock().entries.remove(&id) {
+            // Ignore send failure — receiver already dropped means subscription
+            // is already cancelled.
+            let _ = entry.cancel_sender.send(());
+        }
+    }
+
+    /// The live subscriptions, oldest first.
+    pub(crate) fn list(&self) -> Vec<SubscriptionInfo> {
+        let inner = self.inner.lock();
+        let mut list: Vec<_> = inner
+            .entries
+            .iter()
+            .map(|(id, entry)| SubscriptionInfo {
+                subscription_id: *id,
+                name: entry.name.clone(),
+                args: entry.args.clone(),
+                age_ms: entry.created.elapsed().as_millis() as u64,
+            })
+            .collect();
+        list.sort_by_key(|info| info.subscription_id);
+        list
+    }
+
+    /// The number of live subscriptions.
+    pub(crate) fn len(&self) -> usize {
+        self.inner.lock().entries.len()
+    }
+
+    /// Cancels every live subscription.
+    pub(crate) fn clear(&self) {
+        for (_, entry) in self.inner.lock().entries.drain() {
+            let _ = entry.cancel_sender.send(());
+        }
+    }
+
+    /// Sets the maximum number of live subscriptions, returning the ones that
+    /// had to be evicted to get under it.
+    pub(crate) fn set_limit(&self, max_subscriptions: Option<usize>) -> Vec<Evicted> {
+        let mut inner = self.inner.lock();
+        inner.max_subscriptions = max_subscriptions;
+        match max_subscriptions {
+            Some(limit) => inner.evict_over_limit(limit),
+            None => vec![],
+        }
+    }
+}
+
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct SubscriptionHandle {
+    id: u64,
+    subscriptions: Weak<Subscriptions>,
+    /// Closed once the subscription's task has exited.
+    running: watch::Receiver<()>,
+}
+
+impl SubscriptionHandle {
+    /// Creates the handle of the subscription `id`, whose task drops the
+    /// sender of `running` when it exits.
+    pub(crate) fn new(
+        id: u64,
+        subscriptions: &Arc<Subscriptions>,
+        running: watch::Receiver<()>,
+    ) -> Self {
+        SubscriptionHandle {
+            id,
+            subscriptions: Arc::downgrade(subscriptions),
+            running,
+        }
+    }
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl SubscriptionHandle {
+    /// An identifier for this subscription that's unique within its client.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn cancel(&self) {
+        if let Some(subscriptions) = self.subscriptions.upgrade() {
+            subscriptions.remove(self.id);
+        }
+    }
+
+    /// Whether the subscription is still running. Once this is `false`, the
+    /// subscriber won't be called again.
+    pub fn is_active(&self) -> bool {
+        self.running.has_changed().is_ok()
+    }
+
+    /// Cancels the subscription and waits until it has stopped, after which
+    /// the subscriber won't be called again.
+    pub async fn cancel_and_wait(&self) {
+        self.cancel();
+        let mut running = self.running.clone();
+        while running.changed().await.is_ok() {}
+    }
+}
+
+impl Drop for SubscriptionHandle {
+    /// Cancels the subscription once the host lets go of its handle, so a
+    /// leaked handle doesn't keep it running for the life of the client.
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::channel::oneshot;
+
+    use super::Subscriptions;
+
+    #[test]
+    fn test_least_recently_used_subscription_is_evicted() {
+        let subscriptions = Subscriptions::default();
+        subscriptions.set_limit(Some(2));
+        let (tx_a, mut rx_a) = oneshot::channel();
+        let (tx_b, mut rx_b) = oneshot::channel();
+        let (tx_c, mut rx_c) = oneshot::channel();
+
+        let (a, _) = subscriptions.insert("a".into(), "{}".into(), tx_a);
+        let (b, _) = subscriptions.insert("b".into(), "{}".into(), tx_b);
+        subscriptions.touch(a);
+        let (_, evicted) = subscriptions.insert("c".into(), "{}".into(), tx_c);
+
+        assert_eq!(evicted.iter().map(|e| e.id).collect::<Vec<_>>(), vec![b]);
+        assert_eq!(rx_b.try_recv(), Ok(Some(())));
+        assert_eq!(rx_a.try_recv(), Ok(None));
+        assert_eq!(rx_c.try_recv(), Ok(None));
+    }
+}
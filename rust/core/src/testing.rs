@@ -0,0 +1,325 @@
+//! A harness for end-to-end tests against a local Convex backend, behind the
+//! `testing` feature.
+//!
+//! [LocalBackend::start] attaches to the backend at `CONVEX_LOCAL_BACKEND_URL`
+//! if that's set, and otherwise launches `convex-local-backend` (or the binary
+//! at `CONVEX_LOCAL_BACKEND_BIN`) on free ports, with its data in a temporary
+//! directory that goes away with it. Deploying functions and seeding data
+//! take the admin key of the backend, from `CONVEX_LOCAL_BACKEND_ADMIN_KEY`.
+//!
+//! Tests that share an attached backend see each other's data, so run them
+//! with `--test-threads=1` or keep their data apart.
+//!
+//! The end-to-end tests of this crate use it too. They're ignored by default
+//! and run with `cargo test -p convex-mobile-core -- --ignored`, which also
+//! needs `npx` to deploy `app_for_test`.
+use std::{
+    collections::HashMap,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use tokio::process::{Child, Command};
+
+use crate::{http, ClientOptions, MobileConvexClient};
+
+/// How long a backend gets to start serving requests.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A Convex backend on this machine, which is stopped when dropped if it was
+/// launched here.
+pub struct LocalBackend {
+    url: String,
+    admin_key: Option<String>,
+    process: Option<Child>,
+    data_dir: Option<PathBuf>,
+}
+
+impl LocalBackend {
+    /// Attaches to the backend configured by the environment, or launches
+    /// one.
+    pub async fn start() -> anyhow::Result<LocalBackend> {
+        let admin_key = std::env::var("CONVEX_LOCAL_BACKEND_ADMIN_KEY").ok();
+        match std::env::var("CONVEX_LOCAL_BACKEND_URL") {
+            Ok(url) => Self::attach(url, admin_key).await,
+            Err(_) => {
+                let binary = std::env::var("CONVEX_LOCAL_BACKEND_BIN")
+                    .unwrap_or_else(|_| "convex-local-backend".into());
+                Self::launch(&binary, admin_key).await
+            },
+        }
+    }
+
+    /// Attaches to the backend running at `url`, once it serves requests.
+    pub async fn attach(url: String, admin_key: Option<String>) -> anyhow::Result<LocalBackend> {
+        let mut backend = LocalBackend {
+            url,
+            admin_key,
+            process: None,
+            data_dir: None,
+        };
+        backend.wait_until_ready().await?;
+        Ok(backend)
+    }
+
+    /// Launches the local backend `binary` on free ports.
+    pub async fn launch(binary: &str, admin_key: Option<String>) -> anyhow::Result<LocalBackend> {
+        let port = free_port()?;
+        let site_port = free_port()?;
+        let data_dir =
+            std::env::temp_dir().join(format!("convex-backend-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&data_dir)?;
+        let url = format!("http://127.0.0.1:{port}");
+        let process = Command::new(binary)
+            .arg("--port")
+            .arg(port.to_string())
+            .arg("--site-proxy-port")
+            .arg(site_port.to_string())
+            .arg("--convex-origin")
+            .arg(&url)
+            .arg("--convex-site")
+            .arg(format!("http://127.0.0.1:{site_port}"))
+            .arg("--local-storage")
+            .arg(data_dir.join("storage"))
+            .arg("--disable-beacon")
+            .arg(data_dir.join("convex_local_backend.sqlite3"))
+            .current_dir(&data_dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to launch {binary}"))?;
+        let mut backend = LocalBackend {
+            url,
+            admin_key,
+            process: Some(process),
+            data_dir: Some(data_dir),
+        };
+        backend.wait_until_ready().await?;
+        Ok(backend)
+    }
+
+    /// The deployment URL to connect clients to.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Pushes the functions of the Convex app in `app_dir`, like
+    /// `app_for_test`, with the Convex CLI.
+    pub async fn deploy(&self, app_dir: &Path) -> anyhow::Result<()> {
+        let status = Command::new("npx")
+            .args(["convex", "deploy"])
+            .env("CONVEX_SELF_HOSTED_URL", &self.url)
+            .env("CONVEX_SELF_HOSTED_ADMIN_KEY", self.admin_key()?)
+            .current_dir(app_dir)
+            .stdin(Stdio::null())
+            .status()
+            .await
+            .context("Failed to run the Convex CLI")?;
+        anyhow::ensure!(
+            status.success(),
+            "Deploying {} failed: {status}",
+            app_dir.display()
+        );
+        Ok(())
+    }
+
+    /// Runs the mutation `name`, which may be internal, as an admin with the
+    /// JSON object `args`, e.g. to seed data before a test.
+    pub async fn seed(&self, name: &str, args: serde_json::Value) -> anyhow::Result<String> {
+        let args = match args {
+            serde_json::Value::Object(args) => args
+                .into_iter()
+                .map(|(key, value)| (key, value.to_string()))
+                .collect(),
+            serde_json::Value::Null => HashMap::new(),
+            _ => anyhow::bail!("The args of {name} must be a JSON object"),
+        };
+        let client = self.admin_client().await?;
+        Ok(client.mutation(name.into(), args, None).await?)
+    }
+
+    /// A client that's connected to the backend.
+    pub async fn client(&self) -> anyhow::Result<MobileConvexClient> {
+        let options = ClientOptions {
+            use_shared_runtime: true,
+            ..Default::default()
+        };
+        let client =
+            MobileConvexClient::with_options(self.url.clone(), "testing".into(), None, options);
+        client
+            .wait_for_connection(Some(STARTUP_TIMEOUT.as_millis() as u64))
+            .await?;
+        Ok(client)
+    }
+
+    /// A client that's connected to the backend as an admin.
+    pub async fn admin_client(&self) -> anyhow::Result<MobileConvexClient> {
+        let client = self.client().await?;
+        client.set_admin_auth(self.admin_key()?.into()).await?;
+        Ok(client)
+    }
+
+    fn admin_key(&self) -> anyhow::Result<&str> {
+        self.admin_key
+            .as_deref()
+            .context("Set CONVEX_LOCAL_BACKEND_ADMIN_KEY to the admin key of the backend")
+    }
+
+    async fn wait_until_ready(&mut self) -> anyhow::Result<()> {
+        let version = format!("{}/version", self.url);
+        let http = http::client_builder()?.build()?;
+        let started = Instant::now();
+        loop {
+            if let Some(process) = &mut self.process {
+                if let Some(status) = process.try_wait()? {
+                    anyhow::bail!("The local backend exited with {status}");
+                }
+            }
+            if let Ok(response) = http.get(&version).send().await {
+                if response.status().is_success() {
+                    return Ok(());
+                }
+            }
+            anyhow::ensure!(
+                started.elapsed() < STARTUP_TIMEOUT,
+                "The backend at {} didn't start serving requests",
+                self.url
+            );
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+impl Drop for LocalBackend {
+    fn drop(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            let _ = process.start_kill();
+        }
+        if let Some(data_dir) = self.data_dir.take() {
+            let _ = std::fs::remove_dir_all(data_dir);
+        }
+    }
+}
+
+/// A port that nothing listens on right now.
+fn free_port() -> anyhow::Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path, sync::Arc};
+
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    use super::LocalBackend;
+    use crate::QuerySubscriber;
+
+    struct Updates(mpsc::UnboundedSender<String>);
+
+    #[async_trait::async_trait]
+    impl QuerySubscriber for Updates {
+        async fn on_update(&self, value: String, _is_stale: bool) {
+            let _ = self.0.send(value);
+        }
+
+        async fn on_error(&self, message: String, _data: Option<String>) {
+            let _ = self.0.send(message);
+        }
+
+        async fn on_subscribed(&self) {}
+
+        async fn on_resubscribed(&self) {}
+    }
+
+    async fn backend() -> LocalBackend {
+        let backend = LocalBackend::start().await.unwrap();
+        let app = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../app_for_test");
+        backend.deploy(&app).await.unwrap();
+        backend.seed("messages:clearAll", json!({})).await.unwrap();
+        backend
+    }
+
+    fn message(body: &str) -> HashMap<String, String> {
+        HashMap::from([
+            ("body".to_string(), json!(body).to_string()),
+            ("author".to_string(), json!("test").to_string()),
+        ])
+    }
+
+    #[tokio::test]
+    #[ignore = "needs convex-local-backend"]
+    async fn test_subscriptions_get_the_mutations_of_other_clients() {
+        let backend = backend().await;
+        let (sender, mut updates) = mpsc::unbounded_channel();
+        let subscriber = backend.client().await.unwrap();
+        let _handle = subscriber
+            .subscribe(
+                "messages:list".into(),
+                HashMap::new(),
+                Arc::new(Updates(sender)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(updates.recv().await.unwrap(), "[]");
+
+        backend
+            .client()
+            .await
+            .unwrap()
+            .mutation("messages:send".into(), message("hi"), None)
+            .await
+            .unwrap();
+        assert!(updates.recv().await.unwrap().contains("\"body\":\"hi\""));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs convex-local-backend"]
+    async fn test_subscriptions_carry_over_a_reconnect() {
+        let backend = backend().await;
+        let (sender, mut updates) = mpsc::unbounded_channel();
+        let client = backend.client().await.unwrap();
+        let _handle = client
+            .subscribe(
+                "messages:list".into(),
+                HashMap::new(),
+                Arc::new(Updates(sender)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(updates.recv().await.unwrap(), "[]");
+
+        client.reconnect().await.unwrap();
+        client
+            .mutation("messages:send".into(), message("again"), None)
+            .await
+            .unwrap();
+        loop {
+            if updates.recv().await.unwrap().contains("\"body\":\"again\"") {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs convex-local-backend"]
+    async fn test_convex_errors_carry_their_data() {
+        let backend = backend().await;
+        let error = backend
+            .client()
+            .await
+            .unwrap()
+            .action("messages:forceActionError".into(), HashMap::new(), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            crate::ClientError::ConvexError { data } if data == "\"forced error data\""
+        ));
+    }
+}
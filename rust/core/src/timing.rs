@@ -0,0 +1,16 @@
+//! How long function calls take, as seen from the client.
+
+/// The result of a function call along with how long it took.
+///
+/// The round trip runs from sending the call until its result arrives, so it
+/// includes the network latency as well as the time the function ran for.
+/// The backend doesn't report the execution time on its own: the results it
+/// sends over the WebSocket only carry the value, the function's log lines
+/// and, for mutations, the timestamp they were committed at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct TimedResult {
+    /// The JSON encoded value the function returned.
+    pub value: String,
+    pub round_trip_ms: u64,
+}
@@ -0,0 +1,111 @@
+//! Convex values that cross the FFI boundary without being JSON encoded.
+use std::collections::HashMap;
+
+use convex::Value;
+
+/// A Convex value, mirroring [convex::Value].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum ConvexValue {
+    Null,
+    Int64 {
+        value: i64,
+    },
+    Float64 {
+        value: f64,
+    },
+    Boolean {
+        value: bool,
+    },
+    String {
+        value: String,
+    },
+    Bytes {
+        value: Vec<u8>,
+    },
+    Array {
+        values: Vec<ConvexValue>,
+    },
+    Object {
+        fields: HashMap<String, ConvexValue>,
+    },
+}
+
+impl From<Value> for ConvexValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => ConvexValue::Null,
+            Value::Int64(value) => ConvexValue::Int64 { value },
+            Value::Float64(value) => ConvexValue::Float64 { value },
+            Value::Boolean(value) => ConvexValue::Boolean { value },
+            Value::String(value) => ConvexValue::String { value },
+            Value::Bytes(value) => ConvexValue::Bytes { value },
+            Value::Array(values) => ConvexValue::Array {
+                values: values.into_iter().map(ConvexValue::from).collect(),
+            },
+            Value::Object(fields) => ConvexValue::Object {
+                fields: fields
+                    .into_iter()
+                    .map(|(k, v)| (k, ConvexValue::from(v)))
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl From<ConvexValue> for Value {
+    fn from(value: ConvexValue) -> Self {
+        match value {
+            ConvexValue::Null => Value::Null,
+            ConvexValue::Int64 { value } => Value::Int64(value),
+            ConvexValue::Float64 { value } => Value::Float64(value),
+            ConvexValue::Boolean { value } => Value::Boolean(value),
+            ConvexValue::String { value } => Value::String(value),
+            ConvexValue::Bytes { value } => Value::Bytes(value),
+            ConvexValue::Array { values } => {
+                Value::Array(values.into_iter().map(Value::from).collect())
+            },
+            ConvexValue::Object { fields } => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (k, Value::from(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Receives updates for a query subscription as [ConvexValue]s.
+///
+/// Behaves like [crate::QuerySubscriber] otherwise. The `data` of an error is
+/// the data of a `ConvexError` thrown by the query, if that's what it was.
+#[cfg_attr(feature = "uniffi", uniffi::export(with_foreign))]
+#[async_trait::async_trait]
+pub trait ValueQuerySubscriber: Send + Sync {
+    async fn on_update(&self, value: ConvexValue, is_stale: bool) -> ();
+
+    async fn on_error(&self, message: String, data: Option<ConvexValue>) -> ();
+
+    async fn on_subscribed(&self) -> ();
+
+    async fn on_resubscribed(&self) -> ();
+}
+
+#[cfg(test)]
+mod tests {
+    use convex::Value;
+    use maplit::btreemap;
+
+    use super::ConvexValue;
+
+    #[test]
+    fn test_values_round_trip_without_losing_types() {
+        let value = Value::Object(btreemap! {
+            String::from("count") => Value::Int64(1 << 60),
+            String::from("ratio") => Value::Float64(0.5),
+            String::from("blob") => Value::Bytes(vec![0, 255]),
+            String::from("tags") => Value::Array(vec![Value::Null, Value::Boolean(true)]),
+        });
+        assert_eq!(Value::from(ConvexValue::from(value.clone())), value);
+    }
+}
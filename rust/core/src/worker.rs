@@ -0,0 +1,180 @@
+//! A single task that runs the one-shot function calls of a client.
+//!
+//! Queries, mutations and actions are posted to the worker over a channel
+//! instead of each spawning a task of its own. The worker hands every call to
+//! the client before it looks at the next one, so calls reach the backend in
+//! the order they were made, and then waits for all of their results
+//! concurrently.
+//!
+//! Each call still runs on a clone of the current [ConvexClient]. Its calls
+//! take `&mut self` until their result arrives, so one client can only have
+//! one call in flight at a time. A clone is a handle to the same connection,
+//! so it doesn't open another one.
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use convex::{ConvexClient, FunctionResult, Value};
+use futures::{poll, select_biased, stream::FuturesUnordered, FutureExt, StreamExt};
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, oneshot, watch},
+};
+use tracing::debug;
+
+/// The kind of function to call.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Call {
+    Query,
+    Mutation,
+    Action,
+}
+
+struct Command {
+    call: Call,
+    name: String,
+    args: BTreeMap<String, Value>,
+    reply: oneshot::Sender<anyhow::Result<FunctionResult>>,
+}
+
+impl Command {
+    async fn run(
+        self,
+        mut client: ConvexClient,
+        mut clients: watch::Receiver<Option<ConvexClient>>,
+    ) {
+        let Command {
+            call,
+            name,
+            args,
+            mut reply,
+        } = self;
+        let result = async {
+            match call {
+                Call::Query => client.query(&name, args).await,
+                Call::Mutation => client.mutation(&name, args).await,
+                Call::Action => client.action(&name, args).await,
+            }
+        };
+        // Stop waiting for the result once the caller is gone, which for a
+        // query also drops its subscription.
+        tokio::select! {
+            biased;
+            result = result => {
+                let _ = reply.send(result);
+            },
+            _ = reply.closed() => debug!("Abandoned call to {name}"),
+            // The client is dropped on shutdown or after giving up on
+            // connecting, and shouldn't be kept alive by calls that would
+            // never finish.
+            _ = clients.wait_for(Option::is_none) => {
+                let _ = reply.send(Err(anyhow::anyhow!("Client is not connected")));
+            },
+        }
+    }
+}
+
+/// The handle that calls are posted to the worker through.
+pub(crate) struct Worker {
+    commands: mpsc::UnboundedSender<Command>,
+    pending: Arc<AtomicUsize>,
+}
+
+/// Counts a call as pending for as long as its result is awaited.
+struct Pending(Arc<AtomicUsize>);
+
+impl Pending {
+    fn new(pending: &Arc<AtomicUsize>) -> Self {
+        pending.fetch_add(1, Ordering::Relaxed);
+        Pending(pending.clone())
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Worker {
+    /// Spawns the worker, which runs calls on whichever client `clients`
+    /// currently holds until the [Worker] is dropped.
+    pub(crate) fn spawn(rt: &Handle, clients: watch::Receiver<Option<ConvexClient>>) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        rt.spawn(run(receiver, clients));
+        Worker {
+            commands,
+            pending: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// The number of calls that were posted and are still waiting for their
+    /// results.
+    pub(crate) fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Posts a call right away and returns a future for its result, so calls
+    /// posted one after another are made in that order even when their
+    /// results are awaited together.
+    pub(crate) fn post(
+        &self,
+        call: Call,
+        name: String,
+        args: BTreeMap<String, Value>,
+    ) -> impl Future<Output = anyhow::Result<FunctionResult>> {
+        let pending = Pending::new(&self.pending);
+        let (reply, result) = oneshot::channel();
+        let sent = self.commands.send(Command {
+            call,
+            name,
+            args,
+            reply,
+        });
+        async move {
+            let _pending = pending;
+            sent.map_err(|_| anyhow::anyhow!("The client was shut down"))?;
+            result
+                .await
+                .map_err(|_| anyhow::anyhow!("The client was shut down"))?
+        }
+    }
+}
+
+async fn run(
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    clients: watch::Receiver<Option<ConvexClient>>,
+) {
+    let mut in_flight = FuturesUnordered::<Pin<Box<dyn Future<Output = ()> + Send>>>::new();
+    loop {
+        select_biased! {
+            command = commands.recv().fuse() => {
+                let Some(command) = command else {
+                    break
+                };
+                let Some(client) = clients.borrow().clone() else {
+                    let _ = command
+                        .reply
+                        .send(Err(anyhow::anyhow!("Client is not connected")));
+                    continue
+                };
+                // The calls of the underlying client borrow it for as long as
+                // they run, so each one gets a handle of its own to the shared
+                // connection. Polling the call once sends its request.
+                let mut call = Box::pin(command.run(client, clients.clone()));
+                if poll!(&mut call).is_pending() {
+                    in_flight.push(call);
+                }
+            },
+            _ = in_flight.select_next_some() => {},
+        }
+    }
+    // Let the calls that were already made finish.
+    while in_flight.next().await.is_some() {}
+}
@@ -1,13 +1,337 @@
+use std::{
+    collections::VecDeque,
+    fmt::{Debug, Write},
+    sync::{Arc, LazyLock, Mutex, PoisonError, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use convex_mobile_core::{ClientError, LogLevel};
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Metadata, Subscriber,
+};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::{
+    field::RecordFields,
+    filter::{dynamic_filter_fn, LevelFilter, ParseError, Targets},
+    layer::Context,
+    Layer,
+};
+
+use crate::redact;
+
+/// Receives the Rust-side logs, e.g. to hand them to Timber or swift-log.
+///
+/// It's called synchronously on whichever thread logged, so it shouldn't
+/// block, and it shouldn't call back into the client, which may log again.
+#[uniffi::export(with_foreign)]
+pub trait LogSink: Send + Sync {
+    fn log(&self, level: LogLevel, target: String, message: String);
+}
+
+static SINK: RwLock<Option<(Arc<dyn LogSink>, Level)>> = RwLock::new(None);
+
+/// What's logged at all, by target and level, like `RUST_LOG`.
+static FILTER: LazyLock<RwLock<Targets>> =
+    LazyLock::new(|| RwLock::new(Targets::new().with_default(Level::TRACE)));
+
+pub(crate) fn set_level(level: LogLevel) {
+    set_filter(Targets::new().with_default(Level::from(level)));
+}
+
+/// Sets a filter like `convex=debug,tungstenite=warn`, where a bare level sets
+/// the level of targets that aren't listed.
+pub(crate) fn set_directives(filter: &str) -> Result<(), ClientError> {
+    let filter = filter
+        .parse()
+        .map_err(|e: ParseError| ClientError::InvalidArgument {
+            key: "filter".into(),
+            reason: e.to_string(),
+        })?;
+    set_filter(filter);
+    Ok(())
+}
+
+fn set_filter(filter: Targets) {
+    *FILTER.write().unwrap_or_else(PoisonError::into_inner) = filter;
+    // So the `log` crate skips records that wouldn't be logged before they're
+    // bridged into `tracing`.
+    log::set_max_level(log_level_filter());
+}
+
+fn log_level_filter() -> log::LevelFilter {
+    let filter = FILTER.read().unwrap_or_else(PoisonError::into_inner);
+    let most_verbose = filter
+        .iter()
+        .map(|(_, level)| level)
+        .chain(filter.default_level())
+        .max()
+        .unwrap_or(LevelFilter::OFF);
+    match most_verbose.into_level() {
+        None => log::LevelFilter::Off,
+        Some(Level::ERROR) => log::LevelFilter::Error,
+        Some(Level::WARN) => log::LevelFilter::Warn,
+        Some(Level::INFO) => log::LevelFilter::Info,
+        Some(Level::DEBUG) => log::LevelFilter::Debug,
+        Some(Level::TRACE) => log::LevelFilter::Trace,
+    }
+}
+
+/// Whether events like those described by `metadata` are logged at all,
+/// going by the level of the client that's logging them if it has one.
+fn level_enabled(metadata: &Metadata<'_>) -> bool {
+    if let Some(level) = convex_mobile_core::client_log_level() {
+        return *metadata.level() <= Level::from(level);
+    }
+    FILTER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .would_enable(metadata.target(), metadata.level())
+}
+
+pub(crate) fn set_sink(sink: Option<Arc<dyn LogSink>>, max_level: LogLevel) {
+    *SINK.write().unwrap_or_else(PoisonError::into_inner) =
+        sink.map(|sink| (sink, max_level.into()));
+}
+
+fn has_sink() -> bool {
+    SINK.read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .is_some()
+}
+
+/// Whether the sink wants events like those described by `metadata`.
+fn sink_enabled(metadata: &Metadata<'_>) -> bool {
+    level_enabled(metadata)
+        && SINK
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .is_some_and(|(_, max_level)| metadata.level() <= max_level)
+}
+
+/// Hands events to the [LogSink], if there is one.
+struct SinkLayer;
+
+impl<S: Subscriber> Layer<S> for SinkLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let Some((sink, _)) = SINK.read().unwrap_or_else(PoisonError::into_inner).clone() else {
+            return;
+        };
+        let metadata = event.normalized_metadata();
+        let metadata = metadata.as_ref().unwrap_or_else(|| event.metadata());
+        sink.log(
+            metadata.level().into(),
+            metadata.target().to_owned(),
+            format_event(event),
+        );
+    }
+}
+
+/// The latest log records, kept in memory to attach to bug reports.
+struct Capture {
+    records: VecDeque<serde_json::Value>,
+    max_records: usize,
+    max_level: Level,
+}
+
+static CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+
+pub(crate) fn set_capture(max_records: usize, max_level: LogLevel) {
+    let mut capture = CAPTURE.lock().unwrap_or_else(PoisonError::into_inner);
+    if max_records == 0 {
+        *capture = None;
+        return;
+    }
+    let capture = capture.get_or_insert_with(|| Capture {
+        records: VecDeque::new(),
+        max_records,
+        max_level: max_level.into(),
+    });
+    capture.max_records = max_records;
+    capture.max_level = max_level.into();
+    while capture.records.len() > max_records {
+        capture.records.pop_front();
+    }
+}
+
+/// The captured records as a JSON array, oldest first.
+pub(crate) fn export_capture() -> String {
+    let capture = CAPTURE.lock().unwrap_or_else(PoisonError::into_inner);
+    let records = capture
+        .as_ref()
+        .map(|capture| capture.records.iter().cloned().collect())
+        .unwrap_or_default();
+    serde_json::Value::Array(records).to_string()
+}
+
+/// Whether the capture wants events like those described by `metadata`,
+/// whatever the platform log and the [LogSink] are limited to.
+fn capture_enabled(metadata: &Metadata<'_>) -> bool {
+    CAPTURE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .is_some_and(|capture| metadata.level() <= &capture.max_level)
+}
+
+/// Keeps events in the [Capture], if there is one.
+struct CaptureLayer;
+
+impl<S: Subscriber> Layer<S> for CaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.normalized_metadata();
+        let metadata = metadata.as_ref().unwrap_or_else(|| event.metadata());
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        let record = serde_json::json!({
+            "timestamp_ms": timestamp_ms,
+            "level": metadata.level().as_str(),
+            "target": metadata.target(),
+            "message": format_event(event),
+        });
+        let mut capture = CAPTURE.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(capture) = capture.as_mut() else {
+            return;
+        };
+        if capture.records.len() == capture.max_records {
+            capture.records.pop_front();
+        }
+        capture.records.push_back(record);
+    }
+}
+
+/// Formats the message of an event followed by its other fields, with any
+/// secrets in them redacted.
+fn format_event(event: &Event<'_>) -> String {
+    format_fields(event)
+}
+
+fn format_fields(fields: impl RecordFields) -> String {
+    let mut visitor = FieldsVisitor::default();
+    fields.record(&mut visitor);
+    redact::redact(&visitor.0)
+}
+
+#[derive(Default)]
+struct FieldsVisitor(String);
+
+impl Visit for FieldsVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name().starts_with("log.") {
+            // Where a record of the `log` crate came from, which is in its
+            // normalized metadata instead.
+        } else if field.name() == "message" {
+            self.0.insert_str(0, &format!("{value:?}"));
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+/// The layers that every platform logs through: the [LogSink] while there is
+/// one, and `platform` otherwise, along with the [Capture].
+fn layers<S>(platform: impl Layer<S> + Send + Sync + 'static) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    // The filters are dynamic, as the sink can be set at any time.
+    platform
+        .with_filter(dynamic_filter_fn(|metadata, _| {
+            !has_sink() && level_enabled(metadata)
+        }))
+        .and_then(SinkLayer.with_filter(dynamic_filter_fn(|metadata, _| sink_enabled(metadata))))
+        .and_then(
+            CaptureLayer.with_filter(dynamic_filter_fn(|metadata, _| capture_enabled(metadata))),
+        )
+}
+
+/// Formats the fields of desktop log lines like [format_event] does.
+#[cfg(not(any(target_os = "android", apple_mobile)))]
+struct RedactedFields;
+
+#[cfg(not(any(target_os = "android", apple_mobile)))]
+impl<'writer> tracing_subscriber::fmt::FormatFields<'writer> for RedactedFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: tracing_subscriber::fmt::format::Writer<'writer>,
+        fields: R,
+    ) -> std::fmt::Result {
+        write!(writer, "{}", format_fields(fields))
+    }
+}
+
+/// Writes events to logcat or os_log.
+///
+/// This formats events itself rather than using the `tracing` layers for
+/// those, so they're redacted before they're written.
+#[cfg(any(target_os = "android", apple_mobile))]
+struct PlatformLayer {
+    #[cfg(target_os = "android")]
+    log: android_logger::AndroidLogger,
+    #[cfg(apple_mobile)]
+    log: oslog::OsLog,
+}
+
+#[cfg(any(target_os = "android", apple_mobile))]
+impl<S: Subscriber> Layer<S> for PlatformLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.normalized_metadata();
+        let metadata = metadata.as_ref().unwrap_or_else(|| event.metadata());
+        let message = format_event(event);
+
+        #[cfg(target_os = "android")]
+        {
+            let level = match *metadata.level() {
+                Level::ERROR => log::Level::Error,
+                Level::WARN => log::Level::Warn,
+                Level::INFO => log::Level::Info,
+                Level::DEBUG => log::Level::Debug,
+                Level::TRACE => log::Level::Trace,
+            };
+            log::Log::log(
+                &self.log,
+                &log::Record::builder()
+                    .level(level)
+                    .target(metadata.target())
+                    .args(format_args!("{message}"))
+                    .build(),
+            );
+        }
+
+        #[cfg(apple_mobile)]
+        {
+            // The same mapping as `tracing-oslog`, where debug is the level
+            // that isn't persisted.
+            let level = match *metadata.level() {
+                Level::ERROR => oslog::Level::Fault,
+                Level::WARN => oslog::Level::Error,
+                Level::INFO => oslog::Level::Default,
+                Level::DEBUG => oslog::Level::Info,
+                Level::TRACE => oslog::Level::Debug,
+            };
+            self.log
+                .with_level(level, &format!("{}: {message}", metadata.target()));
+        }
+    }
+}
+
 /// Initialize platform-specific logging
 /// Should be called once
 pub fn init_logging() {
+    // Dependencies like tungstenite log through the `log` crate, whose
+    // records then go through the same filters and redaction as the rest.
+    // This fails if the app already set a `log` logger, which keeps them.
+    let _ = tracing_log::LogTracer::init_with_filter(log_level_filter());
+
     #[cfg(target_os = "android")]
     init_android_logging();
 
-    #[cfg(target_os = "ios")]
-    init_ios_logging();
+    #[cfg(apple_mobile)]
+    init_apple_logging();
 
-    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    #[cfg(not(any(target_os = "android", apple_mobile)))]
     init_default_logging();
 }
 
@@ -16,40 +340,133 @@ fn init_android_logging() {
     use tracing_subscriber::layer::SubscriberExt;
     use tracing_subscriber::Registry;
 
-    // Initialize Android logger
-    android_logger::init_once(
+    // Create a tracing subscriber that forwards to Android logcat. The
+    // layers filter what's logged, so the logger itself doesn't.
+    let logcat = android_logger::AndroidLogger::new(
         android_logger::Config::default()
             .with_max_level(log::LevelFilter::Trace)
             .with_tag("ConvexMobile"),
     );
-
-    // Create a tracing subscriber that forwards to Android logcat
-    let android_layer =
-        tracing_android::layer("ConvexMobile").expect("Failed to create Android tracing layer");
-
-    let subscriber = Registry::default().with(android_layer);
+    let subscriber = Registry::default()
+        .with(layers(PlatformLayer { log: logcat }))
+        .with(convex_mobile_core::events_layer());
+    #[cfg(feature = "otlp")]
+    let subscriber = subscriber.with(crate::otlp::layer());
 
     tracing::subscriber::set_global_default(subscriber).expect("Failed to set tracing subscriber");
 }
 
-#[cfg(target_os = "ios")]
-fn init_ios_logging() {
+#[cfg(apple_mobile)]
+fn init_apple_logging() {
     use tracing_subscriber::layer::SubscriberExt;
     use tracing_subscriber::Registry;
 
-    // Create a tracing subscriber that forwards to iOS os_log
-    let oslog_layer = tracing_oslog::OsLogger::new("dev.convex.ConvexMobile", "default");
+    // Create a tracing subscriber that forwards to os_log
+    let oslog_layer = PlatformLayer {
+        log: oslog::OsLog::new("dev.convex.ConvexMobile", "default"),
+    };
 
-    let subscriber = Registry::default().with(oslog_layer);
+    let subscriber = Registry::default()
+        .with(layers(oslog_layer))
+        .with(convex_mobile_core::events_layer());
+    #[cfg(feature = "otlp")]
+    let subscriber = subscriber.with(crate::otlp::layer());
 
     tracing::subscriber::set_global_default(subscriber).expect("Failed to set tracing subscriber");
 }
 
-#[cfg(not(any(target_os = "android", target_os = "ios")))]
+/// The file that the desktop log goes to instead of stderr, if one is set.
+#[cfg(not(any(target_os = "android", apple_mobile)))]
+static FILE: Mutex<Option<std::fs::File>> = Mutex::new(None);
+
+#[cfg(not(any(target_os = "android", apple_mobile)))]
+pub(crate) fn set_file(path: Option<String>) -> Result<(), ClientError> {
+    let file = path
+        .map(|path| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| ClientError::InvalidArgument {
+                    key: "path".into(),
+                    reason: e.to_string(),
+                })
+        })
+        .transpose()?;
+    *FILE.lock().unwrap_or_else(PoisonError::into_inner) = file;
+    Ok(())
+}
+
+/// Writes the desktop log to [FILE] or stderr, a whole event at a time.
+#[cfg(not(any(target_os = "android", apple_mobile)))]
+struct DesktopWriter;
+
+#[cfg(not(any(target_os = "android", apple_mobile)))]
+impl std::io::Write for DesktopWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut *FILE.lock().unwrap_or_else(PoisonError::into_inner) {
+            Some(file) => file.write_all(buf)?,
+            None => std::io::stderr().write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut *FILE.lock().unwrap_or_else(PoisonError::into_inner) {
+            Some(file) => file.flush(),
+            None => std::io::stderr().flush(),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "android", apple_mobile)))]
 fn init_default_logging() {
-    use tracing::Level;
-    use tracing_subscriber::fmt;
+    use tracing_subscriber::filter::LevelFilter;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::{fmt, Layer, Registry};
+
+    let subscriber = Registry::default()
+        .with(layers(
+            fmt::layer()
+                .fmt_fields(RedactedFields)
+                // The file may be set after the layer was built, so colors
+                // are left out everywhere.
+                .with_ansi(false)
+                .with_writer(|| DesktopWriter)
+                .with_filter(LevelFilter::TRACE),
+        ))
+        .with(convex_mobile_core::events_layer());
+    #[cfg(feature = "otlp")]
+    let subscriber = subscriber.with(crate::otlp::layer());
+
+    tracing::subscriber::set_global_default(subscriber).expect("Failed to set tracing subscriber");
+}
+
+#[cfg(test)]
+mod tests {
+    use convex_mobile_core::LogLevel;
+    use tracing_subscriber::{
+        layer::{Identity, SubscriberExt},
+        Registry,
+    };
+
+    use super::{export_capture, layers, set_capture};
+
+    #[test]
+    fn test_log_crate_records_are_redacted() {
+        let _ = tracing_log::LogTracer::init();
+        set_capture(10, LogLevel::Trace);
+        let subscriber = Registry::default().with(layers(Identity::new()));
+        tracing::subscriber::with_default(subscriber, || {
+            log::trace!(target: "tungstenite::protocol", "writing frame Authorization: Bearer opaque-token-123");
+        });
+        let records: serde_json::Value = serde_json::from_str(&export_capture()).unwrap();
+        set_capture(0, LogLevel::Trace);
 
-    // For development/testing on desktop
-    fmt().with_max_level(Level::TRACE).init();
+        assert_eq!(records[0]["target"], "tungstenite::protocol");
+        assert_eq!(
+            records[0]["message"],
+            "writing frame Authorization: Bearer [REDACTED]"
+        );
+    }
 }
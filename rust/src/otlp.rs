@@ -0,0 +1,101 @@
+//! Export of the client's `tracing` spans over OTLP/HTTP.
+use std::sync::OnceLock;
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing::Subscriber;
+use tracing_subscriber::{filter::filter_fn, registry::LookupSpan, Layer};
+
+use convex_mobile_core::ClientError;
+
+/// The provider that batches and exports the spans, kept for as long as the
+/// process runs.
+static PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+pub(crate) fn set_endpoint(endpoint: String) -> Result<(), ClientError> {
+    let invalid = |reason: String| ClientError::InvalidArgument {
+        key: "endpoint".into(),
+        reason,
+    };
+    // The exporter sends from a thread of its own, so it takes a blocking
+    // client, which trusts the same roots as the rest of the client.
+    let tls = convex_mobile_core::tls_config()
+        .map_err(|e| ClientError::InternalError { msg: e.to_string() })?;
+    let client = reqwest::blocking::Client::builder()
+        .use_preconfigured_tls(tls)
+        .build()
+        .map_err(|e| ClientError::InternalError { msg: e.to_string() })?;
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .with_http_client(client)
+        .build()
+        .map_err(|e| invalid(e.to_string()))?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name("convex-mobile")
+                .build(),
+        )
+        .build();
+    PROVIDER
+        .set(provider)
+        .map_err(|_| invalid("The OTLP endpoint was already set".into()))
+}
+
+/// A layer that exports the spans of the Convex crates, if an endpoint was
+/// set.
+///
+/// Other crates are left out, above all the HTTP stack that the exporter
+/// itself uses.
+pub(crate) fn layer<S>() -> Option<impl Layer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let tracer = PROVIDER.get()?.tracer("convexmobile");
+    Some(
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(filter_fn(|metadata| {
+                metadata.target().starts_with("convex")
+            })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::{layer, set_endpoint, PROVIDER};
+
+    #[test]
+    fn test_convex_spans_are_exported() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        let request = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![0; 64 * 1024];
+            let read = stream.read(&mut request).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8_lossy(&request[..read]).into_owned()
+        });
+
+        set_endpoint(endpoint).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!(target: "convex_mobile_core", "convex_call").in_scope(|| {});
+        });
+        PROVIDER.get().unwrap().force_flush().unwrap();
+
+        assert!(request.join().unwrap().starts_with("POST /v1/traces"));
+    }
+}